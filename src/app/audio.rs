@@ -1,39 +1,431 @@
-use rodio::source::SineWave;
-use rodio::{OutputStream, OutputStreamHandle, Source};
+use rodio::{OutputStream, Source};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU8, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
 use std::time::Duration;
 
+pub const SAMPLE_RATE: u32 = 44100;
+const SAMPLES_PER_FRAME: usize = SAMPLE_RATE as usize / 60;
+/// Delay between the emulated frame of a gate edge and the sample it is played at,
+/// it absorbs the jitter of the UI frames.
+const GATE_LATENCY: i64 = 2 * SAMPLES_PER_FRAME as i64;
+/// Edges further than this from their schedule, after a pause or while fast forwarding,
+/// restart the schedule from the current sample.
+const GATE_MAX_DRIFT: i64 = 6 * SAMPLES_PER_FRAME as i64;
+/// Samples between two reads of the edges sent by the UI thread.
+const GATE_POLL_INTERVAL: u64 = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(u8)]
+pub enum Waveform {
+    Square,
+    Sine,
+    Triangle,
+    Noise,
+}
+
+impl Waveform {
+    pub const ALL: [Waveform; 4] = [Waveform::Square, Waveform::Sine, Waveform::Triangle, Waveform::Noise];
+
+    fn from_u8(value: u8) -> Waveform {
+        match value {
+            1 => Waveform::Sine,
+            2 => Waveform::Triangle,
+            3 => Waveform::Noise,
+            _ => Waveform::Square,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ToneSettings {
+    pub waveform: Waveform,
+    pub frequency: f32,
+    pub amplitude: f32,
+    /// Time in seconds for the tone to fade in.
+    pub attack: f32,
+    /// Time in seconds for the tone to fade out.
+    pub release: f32,
+}
+
+impl Default for ToneSettings {
+    fn default() -> Self {
+        Self {
+            waveform: Waveform::Square,
+            frequency: 440.0,
+            amplitude: 0.2,
+            attack: 0.002,
+            release: 0.005,
+        }
+    }
+}
+
+/// Produces the buzzer samples, the envelope avoids pops when the tone starts or stops.
+pub struct ToneGenerator {
+    pub settings: ToneSettings,
+    sample_rate: u32,
+
+    phase: f32,
+    gain: f32,
+    noise_value: f32,
+    noise_state: u32,
+}
+
+impl ToneGenerator {
+    pub fn new(settings: ToneSettings, sample_rate: u32) -> Self {
+        Self {
+            settings,
+            sample_rate,
+            phase: 0.0,
+            gain: 0.0,
+            noise_value: 0.0,
+            noise_state: 0x1234_5678,
+        }
+    }
+
+    fn envelope_step(&self, duration: f32) -> f32 {
+        if duration > 0.0 {
+            1.0 / (duration * self.sample_rate as f32)
+        }
+        else {
+            1.0
+        }
+    }
+
+    fn next_noise(&mut self) -> f32 {
+        // xorshift32
+        self.noise_state ^= self.noise_state << 13;
+        self.noise_state ^= self.noise_state >> 17;
+        self.noise_state ^= self.noise_state << 5;
+        (self.noise_state as f32 / u32::MAX as f32) * 2.0 - 1.0
+    }
+
+    /// Next sample of the tone, `gate` tells whether the buzzer is on.
+    pub fn next_sample(&mut self, gate: bool) -> f32 {
+        if gate {
+            self.gain = (self.gain + self.envelope_step(self.settings.attack)).min(1.0);
+        }
+        else {
+            self.gain = (self.gain - self.envelope_step(self.settings.release)).max(0.0);
+        }
+
+        if self.gain == 0.0 {
+            self.phase = 0.0;
+            return 0.0;
+        }
+
+        let sample = match self.settings.waveform {
+            Waveform::Square => if self.phase < 0.5 { 1.0 } else { -1.0 },
+            Waveform::Sine => (self.phase * std::f32::consts::TAU).sin(),
+            Waveform::Triangle => 4.0 * (self.phase - 0.5).abs() - 1.0,
+            Waveform::Noise => self.noise_value,
+        };
+
+        self.phase += self.settings.frequency / self.sample_rate as f32;
+        if self.phase >= 1.0 {
+            self.phase -= self.phase.floor();
+            self.noise_value = self.next_noise();
+        }
+
+        sample * self.settings.amplitude * self.gain
+    }
+}
+
+/// Renders the buzzer frame by frame into a 16 bits mono WAV file, used when running
+/// without a sound device.
+pub struct WavRecorder {
+    generator: ToneGenerator,
+    samples: Vec<i16>,
+}
+
+impl WavRecorder {
+    pub fn new(settings: ToneSettings) -> Self {
+        Self {
+            generator: ToneGenerator::new(settings, SAMPLE_RATE),
+            samples: Vec::new(),
+        }
+    }
+
+    /// Renders one 60 Hz frame of audio.
+    pub fn push_frame(&mut self, playing: bool) {
+        for _ in 0..SAMPLES_PER_FRAME {
+            let sample = self.generator.next_sample(playing).clamp(-1.0, 1.0);
+            self.samples.push((sample * i16::MAX as f32) as i16);
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        let data_size = (self.samples.len() * 2) as u32;
+
+        writer.write_all(b"RIFF")?;
+        writer.write_all(&(36 + data_size).to_le_bytes())?;
+        writer.write_all(b"WAVE")?;
+
+        writer.write_all(b"fmt ")?;
+        writer.write_all(&16u32.to_le_bytes())?;
+        writer.write_all(&1u16.to_le_bytes())?; // PCM
+        writer.write_all(&1u16.to_le_bytes())?; // mono
+        writer.write_all(&SAMPLE_RATE.to_le_bytes())?;
+        writer.write_all(&(SAMPLE_RATE * 2).to_le_bytes())?; // byte rate
+        writer.write_all(&2u16.to_le_bytes())?; // block align
+        writer.write_all(&16u16.to_le_bytes())?; // bits per sample
+
+        writer.write_all(b"data")?;
+        writer.write_all(&data_size.to_le_bytes())?;
+        for sample in self.samples.iter() {
+            writer.write_all(&sample.to_le_bytes())?;
+        }
+
+        writer.flush()
+    }
+}
+
+fn store_f32(atomic: &AtomicU32, value: f32) {
+    atomic.store(value.to_bits(), Ordering::Relaxed);
+}
+
+fn load_f32(atomic: &AtomicU32) -> f32 {
+    f32::from_bits(atomic.load(Ordering::Relaxed))
+}
+
+/// The buzzer turned on or off at the start of an emulated 60 Hz frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct GateEdge {
+    frame: u64,
+    playing: bool,
+}
+
+/// Plays the gate edges at their emulated frame, `SAMPLES_PER_FRAME` samples apart,
+/// so the tone lasts exactly as long as the sound timer whatever the UI frame rate.
+struct GateSchedule {
+    edges: VecDeque<GateEdge>,
+    /// Frame of an edge and the sample it was played at, later edges are placed
+    /// relative to it.
+    anchor: Option<(u64, i64)>,
+    sample: i64,
+    gate: bool,
+}
+
+impl GateSchedule {
+    fn new() -> Self {
+        Self {
+            edges: VecDeque::new(),
+            anchor: None,
+            sample: 0,
+            gate: false,
+        }
+    }
+
+    fn push(&mut self, edge: GateEdge) {
+        self.edges.push_back(edge);
+    }
+
+    /// Gate of the next sample.
+    fn next_gate(&mut self) -> bool {
+        while let Some(edge) = self.edges.front().copied() {
+            let due = self
+                .anchor
+                .map(|(frame, sample)| sample + (edge.frame as i64 - frame as i64) * SAMPLES_PER_FRAME as i64)
+                .filter(|due| (due - self.sample).abs() <= GATE_MAX_DRIFT)
+                .unwrap_or(self.sample + GATE_LATENCY);
+            self.anchor = Some((edge.frame, due));
+            if due > self.sample {
+                break;
+            }
+
+            self.gate = edge.playing;
+            self.edges.pop_front();
+        }
+
+        self.sample += 1;
+        self.gate
+    }
+}
+
+/// State written by the UI thread and read by the audio thread.
+struct BuzzerState {
+    muted: AtomicBool,
+    waveform: AtomicU8,
+    frequency: AtomicU32,
+    amplitude: AtomicU32,
+    attack: AtomicU32,
+    release: AtomicU32,
+}
+
+impl BuzzerState {
+    fn new(settings: &ToneSettings) -> Self {
+        let state = Self {
+            muted: AtomicBool::new(false),
+            waveform: AtomicU8::new(0),
+            frequency: AtomicU32::new(0),
+            amplitude: AtomicU32::new(0),
+            attack: AtomicU32::new(0),
+            release: AtomicU32::new(0),
+        };
+        state.store_settings(settings);
+        state
+    }
+
+    fn store_settings(&self, settings: &ToneSettings) {
+        self.waveform.store(settings.waveform as u8, Ordering::Relaxed);
+        store_f32(&self.frequency, settings.frequency);
+        store_f32(&self.amplitude, settings.amplitude);
+        store_f32(&self.attack, settings.attack);
+        store_f32(&self.release, settings.release);
+    }
+
+    fn load_settings(&self) -> ToneSettings {
+        ToneSettings {
+            waveform: Waveform::from_u8(self.waveform.load(Ordering::Relaxed)),
+            frequency: load_f32(&self.frequency),
+            amplitude: load_f32(&self.amplitude),
+            attack: load_f32(&self.attack),
+            release: load_f32(&self.release),
+        }
+    }
+}
+
+/// Endless source played for the whole life of the buzzer.
+struct BuzzerSource {
+    state: Arc<BuzzerState>,
+    generator: ToneGenerator,
+    edges: Receiver<GateEdge>,
+    schedule: GateSchedule,
+    until_poll: u64,
+}
+
+impl Iterator for BuzzerSource {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.until_poll == 0 {
+            self.until_poll = GATE_POLL_INTERVAL;
+            self.generator.settings = self.state.load_settings();
+            for edge in self.edges.try_iter() {
+                self.schedule.push(edge);
+            }
+        }
+        self.until_poll -= 1;
+
+        let gate = self.schedule.next_gate() && !self.state.muted.load(Ordering::Relaxed);
+        Some(self.generator.next_sample(gate))
+    }
+}
+
+impl Source for BuzzerSource {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
 pub struct Chip8Buzzer {
-    stream_handle: OutputStreamHandle,
-    _stream: OutputStream,
-    frequency: f32,
-    amplitude: f32,
+    /// `None` when no sound device is available, the buzzer is then a null sink.
+    stream: Option<OutputStream>,
+    state: Arc<BuzzerState>,
+    edges: Sender<GateEdge>,
+    /// Last gate sent to the audio thread.
+    playing: bool,
+    settings: ToneSettings,
+    muted: bool,
 }
 
 impl Chip8Buzzer {
     pub fn new() -> Self {
-        let (stream, stream_handle) = OutputStream::try_default().unwrap();
+        let settings = ToneSettings::default();
+        let state = Arc::new(BuzzerState::new(&settings));
+        let (edges, receiver) = mpsc::channel();
+
+        let source = BuzzerSource {
+            state: state.clone(),
+            generator: ToneGenerator::new(settings, SAMPLE_RATE),
+            edges: receiver,
+            schedule: GateSchedule::new(),
+            until_poll: 0,
+        };
+        let stream = match OutputStream::try_default() {
+            Ok((stream, stream_handle)) => {
+                stream_handle.play_raw(source).ok().map(|_| stream)
+            },
+            Err(_) => None,
+        };
+
         Self {
-            stream_handle,
-            _stream: stream,
-            frequency: 440.0,
-            amplitude: 0.2,
+            stream,
+            state,
+            edges,
+            playing: false,
+            settings,
+            muted: false,
         }
     }
 
-    pub fn play(&self) {
-        let source = SineWave::new(self.frequency)
-        .amplify(self.amplitude)
-        .take_duration(Duration::from_millis(16))
-        ;
+    /// Turns the tone on or off from the emulated `frame`, the audio thread plays the
+    /// change one frame of samples after the previous one.
+    pub fn set_playing(&mut self, frame: u64, playing: bool) {
+        if playing != self.playing {
+            self.playing = playing;
+            // The audio thread owning the receiver only stops with the stream.
+            let _ = self.edges.send(GateEdge { frame, playing });
+        }
+    }
+
+    pub fn has_output(&self) -> bool {
+        self.stream.is_some()
+    }
 
-        self.stream_handle.play_raw(source).unwrap();
+    pub fn is_muted(&self) -> bool {
+        self.muted
+    }
+
+    pub fn set_muted(&mut self, muted: bool) {
+        self.muted = muted;
+        self.state.muted.store(muted, Ordering::Relaxed);
     }
 
     pub fn draw_control(&mut self, ui: &mut egui::Ui) {
-        let freq_control = egui::DragValue::new(&mut self.frequency)
+        if !self.has_output() {
+            ui.label("No sound device available, the buzzer is silent");
+        }
+
+        let mut muted = self.muted;
+        if ui.checkbox(&mut muted, "Mute").changed() {
+            self.set_muted(muted);
+        }
+
+        let settings = &mut self.settings;
+        ui.horizontal(|ui| {
+            ui.label("Waveform");
+            egui::ComboBox::from_id_source("buzzer_waveform")
+            .selected_text(format!("{:?}", settings.waveform))
+            .show_ui(ui, |ui| {
+                for waveform in Waveform::ALL {
+                    ui.selectable_value(&mut settings.waveform, waveform, format!("{:?}", waveform));
+                }
+            });
+        });
+
+        let freq_control = egui::DragValue::new(&mut settings.frequency)
         .clamp_range(300.0..=1000.0);
-        let amplitude_control = egui::DragValue::new(&mut self.amplitude)
-        .clamp_range(0.2..=10.0);
+        let amplitude_control = egui::DragValue::new(&mut settings.amplitude)
+        .speed(0.01)
+        .clamp_range(0.0..=1.0);
         ui.horizontal(|ui|{
             ui.label("Sound Frequency");
             ui.add(freq_control);
@@ -45,5 +437,53 @@ impl Chip8Buzzer {
             ui.add(amplitude_control);
         });
 
+        ui.horizontal(|ui| {
+            ui.label("Attack");
+            ui.add(egui::Slider::new(&mut settings.attack, 0.0..=0.05).suffix(" s"));
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("Release");
+            ui.add(egui::Slider::new(&mut settings.release, 0.0..=0.05).suffix(" s"));
+        });
+
+        self.state.store_settings(&self.settings);
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gates(schedule: &mut GateSchedule, count: i64) -> Vec<bool> {
+        (0..count).map(|_| schedule.next_gate()).collect()
+    }
+
+    #[test]
+    fn gate_edges_are_one_frame_of_samples_apart() {
+        let mut schedule = GateSchedule::new();
+        schedule.push(GateEdge { frame: 10, playing: true });
+        schedule.push(GateEdge { frame: 13, playing: false });
+
+        let gates = gates(&mut schedule, GATE_LATENCY + 5 * SAMPLES_PER_FRAME as i64);
+        let on = gates.iter().filter(|gate| **gate).count();
+        assert_eq!(on, 3 * SAMPLES_PER_FRAME);
+        assert!(gates[GATE_LATENCY as usize]);
+        assert!(!gates[GATE_LATENCY as usize - 1]);
+    }
+
+    #[test]
+    fn late_gate_edges_restart_the_schedule() {
+        let mut schedule = GateSchedule::new();
+        schedule.push(GateEdge { frame: 0, playing: true });
+        schedule.push(GateEdge { frame: 1, playing: false });
+        gates(&mut schedule, GATE_LATENCY + 2 * SAMPLES_PER_FRAME as i64);
+
+        // The emulation was paused for a while then beeps again.
+        gates(&mut schedule, 100 * SAMPLES_PER_FRAME as i64);
+        schedule.push(GateEdge { frame: 2, playing: true });
+        schedule.push(GateEdge { frame: 4, playing: false });
+        let on = gates(&mut schedule, GATE_LATENCY + 3 * SAMPLES_PER_FRAME as i64).iter().filter(|gate| **gate).count();
+        assert_eq!(on, 2 * SAMPLES_PER_FRAME);
+    }
+}
@@ -0,0 +1,218 @@
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::chip8_core::graphics::{FrameBuffer, Screen, SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::timer::Timer;
+
+pub const CAPTURE_DIRECTORY: &str = "captures";
+const CAPTURE_SCALE: usize = 8;
+/// Shortest frame delay, in hundredths of a second, honored by most GIF viewers.
+const MIN_GIF_DELAY: u16 = 2;
+
+/// Path in the capture directory for a new capture, named after the current time.
+pub fn capture_path(prefix: &str, extension: &str) -> PathBuf {
+    let timestamp = SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map(|duration| duration.as_millis())
+    .unwrap_or(0);
+
+    let mut file_name = format!("{}_{}", prefix, timestamp);
+    if !extension.is_empty() {
+        file_name = format!("{}.{}", file_name, extension);
+    }
+    Path::new(CAPTURE_DIRECTORY).join(file_name)
+}
+
+fn create_file(path: &Path) -> io::Result<BufWriter<File>> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    Ok(BufWriter::new(File::create(path)?))
+}
+
+/// Asks for a file to save and writes into it, the error comes with the failed `action`
+/// to show to the user.
+pub fn export_file<F>(filter: &str, extension: &str, file_name: &str, action: &'static str, write: F) -> Option<(&'static str, io::Error)>
+where
+    F: FnOnce(&mut BufWriter<File>) -> io::Result<()>
+{
+    let file = rfd::FileDialog::new()
+    .add_filter(filter, &[extension])
+    .set_file_name(file_name)
+    .save_file()?;
+
+    create_file(&file)
+    .and_then(|mut writer| {
+        write(&mut writer)?;
+        writer.flush()
+    })
+    .err()
+    .map(|e| (action, e))
+}
+
+/// Screen pixels as palette indexes: 0 for the background and 1 for the foreground.
+fn screen_indexes(screen: &Screen, scale: usize) -> Vec<u8> {
+    let mut indexes = Vec::with_capacity(SCREEN_WIDTH * SCREEN_HEIGHT * scale * scale);
+    for y in 0..SCREEN_HEIGHT * scale {
+        for x in 0..SCREEN_WIDTH * scale {
+            indexes.push(screen.is_pixel_set(x / scale, y / scale) as u8);
+        }
+    }
+    indexes
+}
+
+fn screen_rgb(screen: &Screen, palette: &[[u8; 3]; 2], scale: usize) -> Vec<u8> {
+    screen_indexes(screen, scale)
+    .into_iter()
+    .flat_map(|index| palette[index as usize])
+    .collect()
+}
+
+pub fn save_screenshot(path: &Path, screen: &Screen, palette: &[[u8; 3]; 2]) -> io::Result<()> {
+    let writer = create_file(path)?;
+
+    let mut encoder = png::Encoder::new(
+        writer,
+        (SCREEN_WIDTH * CAPTURE_SCALE) as u32,
+        (SCREEN_HEIGHT * CAPTURE_SCALE) as u32
+    );
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&screen_rgb(screen, palette, CAPTURE_SCALE))?;
+
+    Ok(())
+}
+
+enum RecorderOutput {
+    Gif(gif::Encoder<BufWriter<File>>),
+    /// PPM images plus a concat list for ffmpeg:
+    /// `ffmpeg -f concat -i frames.txt recording.mp4`
+    RawFrames {
+        directory: PathBuf,
+        frame_list: BufWriter<File>,
+        frame_index: usize,
+    },
+}
+
+/// Records every screen update with the time it stayed on screen.
+pub struct ScreenRecorder {
+    output: RecorderOutput,
+    palette: [[u8; 3]; 2],
+
+    last_frame: Option<Vec<u8>>,
+    frame_timer: Timer,
+    error: Option<io::Error>,
+}
+
+impl ScreenRecorder {
+    pub fn new_gif(path: &Path, palette: [[u8; 3]; 2]) -> io::Result<Self> {
+        let writer = create_file(path)?;
+        let global_palette: Vec<u8> = palette.iter().flatten().copied().collect();
+
+        let mut encoder = gif::Encoder::new(
+            writer,
+            (SCREEN_WIDTH * CAPTURE_SCALE) as u16,
+            (SCREEN_HEIGHT * CAPTURE_SCALE) as u16,
+            &global_palette
+        ).map_err(io::Error::other)?;
+        encoder.set_repeat(gif::Repeat::Infinite).map_err(io::Error::other)?;
+
+        Ok(Self::new(RecorderOutput::Gif(encoder), palette))
+    }
+
+    pub fn new_raw_frames(directory: &Path, palette: [[u8; 3]; 2]) -> io::Result<Self> {
+        let frame_list = create_file(&directory.join("frames.txt"))?;
+
+        Ok(Self::new(RecorderOutput::RawFrames {
+            directory: directory.to_path_buf(),
+            frame_list,
+            frame_index: 0,
+        }, palette))
+    }
+
+    fn new(output: RecorderOutput, palette: [[u8; 3]; 2]) -> Self {
+        Self {
+            output,
+            palette,
+            last_frame: None,
+            frame_timer: Timer::new(),
+            error: None,
+        }
+    }
+
+    fn write_frame(&mut self, indexes: &[u8], duration_secs: f64) -> io::Result<()> {
+        let width = SCREEN_WIDTH * CAPTURE_SCALE;
+        let height = SCREEN_HEIGHT * CAPTURE_SCALE;
+
+        match &mut self.output {
+            RecorderOutput::Gif(encoder) => {
+                let frame = gif::Frame {
+                    width: width as u16,
+                    height: height as u16,
+                    buffer: std::borrow::Cow::Borrowed(indexes),
+                    delay: ((duration_secs * 100.0).round() as u16).max(MIN_GIF_DELAY),
+                    ..gif::Frame::default()
+                };
+
+                encoder.write_frame(&frame).map_err(io::Error::other)
+            },
+            RecorderOutput::RawFrames { directory, frame_list, frame_index } => {
+                let file_name = format!("frame_{:05}.ppm", frame_index);
+                let mut image = create_file(&directory.join(&file_name))?;
+                write!(image, "P6\n{} {}\n255\n", width, height)?;
+                for index in indexes {
+                    image.write_all(&self.palette[*index as usize])?;
+                }
+                image.flush()?;
+
+                writeln!(frame_list, "file '{}'", file_name)?;
+                writeln!(frame_list, "duration {:.4}", duration_secs)?;
+                *frame_index += 1;
+
+                Ok(())
+            },
+        }
+    }
+
+    /// Writes the frame still on screen and closes the recording.
+    pub fn finish(mut self) -> io::Result<()> {
+        if let Some(error) = self.error.take() {
+            return Err(error);
+        }
+
+        if let Some(frame) = self.last_frame.take() {
+            let duration = self.frame_timer.elapsed().as_secs_f64();
+            self.write_frame(&frame, duration)?;
+        }
+
+        if let RecorderOutput::RawFrames { frame_list, .. } = &mut self.output {
+            frame_list.flush()?;
+        }
+
+        Ok(())
+    }
+}
+
+impl FrameBuffer for ScreenRecorder {
+    fn update(&mut self, screen: &Screen) {
+        if self.error.is_some() {
+            return;
+        }
+
+        let frame = screen_indexes(screen, CAPTURE_SCALE);
+        if self.last_frame.as_ref() == Some(&frame) {
+            return;
+        }
+
+        let duration = self.frame_timer.restart().as_secs_f64();
+        if let Some(previous_frame) = self.last_frame.replace(frame) {
+            if let Err(e) = self.write_frame(&previous_frame, duration) {
+                self.error = Some(e);
+            }
+        }
+    }
+}
@@ -0,0 +1,583 @@
+use std::collections::VecDeque;
+use std::path::PathBuf;
+
+use crate::chip8_core::chip8::{Chip8, Location};
+use crate::chip8_core::instruction::Instruction;
+use crate::chip8_core::symbols::{self, Register, SymbolTable};
+
+const LOG_CAPACITY: usize = 500;
+const HISTORY_CAPACITY: usize = 100;
+const DUMP_BYTES_PER_ROW: usize = 16;
+const DEFAULT_DUMP_LENGTH: u16 = 64;
+const DEFAULT_DISASSEMBLY_COUNT: u16 = 10;
+
+/// Names and usages of the commands, completed with tab.
+const COMMANDS: [(&str, &str); 17] = [
+    ("help", "help"),
+    ("step", "step [count]"),
+    ("continue", "continue"),
+    ("pause", "pause"),
+    ("break", "break <address>"),
+    ("delete", "delete <address>"),
+    ("breaks", "breaks"),
+    ("set", "set <register> <value>"),
+    ("poke", "poke <address> <byte> [byte...]"),
+    ("dump", "dump <address> [length]"),
+    ("disasm", "disasm <address> [count]"),
+    ("print", "print <register|address>"),
+    ("watch", "watch <register|address>"),
+    ("unwatch", "unwatch <register|address>"),
+    ("reset", "reset"),
+    ("load", "load <path>"),
+    ("clear", "clear"),
+];
+
+const REGISTERS: [&str; 21] = [
+    "v0", "v1", "v2", "v3", "v4", "v5", "v6", "v7",
+    "v8", "v9", "va", "vb", "vc", "vd", "ve", "vf",
+    "i", "pc", "sp", "dt", "st",
+];
+
+/// What the console asks `MainApp` to do with the execution state.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConsoleRequest {
+    /// Executes this many instructions, stopping early on a breakpoint or a watch.
+    Step(u32),
+    Continue,
+    Pause,
+    /// Restarts the loaded ROM.
+    Reset,
+    Load(PathBuf),
+}
+
+struct Watch {
+    expression: String,
+    location: Location,
+    value: u16,
+}
+
+struct LogLine {
+    text: String,
+    error: bool,
+}
+
+/// Command line of the debug mode. Commands reading or writing the machine run at
+/// once, the ones changing the execution state are queued for `MainApp`, see
+/// `take_requests`.
+pub struct DebugConsole {
+    log: VecDeque<LogLine>,
+    input: String,
+    history: VecDeque<String>,
+    /// Entry of the history shown in the input while browsing it with the arrows.
+    history_index: Option<usize>,
+    watches: Vec<Watch>,
+    requests: Vec<ConsoleRequest>,
+}
+
+impl DebugConsole {
+    pub fn new() -> Self {
+        Self {
+            log: VecDeque::new(),
+            input: String::new(),
+            history: VecDeque::new(),
+            history_index: None,
+            watches: Vec::new(),
+            requests: Vec::new(),
+        }
+    }
+
+    pub fn print(&mut self, text: String) {
+        self.log(text, false);
+    }
+
+    pub fn print_error(&mut self, text: String) {
+        self.log(text, true);
+    }
+
+    fn log(&mut self, text: String, error: bool) {
+        if self.log.len() >= LOG_CAPACITY {
+            self.log.pop_front();
+        }
+        self.log.push_back(LogLine { text, error });
+    }
+
+    pub fn take_requests(&mut self) -> Vec<ConsoleRequest> {
+        std::mem::take(&mut self.requests)
+    }
+
+    /// Updates the watched values, returns true when one of them changed. The watches
+    /// are set in `Chip8` too, so that `Chip8::take_watch_hit` tells when to check them.
+    pub fn check_watches(&mut self, chip8: &Chip8) -> bool {
+        let mut changed = Vec::new();
+        for watch in self.watches.iter_mut() {
+            let value = chip8.read_location(watch.location);
+            if value != watch.value {
+                changed.push(format!("{} changed: {:#X} -> {:#X} at PC {:#05X}", watch.expression, watch.value, value, chip8.get_cpu().pc));
+                watch.value = value;
+            }
+        }
+
+        let any_changed = !changed.is_empty();
+        for text in changed {
+            self.print(text);
+        }
+        any_changed
+    }
+
+    /// Runs one command line, errors are printed in the log.
+    pub fn execute(&mut self, line: &str, chip8: &mut Chip8, symbols: &SymbolTable) {
+        self.print(format!("> {}", line));
+        if self.history.back().map(String::as_str) != Some(line) {
+            if self.history.len() >= HISTORY_CAPACITY {
+                self.history.pop_front();
+            }
+            self.history.push_back(line.to_string());
+        }
+
+        if let Err(e) = self.run_command(line, chip8, symbols) {
+            self.print_error(e);
+        }
+
+        // Changes made by the commands themselves do not stop the execution.
+        for watch in self.watches.iter_mut() {
+            watch.value = chip8.read_location(watch.location);
+        }
+        chip8.set_watches(self.watches.iter().map(|watch| watch.location).collect());
+    }
+
+    fn run_command(&mut self, line: &str, chip8: &mut Chip8, symbols: &SymbolTable) -> Result<(), String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let (command, arguments) = match words.split_first() {
+            Some((command, arguments)) => (*command, arguments),
+            None => return Ok(()),
+        };
+
+        match command {
+            "help" => {
+                for (_, usage) in COMMANDS {
+                    self.print(format!("  {}", usage));
+                }
+                self.print(String::from("Addresses are hexadecimal, labels or registers such as pc and i. Values are decimal or prefixed by 0x."));
+            },
+            "step" => {
+                let count = match arguments.first() {
+                    Some(count) => count.parse().map_err(|_| format!("not a count: {}", count))?,
+                    None => 1,
+                };
+                self.requests.push(ConsoleRequest::Step(count));
+            },
+            "continue" => self.requests.push(ConsoleRequest::Continue),
+            "pause" => self.requests.push(ConsoleRequest::Pause),
+            "break" => {
+                let address = parse_address(argument(arguments, 0)?, chip8, symbols)?;
+                if chip8.add_breakpoint(address) {
+                    self.print(format!("Breakpoint at {}", symbols.describe(address)));
+                }
+                else {
+                    self.print(format!("{} already has a breakpoint", symbols.describe(address)));
+                }
+            },
+            "delete" => {
+                let address = parse_address(argument(arguments, 0)?, chip8, symbols)?;
+                if !chip8.remove_breakpoint(address) {
+                    return Err(format!("no breakpoint at {}", symbols.describe(address)));
+                }
+                self.print(format!("Deleted the breakpoint at {}", symbols.describe(address)));
+            },
+            "breaks" => {
+                let breakpoints: Vec<String> = chip8.get_breakpoints().iter()
+                .map(|address| symbols.describe(*address))
+                .collect();
+                if breakpoints.is_empty() {
+                    self.print(String::from("No breakpoints"));
+                }
+                else {
+                    self.print(breakpoints.join(", "));
+                }
+            },
+            "set" => {
+                let name = argument(arguments, 0)?;
+                let register = Register::parse(name).ok_or_else(|| format!("unknown register: {}", name))?;
+                let value = parse_number(argument(arguments, 1)?)?;
+                register.write(chip8.get_cpu_mut(), value)?;
+                self.print(format!("{} = {:#X}", name, value));
+            },
+            "poke" => {
+                let address = parse_address(argument(arguments, 0)?, chip8, symbols)?;
+                argument(arguments, 1)?;
+                let bytes = arguments[1..].iter()
+                .map(|byte| parse_number(byte).and_then(|value| u8::try_from(value).map_err(|_| format!("not a byte: {}", byte))))
+                .collect::<Result<Vec<u8>, String>>()?;
+                chip8.get_ram_mut().write_bytes(address, &bytes)
+                .map_err(|e| e.to_string())?;
+                self.print(format!("Wrote {} bytes at {:#05X}", bytes.len(), address));
+            },
+            "dump" => {
+                let address = parse_address(argument(arguments, 0)?, chip8, symbols)?;
+                let length = match arguments.get(1) {
+                    Some(length) => parse_number(length)?,
+                    None => DEFAULT_DUMP_LENGTH,
+                };
+                self.dump(chip8, address, length);
+            },
+            "disasm" => {
+                let address = match arguments.first() {
+                    Some(address) => parse_address(address, chip8, symbols)?,
+                    None => chip8.get_cpu().pc,
+                };
+                let count = match arguments.get(1) {
+                    Some(count) => parse_number(count)?,
+                    None => DEFAULT_DISASSEMBLY_COUNT,
+                };
+                self.disassemble(chip8, symbols, address, count);
+            },
+            "print" => {
+                let expression = argument(arguments, 0)?;
+                let location = parse_location(expression, chip8, symbols)?;
+                self.print(format!("{} = {:#X}", expression, chip8.read_location(location)));
+            },
+            "watch" => {
+                let expression = argument(arguments, 0)?;
+                let location = parse_location(expression, chip8, symbols)?;
+                let value = chip8.read_location(location);
+                self.watches.retain(|watch| watch.location != location);
+                self.watches.push(Watch { expression: expression.to_string(), location, value });
+                self.print(format!("Watching {} = {:#X}", expression, value));
+            },
+            "unwatch" => {
+                let expression = argument(arguments, 0)?;
+                let location = parse_location(expression, chip8, symbols)?;
+                let count = self.watches.len();
+                self.watches.retain(|watch| watch.location != location);
+                if self.watches.len() == count {
+                    return Err(format!("{} is not watched", expression));
+                }
+            },
+            "reset" => self.requests.push(ConsoleRequest::Reset),
+            "load" => {
+                let path = line.trim().split_once(char::is_whitespace)
+                .map(|(_, path)| path.trim())
+                .ok_or("missing path")?;
+                self.requests.push(ConsoleRequest::Load(PathBuf::from(path)));
+            },
+            "clear" => self.log.clear(),
+            command => return Err(format!("unknown command: {}, type help for the list", command)),
+        }
+        Ok(())
+    }
+
+    fn dump(&mut self, chip8: &Chip8, address: u16, length: u16) {
+        let ram = chip8.get_ram();
+        let end = ram.size().min(address as usize + length as usize);
+
+        for row_start in (address as usize..end).step_by(DUMP_BYTES_PER_ROW) {
+            let bytes: Vec<String> = (row_start..end.min(row_start + DUMP_BYTES_PER_ROW))
+            .filter_map(|address| ram.read8(address as u16).ok())
+            .map(|byte| format!("{:02X}", byte))
+            .collect();
+            self.print(format!("{:#05X}  {}", row_start, bytes.join(" ")));
+        }
+    }
+
+    fn disassemble(&mut self, chip8: &Chip8, symbols: &SymbolTable, address: u16, count: u16) {
+        let pc = chip8.get_cpu().pc;
+        for index in 0..count {
+            let address = match address.checked_add(2 * index) {
+                Some(address) => address,
+                None => break,
+            };
+            let opcode = match chip8.get_ram().read16(address) {
+                Ok(opcode) => opcode,
+                Err(_) => break,
+            };
+
+            if let Some(label) = symbols.label(address) {
+                self.print(format!("{}:", label));
+            }
+            let marker = if address == pc { "=>" } else { "  " };
+            self.print(format!("{} {:#05X}  {:04X}  {}", marker, address, opcode, Instruction::new(opcode).to_string()));
+        }
+    }
+
+    /// Completes the last word of the input with a command name, a register or a
+    /// label. Several candidates are completed up to their common prefix and listed.
+    fn complete(&mut self, symbols: &SymbolTable) {
+        let (start, word) = match self.input.rfind(char::is_whitespace) {
+            Some(index) => (index + 1, &self.input[index + 1..]),
+            None => (0, self.input.as_str()),
+        };
+
+        let candidates: Vec<String> = if start == 0 {
+            COMMANDS.iter()
+            .map(|(name, _)| name.to_string())
+            .filter(|name| name.starts_with(word))
+            .collect()
+        }
+        else {
+            REGISTERS.iter()
+            .map(|name| name.to_string())
+            .chain(symbols.labels().map(|(_, label)| label.to_string()))
+            .filter(|name| name.starts_with(word))
+            .collect()
+        };
+
+        match candidates.as_slice() {
+            [] => {},
+            [candidate] => {
+                self.input.replace_range(start.., candidate);
+                self.input.push(' ');
+            },
+            _ => {
+                let prefix = common_prefix(&candidates);
+                self.input.replace_range(start.., &prefix);
+                self.print(candidates.join("  "));
+            },
+        }
+    }
+
+    /// Shows the entry of the history before (`older`) or after the current one.
+    fn browse_history(&mut self, older: bool) {
+        let index = match (self.history_index, older) {
+            (None, true) => self.history.len().checked_sub(1),
+            (None, false) => None,
+            (Some(index), true) => Some(index.saturating_sub(1)),
+            (Some(index), false) => Some(index + 1).filter(|index| *index < self.history.len()),
+        };
+
+        self.history_index = index;
+        self.input = match index {
+            Some(index) => self.history[index].clone(),
+            None => String::new(),
+        };
+    }
+
+    pub fn draw(&mut self, ui: &mut egui::Ui, chip8: &mut Chip8, symbols: &SymbolTable) {
+        let input_height = ui.spacing().interact_size.y + ui.spacing().item_spacing.y * 2.0;
+        let watches_height = if self.watches.is_empty() { 0.0 } else { input_height };
+        egui::ScrollArea::vertical()
+        .max_height((ui.available_height() - input_height - watches_height).max(0.0))
+        .auto_shrink([false, false])
+        .stick_to_bottom(true)
+        .show(ui, |ui| {
+            for line in self.log.iter() {
+                let text = egui::RichText::new(&line.text).monospace();
+                ui.label(if line.error { text.color(ui.visuals().error_fg_color) } else { text });
+            }
+        });
+
+        if !self.watches.is_empty() {
+            let watches: Vec<String> = self.watches.iter()
+            .map(|watch| format!("{} = {:#X}", watch.expression, chip8.read_location(watch.location)))
+            .collect();
+            ui.monospace(format!("Watching {}", watches.join(", ")));
+        }
+
+        let input_id = ui.make_persistent_id("console_input");
+        let input = ui.add(egui::TextEdit::singleline(&mut self.input)
+            .id(input_id)
+            .desired_width(f32::INFINITY)
+            .font(egui::TextStyle::Monospace)
+            .lock_focus(true)
+            .hint_text("help"));
+
+        if input.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+            let line = std::mem::take(&mut self.input);
+            self.history_index = None;
+            if !line.trim().is_empty() {
+                self.execute(line.trim(), chip8, symbols);
+            }
+            input.request_focus();
+        }
+        else if input.has_focus() {
+            let (tab, up, down) = ui.input(|i| (
+                i.key_pressed(egui::Key::Tab),
+                i.key_pressed(egui::Key::ArrowUp),
+                i.key_pressed(egui::Key::ArrowDown),
+            ));
+            if tab {
+                self.complete(symbols);
+            }
+            else if up || down {
+                self.browse_history(up);
+            }
+
+            if tab || up || down {
+                // Moves the cursor after the text replaced by the completion or the history.
+                if let Some(mut state) = egui::text_edit::TextEditState::load(ui.ctx(), input_id) {
+                    let end = egui::text::CCursor::new(self.input.chars().count());
+                    state.set_ccursor_range(Some(egui::text_edit::CCursorRange::one(end)));
+                    state.store(ui.ctx(), input_id);
+                }
+            }
+        }
+    }
+}
+
+fn argument<'a>(arguments: &[&'a str], index: usize) -> Result<&'a str, String> {
+    arguments.get(index)
+    .copied()
+    .ok_or_else(|| String::from("missing argument, type help for the usage"))
+}
+
+fn parse_number(text: &str) -> Result<u16, String> {
+    symbols::parse_number(text).ok_or_else(|| format!("not a number: {}", text))
+}
+
+/// A register holding an address, a label or a hexadecimal address.
+fn parse_address(text: &str, chip8: &Chip8, symbols: &SymbolTable) -> Result<u16, String> {
+    match Register::parse(text) {
+        Some(register) => Ok(register.read(chip8.get_cpu())),
+        None => symbols.parse_address(text).ok_or_else(|| format!("unknown address: {}", text)),
+    }
+}
+
+fn parse_location(text: &str, chip8: &Chip8, symbols: &SymbolTable) -> Result<Location, String> {
+    match Register::parse(text) {
+        Some(register) => Ok(Location::Register(register)),
+        None => parse_address(text, chip8, symbols).map(Location::Byte),
+    }
+}
+
+fn common_prefix(words: &[String]) -> String {
+    let first = &words[0];
+    let length = words[1..].iter()
+    .map(|word| first.chars().zip(word.chars()).take_while(|(a, b)| a == b).count())
+    .min()
+    .unwrap_or(first.len());
+    first.chars().take(length).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8_core::machine::MachineConfig;
+
+    fn log_lines(console: &DebugConsole) -> Vec<String> {
+        console.log.iter().map(|line| line.text.clone()).collect()
+    }
+
+    #[test]
+    fn numbers_and_addresses_are_parsed() {
+        let mut chip8 = Chip8::new(MachineConfig::default());
+        chip8.get_cpu_mut().i = 0x300;
+        let mut symbols = SymbolTable::new();
+        symbols.set_label(0x250, "draw");
+
+        assert_eq!(parse_number("0x2A"), Ok(0x2A));
+        assert_eq!(parse_number("42"), Ok(42));
+        assert!(parse_number("2A").is_err());
+
+        assert_eq!(parse_address("i", &chip8, &symbols), Ok(0x300));
+        assert_eq!(parse_address("draw", &chip8, &symbols), Ok(0x250));
+        assert_eq!(parse_address("2A0", &chip8, &symbols), Ok(0x2A0));
+        assert!(parse_address("main", &chip8, &symbols).is_err());
+
+        assert_eq!(parse_location("VF", &chip8, &symbols), Ok(Location::Register(Register::V(0xF))));
+        assert_eq!(parse_location("draw", &chip8, &symbols), Ok(Location::Byte(0x250)));
+    }
+
+    #[test]
+    fn commands_write_registers_and_memory() {
+        let mut console = DebugConsole::new();
+        let mut chip8 = Chip8::new(MachineConfig::default());
+        let symbols = SymbolTable::new();
+
+        console.execute("set va 0x2A", &mut chip8, &symbols);
+        console.execute("poke 300 1 0x02 $3", &mut chip8, &symbols);
+        assert_eq!(chip8.get_cpu().v[0xA], 0x2A);
+        assert_eq!(chip8.get_ram().read8(0x302).unwrap(), 3);
+
+        for line in ["set v0 256", "set sp 1", "set vg 1"] {
+            console.execute(line, &mut chip8, &symbols);
+            assert!(console.log.back().unwrap().error);
+        }
+    }
+
+    #[test]
+    fn watches_report_the_changes_once() {
+        let mut console = DebugConsole::new();
+        let mut chip8 = Chip8::new(MachineConfig::default());
+        let symbols = SymbolTable::new();
+
+        console.execute("watch 300", &mut chip8, &symbols);
+        assert_eq!(chip8.get_watches(), vec![Location::Byte(0x300)]);
+        assert!(!console.check_watches(&chip8));
+
+        chip8.get_ram_mut().write8(0x300, 7).unwrap();
+        assert!(console.check_watches(&chip8));
+        assert!(log_lines(&console).last().unwrap().starts_with("300 changed: 0x0 -> 0x7"));
+        assert!(!console.check_watches(&chip8));
+
+        console.execute("unwatch 300", &mut chip8, &symbols);
+        assert!(chip8.get_watches().is_empty());
+    }
+
+    #[test]
+    fn common_prefix_stops_at_the_first_difference() {
+        let words = |words: &[&str]| words.iter().map(|word| word.to_string()).collect::<Vec<String>>();
+
+        assert_eq!(common_prefix(&words(&["delete", "disasm", "dump"])), "d");
+        assert_eq!(common_prefix(&words(&["watch"])), "watch");
+        assert_eq!(common_prefix(&words(&["break", "breaks"])), "break");
+    }
+
+    #[test]
+    fn complete_fills_commands_registers_and_labels() {
+        let mut console = DebugConsole::new();
+        let mut symbols = SymbolTable::new();
+        symbols.set_label(0x250, "draw_player");
+
+        console.input = String::from("wa");
+        console.complete(&symbols);
+        assert_eq!(console.input, "watch ");
+
+        console.input = String::from("break dr");
+        console.complete(&symbols);
+        assert_eq!(console.input, "break draw_player ");
+
+        console.input = String::from("d");
+        console.complete(&symbols);
+        assert_eq!(console.input, "d");
+        assert_eq!(log_lines(&console).last().unwrap(), "delete  dump  disasm");
+
+        console.input = String::from("print x");
+        console.complete(&symbols);
+        assert_eq!(console.input, "print x");
+    }
+
+    #[test]
+    fn history_is_browsed_from_the_latest_entry() {
+        let mut console = DebugConsole::new();
+        let mut chip8 = Chip8::new(MachineConfig::default());
+        let symbols = SymbolTable::new();
+        for line in ["breaks", "pause", "pause", "continue"] {
+            console.execute(line, &mut chip8, &symbols);
+        }
+
+        console.browse_history(true);
+        assert_eq!(console.input, "continue");
+        console.browse_history(true);
+        console.browse_history(true);
+        assert_eq!(console.input, "breaks");
+        console.browse_history(true);
+        assert_eq!(console.input, "breaks");
+        console.browse_history(false);
+        assert_eq!(console.input, "pause");
+        console.browse_history(false);
+        console.browse_history(false);
+        assert_eq!(console.input, "");
+    }
+
+    #[test]
+    fn history_keeps_the_latest_entries() {
+        let mut console = DebugConsole::new();
+        let mut chip8 = Chip8::new(MachineConfig::default());
+        let symbols = SymbolTable::new();
+        for count in 0..HISTORY_CAPACITY + 5 {
+            console.execute(&format!("step {}", count), &mut chip8, &symbols);
+        }
+
+        assert_eq!(console.history.len(), HISTORY_CAPACITY);
+        assert_eq!(console.history.front().unwrap(), "step 5");
+    }
+}
@@ -0,0 +1,293 @@
+use std::io;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use crate::chip8_core::instruction::Instruction;
+use crate::chip8_core::memory::Ram;
+use crate::chip8_core::profiler::Profiler;
+use crate::chip8_core::symbols::SymbolTable;
+
+/// Rows kept above the scroll target so the target is not on the first line.
+const SCROLL_CONTEXT_ROWS: usize = 4;
+
+struct LabelEditor {
+    address: u16,
+    label: String,
+    comment: String,
+}
+
+/// Disassembly of the whole memory. Rows are two bytes apart and aligned on the
+/// parity of the PC, or of the last address jumped to, so code reached through an
+/// odd address decodes correctly.
+pub struct DisassemblyView {
+    pub symbols: SymbolTable,
+    /// Address selected for "Run to cursor".
+    pub cursor: Option<u16>,
+    pub follow_pc: bool,
+
+    alignment: u16,
+    scroll_to: Option<u16>,
+    visible_rows: Range<usize>,
+    last_pc: Option<u16>,
+    goto_text: String,
+    label_editor: Option<LabelEditor>,
+    labels_path: Option<PathBuf>,
+    error: Option<(&'static str, io::Error)>,
+}
+
+impl DisassemblyView {
+    pub fn new() -> Self {
+        Self {
+            symbols: SymbolTable::new(),
+            cursor: None,
+            follow_pc: true,
+            alignment: 0,
+            scroll_to: None,
+            visible_rows: 0..0,
+            last_pc: None,
+            goto_text: String::new(),
+            label_editor: None,
+            labels_path: None,
+            error: None,
+        }
+    }
+
+    /// Loads the labels and comments saved for the ROM, if any.
+    pub fn load_rom(&mut self, rom: &Path) -> io::Result<()> {
+        let labels_path = SymbolTable::rom_labels_path(rom);
+        self.symbols.clear();
+        self.cursor = None;
+        self.last_pc = None;
+        self.label_editor = None;
+
+        let result = if labels_path.exists() {
+            self.symbols.load(&labels_path)
+        }
+        else {
+            Ok(())
+        };
+        self.labels_path = Some(labels_path);
+
+        result
+    }
+
+    /// Error that happened while drawing, with the action that failed.
+    pub fn take_error(&mut self) -> Option<(&'static str, io::Error)> {
+        self.error.take()
+    }
+
+    pub fn goto(&mut self, address: u16) {
+        self.alignment = address % 2;
+        self.scroll_to = Some(address);
+    }
+
+    fn save_labels(&mut self) {
+        if let Some(path) = &self.labels_path {
+            if let Err(e) = self.symbols.save(path) {
+                self.error = Some(("saving the labels", e));
+            }
+        }
+    }
+
+    fn load_symbols(&mut self) {
+        let file = rfd::FileDialog::new()
+        .add_filter("Symbols", &["sym", "txt"])
+        .pick_file();
+
+        if let Some(file) = file {
+            match self.symbols.load_symbols(&file) {
+                Ok(_) => self.save_labels(),
+                Err(e) => self.error = Some(("loading the symbols", e)),
+            }
+        }
+    }
+
+    fn row_address(&self, row: usize) -> u16 {
+        self.alignment + 2 * row as u16
+    }
+
+    fn address_row(&self, address: u16) -> usize {
+        (address.saturating_sub(self.alignment) / 2) as usize
+    }
+
+    fn draw_toolbar(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.follow_pc, "Follow PC");
+
+            let goto_edit = ui.add(egui::TextEdit::singleline(&mut self.goto_text)
+                .desired_width(80.0)
+                .hint_text("address"));
+            let submitted = goto_edit.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+            if ui.button("Go to").clicked() || submitted {
+                if let Some(address) = self.symbols.parse_address(&self.goto_text) {
+                    self.follow_pc = false;
+                    self.goto(address);
+                }
+            }
+
+            if ui.button("Load symbols").clicked() {
+                self.load_symbols();
+            }
+        });
+    }
+
+    /// Draws the target of a jump, call or `LD I` as a link scrolling to it.
+    fn draw_target(&mut self, ui: &mut egui::Ui, instruction: &Instruction) -> bool {
+        let mnemonic = match instruction.extract(0xF000, 12) {
+            0x1 => "JP",
+            0x2 => "CALL",
+            0xA => "LD I,",
+            0xB => "JP V0,",
+            _ => return false,
+        };
+
+        let target = instruction.nnn();
+        ui.monospace(mnemonic);
+        if ui.link(egui::RichText::new(self.symbols.describe(target)).monospace()).clicked() {
+            self.follow_pc = false;
+            self.goto(target);
+        }
+        true
+    }
+
+    /// `heat` is the profile with its highest execution count.
+    fn draw_row(&mut self, ui: &mut egui::Ui, ram: &Ram, address: u16, pc: u16, heat: Option<(&Profiler, u64)>) {
+        let opcode = match ram.read16(address) {
+            Ok(opcode) => opcode,
+            Err(_) => return,
+        };
+        let instruction = Instruction::new(opcode);
+        let heat_background = ui.painter().add(egui::Shape::Noop);
+
+        let row = ui.horizontal(|ui| {
+            let color = if address == pc { egui::Color32::GREEN } else { ui.visuals().text_color() };
+            let address_text = egui::RichText::new(format!("{:#05X}", address)).monospace().color(color);
+            let address_label = ui.selectable_label(self.cursor == Some(address), address_text)
+            .on_hover_text("Click to select for \"Run to cursor\", right click to edit the label");
+            if address_label.clicked() {
+                self.cursor = Some(address);
+            }
+            address_label.context_menu(|ui| {
+                if ui.button("Edit label and comment").clicked() {
+                    self.label_editor = Some(LabelEditor {
+                        address,
+                        label: self.symbols.label(address).unwrap_or_default().to_string(),
+                        comment: self.symbols.comment(address).unwrap_or_default().to_string(),
+                    });
+                    ui.close_menu();
+                }
+            });
+
+            let label = self.symbols.label(address)
+            .map(|label| format!("{}:", label))
+            .unwrap_or_default();
+            ui.add_sized([90.0, 0.0], egui::Label::new(
+                egui::RichText::new(label).monospace().color(egui::Color32::LIGHT_BLUE)
+            ));
+
+            ui.monospace(format!("{:04X}", opcode)).on_hover_text(format!("{:#06X}", opcode));
+            if !self.draw_target(ui, &instruction) {
+                ui.monospace(egui::RichText::new(instruction.to_string()).color(color));
+            }
+
+            if let Some(comment) = self.symbols.comment(address) {
+                ui.monospace(egui::RichText::new(format!("; {}", comment)).color(egui::Color32::GRAY));
+            }
+        });
+
+        let executions = heat
+        .and_then(|(profile, max_executions)| profile.address(address).map(|entry| (entry.executions, max_executions)));
+        if let Some((executions, max_executions)) = executions {
+            let rect = egui::Rect::from_x_y_ranges(ui.max_rect().x_range(), row.response.rect.y_range());
+            ui.painter().set(heat_background, egui::Shape::rect_filled(rect, 0.0, heat_color(executions, max_executions)));
+            row.response.on_hover_text(format!("Executed {} times", executions));
+        }
+    }
+
+    fn draw_label_editor(&mut self, ctx: &egui::Context) {
+        let mut close = false;
+        let mut save = false;
+
+        if let Some(editor) = &mut self.label_editor {
+            egui::Window::new(format!("Label at {:#05X}", editor.address))
+            .collapsible(false)
+            .show(ctx, |ui| {
+                egui::Grid::new("label_editor")
+                .show(ui, |ui| {
+                    ui.label("Label");
+                    ui.text_edit_singleline(&mut editor.label);
+                    ui.end_row();
+                    ui.label("Comment");
+                    ui.text_edit_singleline(&mut editor.comment);
+                    ui.end_row();
+                });
+                ui.horizontal(|ui| {
+                    save = ui.button("Save").clicked();
+                    close = ui.button("Cancel").clicked();
+                });
+            });
+        }
+
+        if save {
+            if let Some(editor) = self.label_editor.take() {
+                self.symbols.set_label(editor.address, &editor.label);
+                self.symbols.set_comment(editor.address, &editor.comment);
+                self.save_labels();
+            }
+        }
+        if close {
+            self.label_editor = None;
+        }
+    }
+
+    /// Draws the view, rows are colored by their number of executions when a profile
+    /// is given.
+    pub fn draw(&mut self, ui: &mut egui::Ui, ram: &Ram, pc: u16, profile: Option<&Profiler>) {
+        ui.vertical_centered(|ui| {
+            ui.heading("Program");
+        });
+        ui.separator();
+        self.draw_toolbar(ui);
+
+        if self.follow_pc && self.last_pc != Some(pc) {
+            let visible = self.alignment == pc % 2 && {
+                let row = self.address_row(pc);
+                row > self.visible_rows.start && row + 1 < self.visible_rows.end
+            };
+            if !visible {
+                self.goto(pc);
+            }
+        }
+        self.last_pc = Some(pc);
+
+        let row_height = (ui.text_style_height(&egui::TextStyle::Monospace) + ui.spacing().button_padding.y * 2.0)
+        .max(ui.spacing().interact_size.y);
+        let total_rows = (ram.size() - self.alignment as usize) / 2;
+
+        let mut scroll_area = egui::ScrollArea::vertical()
+        .auto_shrink([false, false]);
+        if let Some(address) = self.scroll_to.take() {
+            let row = self.address_row(address).saturating_sub(SCROLL_CONTEXT_ROWS);
+            scroll_area = scroll_area.vertical_scroll_offset(row as f32 * (row_height + ui.spacing().item_spacing.y));
+        }
+
+        let heat = profile.map(|profile| (profile, profile.max_executions()));
+        ui.group(|ui| {
+            scroll_area.show_rows(ui, row_height, total_rows, |ui, rows| {
+                self.visible_rows = rows.clone();
+                for row in rows {
+                    self.draw_row(ui, ram, self.row_address(row), pc, heat);
+                }
+            });
+        });
+
+        self.draw_label_editor(ui.ctx());
+    }
+}
+
+/// From a faint yellow for the rarely executed rows to red for the hottest one, on a
+/// logarithmic scale.
+fn heat_color(executions: u64, max_executions: u64) -> egui::Color32 {
+    let heat = ((1 + executions) as f32).ln() / ((1 + max_executions.max(1)) as f32).ln();
+    egui::Color32::from_rgba_unmultiplied(255, (220.0 * (1.0 - heat)) as u8, 0, (30.0 + 90.0 * heat) as u8)
+}
@@ -0,0 +1,210 @@
+use crate::chip8_core::graphics::{FrameBuffer, Screen, SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::timer::Timer;
+use super::palette::Palette;
+
+const FRAME_DURATION: f32 = 1.0 / 60.0;
+const MIN_INTENSITY: f32 = 0.01;
+
+/// Size in texels of a CHIP-8 pixel when a post-processing effect is active.
+const EFFECT_PIXEL_SIZE: usize = 4;
+const GRID_DARKENING: f32 = 0.6;
+const SCANLINE_DARKENING: f32 = 0.7;
+const BLOOM_STRENGTH: f32 = 0.35;
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum ScalingMode {
+    Stretch,
+    AspectFit,
+    Integer,
+}
+
+impl ScalingMode {
+    /// Size of the screen image inside an area of the given size.
+    pub fn image_size(&self, area: egui::Vec2) -> egui::Vec2 {
+        let screen_size = egui::vec2(SCREEN_WIDTH as f32, SCREEN_HEIGHT as f32);
+        match self {
+            ScalingMode::Stretch => area,
+            ScalingMode::AspectFit => {
+                let scale = (area.x / screen_size.x).min(area.y / screen_size.y);
+                screen_size * scale
+            },
+            ScalingMode::Integer => {
+                let scale = (area.x / screen_size.x).min(area.y / screen_size.y).floor().max(1.0);
+                screen_size * scale
+            },
+        }
+    }
+}
+
+pub struct ScreenFramebuffer {
+    pub texture: egui::TextureHandle,
+
+    pub palette: Palette,
+
+    pub ghosting: bool,
+    /// Fraction of a pixel's intensity lost every 60 Hz frame once it is turned off.
+    pub decay_rate: f32,
+
+    pub scaling_mode: ScalingMode,
+    pub border_color: [u8; 3],
+    pub pixel_grid: bool,
+    pub scanlines: bool,
+    pub bloom: bool,
+
+    intensity: Vec<f32>,
+    decay_timer: Timer,
+}
+
+impl ScreenFramebuffer {
+    pub fn new(ctx: &egui::Context) -> Self {
+
+        let options = egui::TextureOptions::NEAREST;
+
+        let texture = {
+            ctx.load_texture("ScreenTex",
+            egui::ColorImage::from_rgb([SCREEN_WIDTH, SCREEN_HEIGHT], &[0; SCREEN_HEIGHT * SCREEN_WIDTH * 3]),
+            options)
+        };
+
+        Self {
+            texture,
+            palette: Palette::default(),
+            ghosting: false,
+            decay_rate: 0.3,
+            scaling_mode: ScalingMode::AspectFit,
+            border_color: [0; 3],
+            pixel_grid: false,
+            scanlines: false,
+            bloom: false,
+            intensity: vec![0.0; SCREEN_WIDTH * SCREEN_HEIGHT],
+            decay_timer: Timer::new(),
+        }
+    }
+
+    /// Whether some pixels are still fading out and the texture has to be redrawn
+    /// even though the screen did not change.
+    pub fn is_fading(&self) -> bool {
+        self.ghosting && self.intensity.iter().any(|i| *i > 0.0 && *i < 1.0)
+    }
+
+    fn update_intensity(&mut self, screen: &Screen) {
+        let elapsed_frames = self.decay_timer.restart().as_secs_f32() / FRAME_DURATION;
+        let decay = (1.0 - self.decay_rate).powf(elapsed_frames);
+
+        for y in 0..SCREEN_HEIGHT {
+            for x in 0..SCREEN_WIDTH {
+                let intensity = &mut self.intensity[y * SCREEN_WIDTH + x];
+                *intensity = if screen.is_pixel_set(x, y) {
+                    1.0
+                }
+                else if self.ghosting && *intensity * decay >= MIN_INTENSITY {
+                    *intensity * decay
+                }
+                else {
+                    0.0
+                };
+            }
+        }
+    }
+
+    fn pixel_color(&self, intensity: f32) -> [f32; 3] {
+        let background = self.palette.background();
+        let foreground = self.palette.foreground();
+        let mut color = [0.0; 3];
+        for (i, channel) in color.iter_mut().enumerate() {
+            let background = background[i] as f32;
+            let foreground = foreground[i] as f32;
+            *channel = background + (foreground - background) * intensity;
+        }
+        color
+    }
+
+    fn has_effects(&self) -> bool {
+        self.pixel_grid || self.scanlines || self.bloom
+    }
+
+    /// Adds to every pixel a glow made of the average of its neighbours.
+    fn apply_bloom(colors: &[[f32; 3]]) -> Vec<[f32; 3]> {
+        let mut result = colors.to_vec();
+        for y in 0..SCREEN_HEIGHT {
+            for x in 0..SCREEN_WIDTH {
+                let mut glow = [0.0; 3];
+                let mut count = 0.0;
+                for ny in y.saturating_sub(1)..=(y + 1).min(SCREEN_HEIGHT - 1) {
+                    for nx in x.saturating_sub(1)..=(x + 1).min(SCREEN_WIDTH - 1) {
+                        for (glow, channel) in glow.iter_mut().zip(colors[ny * SCREEN_WIDTH + nx]) {
+                            *glow += channel;
+                        }
+                        count += 1.0;
+                    }
+                }
+                for (channel, glow) in result[y * SCREEN_WIDTH + x].iter_mut().zip(glow) {
+                    *channel += glow / count * BLOOM_STRENGTH;
+                }
+            }
+        }
+        result
+    }
+
+    fn build_image(&self) -> egui::ColorImage {
+        let mut colors: Vec<[f32; 3]> = self.intensity.iter()
+        .map(|intensity| self.pixel_color(*intensity))
+        .collect();
+
+        if !self.has_effects() {
+            let image_buffer: Vec<u8> = colors.iter()
+            .flat_map(|color| color.map(|channel| channel.round() as u8))
+            .collect();
+            return egui::ColorImage::from_rgb([SCREEN_WIDTH, SCREEN_HEIGHT], image_buffer.as_slice());
+        }
+
+        if self.bloom {
+            colors = Self::apply_bloom(&colors);
+        }
+
+        let width = SCREEN_WIDTH * EFFECT_PIXEL_SIZE;
+        let height = SCREEN_HEIGHT * EFFECT_PIXEL_SIZE;
+        let mut image_buffer = Vec::with_capacity(width * height * 3);
+        for y in 0..height {
+            for x in 0..width {
+                let mut factor = 1.0;
+                if self.scanlines && y % 2 == 1 {
+                    factor *= SCANLINE_DARKENING;
+                }
+                let last_texel = EFFECT_PIXEL_SIZE - 1;
+                if self.pixel_grid && (x % EFFECT_PIXEL_SIZE == last_texel || y % EFFECT_PIXEL_SIZE == last_texel) {
+                    factor *= GRID_DARKENING;
+                }
+
+                let color = colors[(y / EFFECT_PIXEL_SIZE) * SCREEN_WIDTH + x / EFFECT_PIXEL_SIZE];
+                image_buffer.extend(color.map(|channel| (channel * factor).round().min(255.0) as u8));
+            }
+        }
+
+        egui::ColorImage::from_rgb([width, height], image_buffer.as_slice())
+    }
+
+    /// Draws the screen in an area of the given size using the scaling mode, fills the rest
+    /// of the area with the border color and returns where the image was drawn.
+    pub fn draw(&self, ui: &mut egui::Ui, area_size: egui::Vec2) -> egui::Rect {
+        let (area, _) = ui.allocate_exact_size(area_size, egui::Sense::hover());
+        let [r, g, b] = self.border_color;
+        ui.painter().rect_filled(area, 0.0, egui::Color32::from_rgb(r, g, b));
+
+        let image_rect = egui::Rect::from_center_size(area.center(), self.scaling_mode.image_size(area_size));
+        egui::Image::new(&self.texture, image_rect.size()).paint_at(ui, image_rect);
+
+        image_rect
+    }
+}
+
+impl FrameBuffer for ScreenFramebuffer {
+    fn update(&mut self, screen: &Screen) {
+            self.update_intensity(screen);
+
+            self.texture.set(
+                self.build_image(),
+                egui::TextureOptions::NEAREST
+            );
+    }
+}
@@ -0,0 +1,195 @@
+use std::io;
+use std::path::{Path, PathBuf};
+
+use super::capture::export_file;
+use crate::chip8_core::chip8::Chip8;
+use crate::chip8_core::coverage::{Coverage, SourceMap, EXECUTED, READ, WRITTEN};
+use crate::chip8_core::memory::Ram;
+use crate::chip8_core::symbols::SymbolTable;
+
+const BYTES_PER_ROW: usize = 16;
+
+const EXECUTED_COLOR: egui::Color32 = egui::Color32::from_rgba_premultiplied(0, 90, 0, 90);
+const READ_COLOR: egui::Color32 = egui::Color32::from_rgba_premultiplied(0, 50, 110, 110);
+const WRITTEN_COLOR: egui::Color32 = egui::Color32::from_rgba_premultiplied(110, 60, 0, 110);
+/// Bytes both executed and read or written, usually self-modifying code.
+const MIXED_COLOR: egui::Color32 = egui::Color32::from_rgba_premultiplied(110, 0, 110, 110);
+
+/// Hexadecimal dump of the whole memory with the coverage recorded by `Coverage`
+/// drawn behind each byte.
+pub struct HexViewer {
+    pub open: bool,
+    pub show_coverage: bool,
+
+    goto_text: String,
+    scroll_to: Option<u16>,
+    source_map: Option<(PathBuf, SourceMap)>,
+}
+
+impl HexViewer {
+    pub fn new() -> Self {
+        Self {
+            open: false,
+            show_coverage: true,
+            goto_text: String::new(),
+            scroll_to: None,
+            source_map: None,
+        }
+    }
+
+    /// Returns the action and the error when loading the source map or an export failed.
+    pub fn show(&mut self, ctx: &egui::Context, chip8: &mut Chip8, symbols: &SymbolTable, rom: Option<&Path>) -> Option<(&'static str, io::Error)> {
+        let mut open = self.open;
+        let mut error = None;
+
+        egui::Window::new("Memory")
+        .open(&mut open)
+        .default_height(400.0)
+        .show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                let mut recording = chip8.get_coverage().enabled;
+                if ui.checkbox(&mut recording, "Record coverage").changed() {
+                    chip8.set_coverage_enabled(recording);
+                }
+                if ui.button("Clear").clicked() {
+                    chip8.clear_coverage();
+                }
+                ui.checkbox(&mut self.show_coverage, "Overlay");
+            });
+            ui.horizontal(|ui| {
+                if ui.button("Load source map").clicked() {
+                    error = self.load_source_map();
+                }
+                if ui.button("Export lcov by address").clicked() {
+                    let name = rom
+                    .and_then(Path::file_name)
+                    .map(|name| name.to_string_lossy().to_string())
+                    .unwrap_or_else(|| String::from("rom.ch8"));
+                    let program_start = chip8.get_config().program_start;
+                    let program_length = chip8.get_program_length();
+                    error = export_file("lcov", "info", "coverage.info", "exporting the coverage", |writer| {
+                        chip8.get_coverage().write_lcov_by_address(writer, &name, program_start, program_length)
+                    });
+                }
+                let export_by_line = ui.add_enabled(self.source_map.is_some(), egui::Button::new("Export lcov by line"));
+                if export_by_line.clicked() {
+                    if let Some((_, source_map)) = &self.source_map {
+                        error = export_file("lcov", "info", "coverage.info", "exporting the coverage", |writer| {
+                            chip8.get_coverage().write_lcov_by_line(writer, source_map)
+                        });
+                    }
+                }
+            });
+            if let Some((path, source_map)) = &self.source_map {
+                ui.label(format!("{}: {} addresses", path.display(), source_map.len()));
+            }
+            draw_legend(ui, chip8.get_coverage());
+            ui.separator();
+
+            ui.horizontal(|ui| {
+                let goto_edit = ui.add(egui::TextEdit::singleline(&mut self.goto_text)
+                    .desired_width(80.0)
+                    .hint_text("address"));
+                let submitted = goto_edit.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                if ui.button("Go to").clicked() || submitted {
+                    self.scroll_to = symbols.parse_address(&self.goto_text);
+                }
+            });
+            let coverage = if self.show_coverage { Some(chip8.get_coverage()) } else { None };
+            self.draw_rows(ui, chip8.get_ram(), coverage);
+        });
+
+        self.open = open;
+        error
+    }
+
+    fn load_source_map(&mut self) -> Option<(&'static str, io::Error)> {
+        let file = rfd::FileDialog::new()
+        .add_filter("Source map", &["map", "txt"])
+        .pick_file()?;
+
+        match SourceMap::load(&file) {
+            Ok(source_map) => {
+                self.source_map = Some((file, source_map));
+                None
+            },
+            Err(e) => Some(("loading the source map", e)),
+        }
+    }
+
+    fn draw_rows(&mut self, ui: &mut egui::Ui, ram: &Ram, coverage: Option<&Coverage>) {
+        let row_height = ui.text_style_height(&egui::TextStyle::Monospace);
+        let total_rows = ram.size().div_ceil(BYTES_PER_ROW);
+
+        let mut scroll_area = egui::ScrollArea::vertical()
+        .auto_shrink([false, false]);
+        if let Some(address) = self.scroll_to.take() {
+            let row = address as usize / BYTES_PER_ROW;
+            scroll_area = scroll_area.vertical_scroll_offset(row as f32 * (row_height + ui.spacing().item_spacing.y));
+        }
+
+        scroll_area.show_rows(ui, row_height, total_rows, |ui, rows| {
+            for row in rows {
+                let start = row * BYTES_PER_ROW;
+                // A 64 KiB memory ends at 0x10000, iterate in usize so the last row fits.
+                let end = (start + BYTES_PER_ROW).min(ram.size());
+                ui.horizontal(|ui| {
+                    ui.spacing_mut().item_spacing.x = 4.0;
+                    ui.monospace(format!("{:04X}", start));
+                    for address in (start..end).map(|address| address as u16) {
+                        let value = match ram.read8(address) {
+                            Ok(value) => value,
+                            Err(_) => break,
+                        };
+                        let mut text = egui::RichText::new(format!("{:02X}", value)).monospace();
+                        let flags = coverage.map_or(0, |coverage| coverage.flags(address));
+                        if let Some(color) = coverage_color(flags) {
+                            text = text.background_color(color);
+                        }
+                        ui.label(text).on_hover_text(describe_flags(address, flags));
+                    }
+                });
+            }
+        });
+    }
+}
+
+fn coverage_color(flags: u8) -> Option<egui::Color32> {
+    match (flags & EXECUTED != 0, flags & READ != 0, flags & WRITTEN != 0) {
+        (false, false, false) => None,
+        (true, false, false) => Some(EXECUTED_COLOR),
+        (false, true, false) => Some(READ_COLOR),
+        (false, _, true) => Some(WRITTEN_COLOR),
+        (true, _, _) => Some(MIXED_COLOR),
+    }
+}
+
+fn describe_flags(address: u16, flags: u8) -> String {
+    let mut uses = Vec::new();
+    if flags & EXECUTED != 0 {
+        uses.push("executed");
+    }
+    if flags & READ != 0 {
+        uses.push("read");
+    }
+    if flags & WRITTEN != 0 {
+        uses.push("written");
+    }
+
+    if uses.is_empty() {
+        format!("{:#05X}: never used", address)
+    }
+    else {
+        format!("{:#05X}: {}", address, uses.join(", "))
+    }
+}
+
+fn draw_legend(ui: &mut egui::Ui, coverage: &Coverage) {
+    ui.horizontal(|ui| {
+        for (name, color, flag) in [("Executed", EXECUTED_COLOR, EXECUTED), ("Read", READ_COLOR, READ), ("Written", WRITTEN_COLOR, WRITTEN)] {
+            ui.label(egui::RichText::new(format!(" {} ", name)).background_color(color));
+            ui.label(format!("{} bytes", coverage.count(flag)));
+        }
+        ui.label(egui::RichText::new(" Code and data ").background_color(MIXED_COLOR));
+    });
+}
@@ -1,65 +1,32 @@
-use crate::chip8_core::chip8_errors::Chip8ErrorKind;
+use crate::chip8_core::chip8_errors::{Chip8Error, ErrorPolicy};
 use crate::chip8_core::graphics::FrameBuffer;
 use crate::chip8_core::instruction::Instruction;
 
 use crate::chip8_core::{
     chip8::Chip8,
-    cpu::CPU,
+    cpu::{CPU, KeyWait},
     memory::Ram,
-    PROGRAM_START_ADDRESS,
     graphics::{SCREEN_HEIGHT, SCREEN_WIDTH},
     keypad::CHIP8_KEYPAD,
+    fonts::{Font, FONT_SIZE},
+    machine::MachineConfig,
+    timing::TimingMode,
 };
 use crate::timer::Timer;
 use super::audio::Chip8Buzzer;
-
-struct ScreenFramebuffer {
-    texture: egui::TextureHandle,
-
-    background_color: [u8; 3],
-    foreground_color: [u8; 3],
-}
-
-impl ScreenFramebuffer {
-    fn new(ctx: &egui::Context) -> Self {
-
-        let options = egui::TextureOptions::NEAREST;
-
-        let texture = {
-            ctx.load_texture("ScreenTex", 
-            egui::ColorImage::from_rgb([SCREEN_WIDTH, SCREEN_HEIGHT], &[0; SCREEN_HEIGHT * SCREEN_WIDTH * 3]), 
-            options)
-        };
-
-        Self {
-            texture,
-            background_color: [0; 3],
-            foreground_color: [255; 3],
-        }
-    }
-}
-
-impl FrameBuffer for ScreenFramebuffer {
-    fn update(&mut self, screen: &crate::chip8_core::graphics::Screen) {
-            let mut image_buffer = Vec::new();
-            for y in 0..SCREEN_HEIGHT {
-                for x in 0..SCREEN_WIDTH {
-                    if screen.is_pixel_set(x, y) {
-                        image_buffer.extend_from_slice(&self.foreground_color);
-                    }
-                    else {
-                        image_buffer.extend_from_slice(&self.background_color);
-    
-                    }
-                }
-            }
-    
-            self.texture.set(
-                egui::ColorImage::from_rgb([SCREEN_WIDTH, SCREEN_HEIGHT], image_buffer.as_slice()),
-                egui::TextureOptions::NEAREST
-            );
-    }
-}
+use super::framebuffer::{ScreenFramebuffer, ScalingMode};
+use super::capture::{self, ScreenRecorder};
+use super::palette::{Palette, PALETTE_EXTENSION};
+use super::disassembly::DisassemblyView;
+use super::sprite_viewer::SpriteViewer;
+use super::profiler_view::ProfilerView;
+use super::hex_viewer::HexViewer;
+use super::scripting::ScriptHost;
+use super::console::{ConsoleRequest, DebugConsole};
+use crate::remote::{RemoteCommand, StopReason, DEFAULT_DAP_PORT, DEFAULT_GDB_PORT};
+use crate::remote::dap::DapServer;
+use crate::remote::gdb::GdbServer;
+use std::path::PathBuf;
 
 fn draw_cpu(ui: &mut egui::Ui, cpu: &CPU) {
     ui.vertical_centered(|ui| {
@@ -82,6 +49,23 @@ fn draw_cpu(ui: &mut egui::Ui, cpu: &CPU) {
     ui.label(format!("Delay timer = {}", cpu.delay_timer));
     ui.add_space(space);
 
+    match cpu.get_key_wait() {
+        Some(KeyWait::Press { .. }) => {
+            ui.label(egui::RichText::new("Waiting for key").color(egui::Color32::YELLOW));
+            ui.add_space(space);
+        },
+        Some(KeyWait::Release(key)) => {
+            ui.label(egui::RichText::new(format!("Waiting for key {:?} release", key)).color(egui::Color32::YELLOW));
+            ui.add_space(space);
+        },
+        None => {},
+    }
+
+    if cpu.is_waiting_for_vblank() {
+        ui.label(egui::RichText::new("Waiting for vertical blank").color(egui::Color32::YELLOW));
+        ui.add_space(space);
+    }
+
     egui::Grid::new("registers_display")
     .spacing((5.0, 10.0))
     .show(ui, |ui| {
@@ -95,51 +79,95 @@ fn draw_cpu(ui: &mut egui::Ui, cpu: &CPU) {
     });
 }
 
-fn draw_code(ui: &mut egui::Ui, ram: &Ram, start: usize, length: usize, pc: usize) {
-    let code = ram.read_bytes(start as u16, (PROGRAM_START_ADDRESS + length) as u16)
-    .unwrap();
-
+fn draw_call_stack(ui: &mut egui::Ui, cpu: &CPU, ram: &Ram) {
     ui.vertical_centered(|ui| {
-        ui.heading("Program");
+        ui.heading("Call stack");
     });
     ui.separator();
 
-    let make_text = |change_color: bool, color: egui::Color32, text_str: &str| {
-        let mut text = egui::RichText::new(text_str);
-        if change_color {
-            text = text.color(color);
+    let frames = cpu.stack.frames();
+    if frames.is_empty() {
+        ui.label("Empty");
+        return;
+    }
+
+    egui::Grid::new("call_stack_display")
+    .striped(true)
+    .show(ui, |ui| {
+        for (depth, return_addr) in frames.iter().enumerate().rev() {
+            let caller = return_addr.wrapping_sub(2);
+            let caller_instr = match ram.read16(caller) {
+                Ok(opcode) => Instruction::new(opcode).to_string(),
+                Err(_) => String::from("???"),
+            };
+            ui.label(format!("#{}", depth));
+            ui.label(format!("{:#04X}", return_addr));
+            ui.label(format!("{:#04X}\t{}", caller, caller_instr));
+            ui.end_row();
         }
-        text
-    };
+    });
+}
 
-    ui.group(|ui| {
-        egui::ScrollArea::new([false, true])
-        .scroll2([false, true])
-        .show_rows(ui, 0.0, (ui.available_height() / ui.spacing().combo_height) as usize, |ui, _| {
-            let mut i = 0;
-            while i < code.len() {
-                let hi = (code[i] as u16) << 8;
-                i += 1;
-                if i >= code.len() {
-                    break;
-                }
-                let lo = code[i] as u16;
-                i += 1;
-                
-                let opcode = hi | lo;
-                ui.vertical_centered(|ui| {
-                    let addr = start + i - 2;
-                    let change_color = addr == pc;
-                    let text = format!("{:#04X}\t{}", addr, Instruction::new(opcode).to_string());
-                    ui.label(make_text(change_color, egui::Color32::GREEN, &text));
-                });
-                if i < code.len() - 1 {
-                    ui.separator();
-                }
+fn draw_error_context(ui: &mut egui::Ui, error: &Chip8Error) {
+    egui::Grid::new("error_context")
+    .show(ui, |ui| {
+        ui.label("PC");
+        ui.label(format!("{:#X}", error.pc));
+        ui.end_row();
+
+        ui.label("Opcode");
+        match error.opcode {
+            Some(opcode) => ui.label(format!("{:#06X}\t{}", opcode, Instruction::new(opcode).to_string())),
+            None => ui.label("-"),
+        };
+        ui.end_row();
+
+        ui.label("I");
+        ui.label(format!("{:#X}", error.i));
+        ui.end_row();
+
+        ui.label("Stack depth");
+        ui.label(format!("{}", error.stack_depth));
+        ui.end_row();
+    });
+}
+
+fn draw_error_log(ui: &mut egui::Ui, chip8: &mut Chip8) {
+    let header = format!("Error log ({})", chip8.get_error_log().len());
+    egui::CollapsingHeader::new(header)
+    .id_source("error_log")
+    .show(ui, |ui| {
+        egui::ScrollArea::vertical()
+        .max_height(150.0)
+        .show(ui, |ui| {
+            for error in chip8.get_error_log() {
+                ui.label(format!("{}", error));
             }
         });
+        if ui.button("Clear").clicked() {
+            chip8.clear_error_log();
+        }
     });
-    
+}
+
+fn error_policy_combo(ui: &mut egui::Ui, label: &str, emulate_label: &str, policy: &mut ErrorPolicy) -> bool {
+    let policy_text = |policy: &ErrorPolicy| match policy {
+        ErrorPolicy::Halt => "Halt",
+        ErrorPolicy::Ignore => "Log and continue",
+        ErrorPolicy::Emulate => emulate_label,
+    };
+    let mut changed = false;
+    ui.label(label);
+    egui::ComboBox::from_id_source(label)
+    .selected_text(policy_text(policy))
+    .show_ui(ui, |ui| {
+        for value in [ErrorPolicy::Halt, ErrorPolicy::Ignore, ErrorPolicy::Emulate] {
+            changed |= ui.selectable_value(policy, value, policy_text(&value)).changed();
+        }
+    });
+    ui.end_row();
+
+    changed
 }
 
 #[derive(PartialEq)]
@@ -147,6 +175,11 @@ enum ExecutionState {
     Pause,
     Continue,
     RunNext,
+    StepOver { return_address: u16, depth: u8 },
+    StepOut { depth: u8 },
+    /// Runs until the frame count changes or a `DXYN`/`00E0` is executed.
+    NextFrame { frame: u64 },
+    RunToCursor { address: u16 },
     Halt,
 }
 
@@ -155,6 +188,42 @@ struct GameLoadingWindow {
     loaded_file_name: String,
 }
 
+struct FileErrorWindow {
+    error: std::io::Error,
+    action: &'static str,
+}
+
+const RAM_SIZES: [usize; 4] = [2048, 4096, 8192, 0x10000];
+
+/// Frames the emulation may run to catch up after a slow UI frame.
+const MAX_PENDING_FRAMES: f64 = 4.0;
+
+/// Hold to fast forward.
+const TURBO_KEY: egui::Key = egui::Key::Tab;
+/// Cycles through the slow motion speeds.
+const SLOW_MOTION_KEY: egui::Key = egui::Key::F9;
+const SLOW_MOTION_SPEEDS: [f64; 4] = [1.0, 0.5, 0.25, 0.1];
+const TURBO_MULTIPLIERS: [f64; 3] = [2.0, 4.0, 8.0];
+/// Part of a UI frame given to the emulation when fast forwarding uncapped, in seconds.
+const UNCAPPED_TIME_BUDGET: f64 = 0.012;
+
+/// Speed of the fast forward while `TURBO_KEY` is held.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TurboSpeed {
+    Multiplier(f64),
+    /// As many frames as the host can run.
+    Uncapped,
+}
+
+impl TurboSpeed {
+    fn name(&self) -> String {
+        match self {
+            TurboSpeed::Multiplier(multiplier) => format!("x{}", multiplier),
+            TurboSpeed::Uncapped => String::from("Uncapped"),
+        }
+    }
+}
+
 const MAP_KEYS_LAYOUT: [egui::Key; 16] = [
     egui::Key::Num1, egui::Key::Num2, egui::Key::Num3, egui::Key::Num4,
     egui::Key::A, egui::Key::Z, egui::Key::E, egui::Key::R,
@@ -171,6 +240,16 @@ pub struct MainApp {
 
     exec_state: ExecutionState,
     game_freq: f64,
+    /// Emulated time not run yet with `TimingMode::CosmacVip`, in 60 Hz frames.
+    pending_frames: f64,
+
+    turbo: bool,
+    /// A text field had the keyboard focus during the last update.
+    typing: bool,
+    turbo_speed: TurboSpeed,
+    slow_motion: f64,
+    /// Emulated frames per real frame, measured over the last updates.
+    measured_speed: f64,
 
     debug_mode: bool,
 
@@ -178,14 +257,37 @@ pub struct MainApp {
 
     buzzer: Chip8Buzzer,
 
-    run_program_result: Option<Chip8ErrorKind>
+    recorder: Option<ScreenRecorder>,
+    file_error_window: Option<FileErrorWindow>,
+
+    rom_path: Option<PathBuf>,
+
+    run_program_result: Option<Chip8Error>,
+
+    disassembly: DisassemblyView,
+    sprite_viewer: SpriteViewer,
+    profiler_view: ProfilerView,
+    hex_viewer: HexViewer,
+    scripts: ScriptHost,
+    console: DebugConsole,
+    gdb_server: Option<GdbServer>,
+    gdb_port: u16,
+    dap_server: Option<DapServer>,
+    dap_port: u16,
+
+    /// Memory layout edited in the settings, applied by `apply_machine_config`.
+    machine_config: MachineConfig,
 }
 
 impl MainApp {
     pub fn new(ctx: &egui::Context) -> Self {
+        let game_freq = 500.0;
+        let machine_config = MachineConfig::default();
+        let mut chip8 = Chip8::new(machine_config);
+        chip8.set_cpu_frequency(game_freq);
 
         Self {
-            chip8: Chip8::new(),
+            chip8,
             game_loading_window: None,
             update_timer: Timer::new(),
             framebuffer: ScreenFramebuffer::new(ctx),
@@ -193,14 +295,34 @@ impl MainApp {
             exec_state: ExecutionState::Pause,
             #[cfg(not(debug_assertions))]
             exec_state: ExecutionState::Continue,
-            game_freq: 500.0,
+            game_freq,
+            pending_frames: 0.0,
+            turbo: false,
+            typing: false,
+            turbo_speed: TurboSpeed::Multiplier(4.0),
+            slow_motion: 1.0,
+            measured_speed: 1.0,
             #[cfg(debug_assertions)]
             debug_mode: true,
             #[cfg(not(debug_assertions))]
             debug_mode: false,
             show_settings_window: false,
             buzzer: Chip8Buzzer::new(),
-            run_program_result: None
+            recorder: None,
+            file_error_window: None,
+            rom_path: None,
+            run_program_result: None,
+            disassembly: DisassemblyView::new(),
+            sprite_viewer: SpriteViewer::new(),
+            profiler_view: ProfilerView::new(),
+            hex_viewer: HexViewer::new(),
+            scripts: ScriptHost::new(),
+            console: DebugConsole::new(),
+            gdb_server: None,
+            gdb_port: DEFAULT_GDB_PORT,
+            dap_server: None,
+            dap_port: DEFAULT_DAP_PORT,
+            machine_config,
         }
     }
 
@@ -211,19 +333,21 @@ impl MainApp {
         .show(ctx, |ui| {
             draw_cpu(ui, self.chip8.get_cpu());
             ui.separator();
-            let prog_length = self.chip8.get_program_length();
-
-            let pc = self.chip8.get_cpu().pc as usize;
-
-            let start = if pc > PROGRAM_START_ADDRESS {
-                let diff = pc - PROGRAM_START_ADDRESS;
-                pc - std::cmp::min(diff, 6)
-            }
-            else {
-                PROGRAM_START_ADDRESS
-            };
-            if prog_length > 0 {
-                draw_code(ui, self.chip8.get_ram(), start, prog_length, pc);
+            draw_call_stack(ui, self.chip8.get_cpu(), self.chip8.get_ram());
+            ui.separator();
+            draw_error_log(ui, &mut self.chip8);
+            ui.separator();
+            if self.chip8.get_program_length() > 0 {
+                let profile = if self.profiler_view.show_heatmap && self.chip8.get_profiler().total_executions() > 0 {
+                    Some(self.chip8.get_profiler())
+                }
+                else {
+                    None
+                };
+                self.disassembly.draw(ui, self.chip8.get_ram(), self.chip8.get_cpu().pc, profile);
+                if let Some((action, e)) = self.disassembly.take_error() {
+                    self.show_file_error(action, e);
+                }
             }
             else {
                 ui.heading("No program loaded, use the menu Game to load one");
@@ -238,6 +362,79 @@ impl MainApp {
         }
     }
 
+    /// Rebuilds the machine with the edited memory layout, keeping the other settings,
+    /// and reloads the ROM.
+    fn apply_machine_config(&mut self) {
+        let mut chip8 = Chip8::new(self.machine_config);
+        chip8.set_cpu_frequency(self.game_freq);
+        chip8.set_timing_mode(self.chip8.get_timing_mode());
+        chip8.set_quirks(*self.chip8.get_quirks());
+        chip8.set_error_policies(*self.chip8.get_error_policies());
+        chip8.set_font(self.chip8.get_font().clone());
+        chip8.set_coverage_enabled(self.chip8.get_coverage().enabled);
+        chip8.set_pc_hooks(self.chip8.get_pc_hooks().clone());
+        for address in self.chip8.get_breakpoints() {
+            chip8.add_breakpoint(*address);
+        }
+        chip8.set_watches(self.chip8.get_watches());
+        self.chip8 = chip8;
+
+        self.run_program_result = None;
+        self.exec_state = if self.debug_mode { ExecutionState::Pause } else { ExecutionState::Continue };
+
+        if let Some(rom) = self.rom_path.clone() {
+            if let Err(e) = self.chip8.load_program(&rom) {
+                let loaded_file_name = rom.file_name().unwrap_or_default().to_string_lossy().to_string();
+                self.game_loading_window = Some(GameLoadingWindow { error: e, loaded_file_name });
+            }
+        }
+    }
+
+    fn draw_machine_settings(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Preset");
+            for (name, preset) in MachineConfig::presets() {
+                if ui.selectable_label(self.machine_config == preset, name).clicked() {
+                    self.machine_config = preset;
+                }
+            }
+        });
+
+        egui::Grid::new("machine_config")
+        .show(ui, |ui| {
+            ui.label("Program start");
+            ui.add(egui::DragValue::new(&mut self.machine_config.program_start)
+                .clamp_range(0..=self.machine_config.ram_size - 2)
+                .hexadecimal(3, false, true));
+            ui.end_row();
+
+            ui.label("Font address");
+            ui.add(egui::DragValue::new(&mut self.machine_config.font_address)
+                .hexadecimal(3, false, true));
+            ui.end_row();
+
+            ui.label("RAM size");
+            egui::ComboBox::from_id_source("ram_size")
+            .selected_text(format!("{} bytes", self.machine_config.ram_size))
+            .show_ui(ui, |ui| {
+                for ram_size in RAM_SIZES {
+                    ui.selectable_value(&mut self.machine_config.ram_size, ram_size, format!("{} bytes", ram_size));
+                }
+            });
+            ui.end_row();
+        });
+
+        let validation = self.machine_config.validate();
+        if let Err(e) = &validation {
+            ui.colored_label(egui::Color32::RED, format!("Invalid layout: {}", e));
+        }
+
+        let changed = self.machine_config != *self.chip8.get_config();
+        if ui.add_enabled(changed && validation.is_ok(), egui::Button::new("Apply and reload the ROM")).clicked() {
+            self.apply_machine_config();
+        }
+    }
+
     fn game_menu(&mut self, ui: &mut egui::Ui, frame: &mut sfml::graphics::RenderWindow) -> bool {
         if ui.button("Load new Game").clicked() {
             
@@ -245,15 +442,8 @@ impl MainApp {
             if let Some(file) = file {
                
                 let file_name = file.file_name().unwrap().to_string_lossy().to_string();
-                self.reset();
-
-                match self.chip8.load_program(&file) {
-                    Ok(_) => {
-                        frame.set_title(&file_name);
-                    },
-                    Err(e) => {
-                        self.game_loading_window = Some(GameLoadingWindow { error: e, loaded_file_name: file_name });
-                    }
+                if let Err(e) = self.load_rom(file, frame) {
+                    self.game_loading_window = Some(GameLoadingWindow { error: e, loaded_file_name: file_name });
                 }
             }
             true
@@ -271,34 +461,611 @@ impl MainApp {
         }
     }
 
+    fn load_rom(&mut self, file: PathBuf, frame: &mut sfml::graphics::RenderWindow) -> std::io::Result<()> {
+        self.reset();
+        self.chip8.load_program(&file)?;
+        self.rom_loaded(file, frame);
+        Ok(())
+    }
+
+    /// Updates the title, the palette and the labels for the ROM just loaded.
+    fn rom_loaded(&mut self, file: PathBuf, frame: &mut sfml::graphics::RenderWindow) {
+        let file_name = file.file_name().unwrap_or_default().to_string_lossy().to_string();
+        frame.set_title(&file_name);
+        self.load_rom_palette(&file);
+        if let Err(e) = self.disassembly.load_rom(&file) {
+            self.show_file_error("loading the labels", e);
+        }
+        self.rom_path = Some(file);
+        self.run_program_result = None;
+    }
+
+    fn screen_palette(&self) -> [[u8; 3]; 2] {
+        [self.framebuffer.palette.background(), self.framebuffer.palette.foreground()]
+    }
+
+    fn show_file_error(&mut self, action: &'static str, error: std::io::Error) {
+        self.file_error_window = Some(FileErrorWindow { error, action });
+    }
+
+    fn set_palette(&mut self, palette: Palette) {
+        self.framebuffer.palette = palette;
+        self.framebuffer.update(self.chip8.get_screen());
+    }
+
+    /// Uses the palette stored next to the ROM, or the default one.
+    fn load_rom_palette(&mut self, rom: &std::path::Path) {
+        let path = Palette::rom_palette_path(rom);
+        if !path.exists() {
+            self.set_palette(Palette::default());
+            return;
+        }
+
+        match Palette::load(&path) {
+            Ok(palette) => self.set_palette(palette),
+            Err(e) => {
+                self.set_palette(Palette::default());
+                self.show_file_error("loading the ROM palette", e);
+            },
+        }
+    }
+
+    fn draw_palette_files(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            if ui.button("Import").clicked() {
+                let file = rfd::FileDialog::new()
+                .add_filter("Palette", &[PALETTE_EXTENSION])
+                .pick_file();
+                if let Some(file) = file {
+                    match Palette::load(&file) {
+                        Ok(palette) => self.set_palette(palette),
+                        Err(e) => self.show_file_error("importing the palette", e),
+                    }
+                }
+            }
+
+            if ui.button("Export").clicked() {
+                let file = rfd::FileDialog::new()
+                .add_filter("Palette", &[PALETTE_EXTENSION])
+                .save_file();
+                if let Some(file) = file {
+                    if let Err(e) = self.framebuffer.palette.save(&file) {
+                        self.show_file_error("exporting the palette", e);
+                    }
+                }
+            }
+
+            if let Some(rom_path) = &self.rom_path {
+                if ui.button("Save for this ROM").clicked() {
+                    let path = Palette::rom_palette_path(rom_path);
+                    if let Err(e) = self.framebuffer.palette.save(&path) {
+                        self.show_file_error("saving the ROM palette", e);
+                    }
+                }
+            }
+        });
+    }
+
+    fn draw_font_settings(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Font");
+            let current_name = self.chip8.get_font().name.clone();
+            egui::ComboBox::from_id_source("font")
+            .selected_text(&current_name)
+            .show_ui(ui, |ui| {
+                for font in Font::built_in() {
+                    if ui.selectable_label(current_name == font.name, &font.name).clicked() {
+                        self.chip8.set_font(font);
+                    }
+                }
+            });
+
+            if ui.button("Load font file").clicked() {
+                let file = rfd::FileDialog::new().pick_file();
+                if let Some(file) = file {
+                    match Font::load(&file) {
+                        Ok(font) => self.chip8.set_font(font),
+                        Err(e) => self.show_file_error("loading the font", e),
+                    }
+                }
+            }
+        });
+
+        let mut font_address = self.chip8.get_font_address();
+        ui.horizontal(|ui| {
+            ui.label("Font address");
+            let max_address = self.chip8.get_config().program_start.saturating_sub(FONT_SIZE as u16);
+            ui.add(egui::DragValue::new(&mut font_address)
+                .clamp_range(0..=max_address)
+                .hexadecimal(3, false, true));
+        });
+        if font_address != self.chip8.get_font_address() {
+            // The range is clamped so the font always fits before the program.
+            self.chip8.set_font_address(font_address).unwrap();
+            self.machine_config.font_address = font_address;
+        }
+    }
+
+    fn save_screenshot(&mut self, path: &std::path::Path) {
+        let result = capture::save_screenshot(path, self.chip8.get_screen(), &self.screen_palette());
+        if let Err(e) = result {
+            self.show_file_error("saving the screenshot", e);
+        }
+    }
+
+    fn start_recording(&mut self, raw_frames: bool) {
+        let result = if raw_frames {
+            ScreenRecorder::new_raw_frames(&capture::capture_path("frames", ""), self.screen_palette())
+        }
+        else {
+            ScreenRecorder::new_gif(&capture::capture_path("recording", "gif"), self.screen_palette())
+        };
+
+        match result {
+            Ok(mut recorder) => {
+                recorder.update(self.chip8.get_screen());
+                self.recorder = Some(recorder);
+            },
+            Err(e) => self.show_file_error("starting the recording", e),
+        }
+    }
+
+    fn stop_recording(&mut self) {
+        if let Some(recorder) = self.recorder.take() {
+            if let Err(e) = recorder.finish() {
+                self.show_file_error("saving the recording", e);
+            }
+        }
+    }
+
+    fn toggle_recording(&mut self, raw_frames: bool) {
+        if self.recorder.is_some() {
+            self.stop_recording();
+        }
+        else {
+            self.start_recording(raw_frames);
+        }
+    }
+
+    fn capture_menu(&mut self, ui: &mut egui::Ui) -> bool {
+        if ui.button("Save screenshot (F12)").clicked() {
+            let file = rfd::FileDialog::new()
+            .add_filter("PNG image", &["png"])
+            .save_file();
+            if let Some(file) = file {
+                self.save_screenshot(&file);
+            }
+            true
+        }
+        else if self.recorder.is_some() {
+            if ui.button("Stop recording").clicked() {
+                self.stop_recording();
+                true
+            }
+            else {
+                false
+            }
+        }
+        else if ui.button("Record GIF (F10)").clicked() {
+            self.start_recording(false);
+            true
+        }
+        else if ui.button("Record raw frames (F11)").clicked() {
+            self.start_recording(true);
+            true
+        }
+        else {
+            false
+        }
+    }
+
+    fn handle_capture_hotkeys(&mut self, ctx: &egui::Context) {
+        let (screenshot, record_gif, record_frames) = ctx.input(|i| (
+            i.key_pressed(egui::Key::F12),
+            i.key_pressed(egui::Key::F10),
+            i.key_pressed(egui::Key::F11),
+        ));
+
+        if screenshot {
+            self.save_screenshot(&capture::capture_path("screenshot", "png"));
+        }
+        if record_gif {
+            self.toggle_recording(false);
+        }
+        if record_frames {
+            self.toggle_recording(true);
+        }
+    }
+
+    fn is_running(&self) -> bool {
+        matches!(
+            self.exec_state,
+            ExecutionState::Continue
+            | ExecutionState::StepOver { .. }
+            | ExecutionState::StepOut { .. }
+            | ExecutionState::NextFrame { .. }
+            | ExecutionState::RunToCursor { .. }
+        )
+    }
+
+    /// Sends the buzzer state with the current frame, called after every instruction
+    /// or frame so the edges follow the sound timer ticks.
+    fn update_buzzer(&mut self) {
+        let playing = self.is_running() && self.chip8.can_play_sound();
+        self.buzzer.set_playing(self.chip8.get_frame_count(), playing);
+    }
+
     fn run_program(&mut self) {
-        let can_run = 
-        self.chip8.get_program_length() > 0 &&
-        self.chip8.get_program_length() + PROGRAM_START_ADDRESS - 2 >= self.chip8.get_cpu().pc as usize;
-
-        if can_run {
-            match self.chip8.run_instruction() {
-                Ok(_) => {},
-                Err(e) => {
-                    self.exec_state = ExecutionState::Halt;
-                    self.run_program_result = Some(e);
+        if self.chip8.can_run() {
+            let frame = self.chip8.get_frame_count();
+            let result = self.chip8.run_instruction();
+            self.run_script_hooks(frame);
+            self.check_breakpoint();
+            self.check_watches();
+            self.update_buzzer();
+            if let Err(e) = result {
+                self.halt(e);
+            }
+        }
+    }
+
+    /// Runs the rest of the current 60 Hz frame, calling the script hooks on the way.
+    fn run_frame(&mut self) -> Result<(), Chip8Error> {
+        let frame = self.chip8.get_frame_count();
+        while self.chip8.get_frame_count() == frame && self.exec_state != ExecutionState::Pause {
+            let result = self.chip8.run_frame();
+            self.run_script_hooks(frame);
+            self.check_breakpoint();
+            self.check_watches();
+            self.update_buzzer();
+            result?;
+        }
+        Ok(())
+    }
+
+    fn check_breakpoint(&mut self) {
+        if self.chip8.take_breakpoint_hit().is_some() {
+            self.exec_state = ExecutionState::Pause;
+            self.debug_mode = true;
+        }
+    }
+
+    fn check_watches(&mut self) {
+        if self.chip8.take_watch_hit() && self.console.check_watches(&self.chip8) {
+            self.exec_state = ExecutionState::Pause;
+            self.debug_mode = true;
+        }
+    }
+
+    /// Runs the commands of the console changing the execution state.
+    fn apply_console_requests(&mut self, frame: &mut sfml::graphics::RenderWindow) {
+        for request in self.console.take_requests() {
+            match request {
+                ConsoleRequest::Step(count) => self.step_instructions(count),
+                ConsoleRequest::Continue => self.exec_state = ExecutionState::Continue,
+                ConsoleRequest::Pause => self.exec_state = ExecutionState::Pause,
+                ConsoleRequest::Reset => {
+                    self.chip8.reload_program();
+                    self.run_program_result = None;
+                    self.exec_state = ExecutionState::Pause;
+                    self.console.print(String::from("Reset"));
+                },
+                ConsoleRequest::Load(file) => {
+                    let name = file.display().to_string();
+                    match self.load_rom(file, frame) {
+                        Ok(()) => self.console.print(format!("Loaded {}", name)),
+                        Err(e) => self.console.print_error(format!("Could not load {}: {}", name, e)),
+                    }
+                },
+            }
+        }
+    }
+
+    /// Executes `count` instructions then pauses, breakpoints, watches and errors stop
+    /// earlier.
+    fn step_instructions(&mut self, count: u32) {
+        let mut executed = 0;
+        while executed < count && self.chip8.can_run() {
+            self.exec_state = ExecutionState::RunNext;
+            self.run_program();
+            executed += 1;
+            if self.exec_state != ExecutionState::RunNext {
+                break;
+            }
+        }
+
+        let pc = self.chip8.get_cpu().pc;
+        let instruction = self.next_instruction().map(|instruction| instruction.to_string()).unwrap_or_default();
+        let location = format!("{} {}", self.disassembly.symbols.describe(pc), instruction);
+        match &self.run_program_result {
+            Some(e) if self.exec_state == ExecutionState::Halt => {
+                let message = format!("Halted after {} instructions: {}", executed, e);
+                self.console.print_error(message);
+            },
+            _ => {
+                self.console.print(format!("Executed {} instructions, at {}", executed, location));
+                self.exec_state = ExecutionState::Pause;
+            },
+        }
+    }
+
+    /// Accepts the GDB and DAP clients and applies their commands.
+    fn poll_remote_servers(&mut self, frame: &mut sfml::graphics::RenderWindow) {
+        let mut commands = Vec::new();
+        if let Some(server) = &mut self.gdb_server {
+            commands.extend(server.poll(&mut self.chip8));
+        }
+        if let Some(server) = &mut self.dap_server {
+            commands.extend(server.poll(&mut self.chip8, &self.disassembly.symbols));
+        }
+
+        for command in commands {
+            match command {
+                RemoteCommand::Continue => self.exec_state = ExecutionState::Continue,
+                RemoteCommand::Step => self.exec_state = ExecutionState::RunNext,
+                RemoteCommand::StepOver => self.step_over(),
+                RemoteCommand::StepOut => {
+                    let depth = self.chip8.get_cpu().stack.sp;
+                    self.exec_state = if depth > 0 { ExecutionState::StepOut { depth } } else { ExecutionState::RunNext };
+                },
+                RemoteCommand::Pause => {
+                    self.exec_state = ExecutionState::Pause;
                     self.debug_mode = true;
+                },
+                RemoteCommand::RomLoaded(file) => self.rom_loaded(file, frame),
+            }
+        }
+    }
+
+    /// Tells the remote debuggers the emulator stopped when they wait for it, and the
+    /// DAP client when the emulator was resumed from the UI.
+    fn report_remote_state(&mut self) {
+        let reason = match self.exec_state {
+            ExecutionState::Halt => StopReason::Error,
+            ExecutionState::Pause => StopReason::Trap,
+            _ => {
+                if let Some(server) = &mut self.dap_server {
+                    server.report_continued();
+                }
+                return;
+            },
+        };
+
+        if let Some(server) = &mut self.gdb_server {
+            server.report_stop(reason);
+        }
+        if let Some(server) = &mut self.dap_server {
+            let error = match reason {
+                StopReason::Error => self.run_program_result.as_ref().map(|e| e.to_string()),
+                _ => None,
+            };
+            server.report_stop(reason, &self.chip8, error.as_deref());
+        }
+    }
+
+    /// Listens for editors on `port`, errors are shown in a window.
+    pub fn start_dap_server(&mut self, port: u16) {
+        self.dap_port = port;
+        match DapServer::bind(port) {
+            Ok(server) => self.dap_server = Some(server),
+            Err(e) => self.show_file_error("starting the DAP server", e),
+        }
+    }
+
+    fn draw_gdb_settings(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Port");
+            ui.add_enabled(self.gdb_server.is_none(), egui::DragValue::new(&mut self.gdb_port));
+
+            if self.gdb_server.is_some() {
+                if ui.button("Stop").clicked() {
+                    self.gdb_server = None;
+                }
+            }
+            else if ui.button("Listen").clicked() {
+                match GdbServer::bind(self.gdb_port) {
+                    Ok(server) => self.gdb_server = Some(server),
+                    Err(e) => self.show_file_error("starting the GDB server", e),
                 }
             }
+        });
+
+        match &self.gdb_server {
+            Some(server) => ui.label(server_status(server.is_connected(), server.local_addr())),
+            None => ui.label("Stopped"),
+        };
+    }
+
+    fn draw_dap_settings(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Port");
+            ui.add_enabled(self.dap_server.is_none(), egui::DragValue::new(&mut self.dap_port));
+
+            if self.dap_server.is_some() {
+                if ui.button("Stop").clicked() {
+                    self.dap_server = None;
+                }
+            }
+            else if ui.button("Listen").clicked() {
+                self.start_dap_server(self.dap_port);
+            }
+        });
+
+        match &self.dap_server {
+            Some(server) => ui.label(server_status(server.is_connected(), server.local_addr())),
+            None => ui.label("Stopped"),
+        };
+    }
+
+    /// Calls the hook of the address the PC reached and the frame callback when `frame`
+    /// is over.
+    fn run_script_hooks(&mut self, frame: u64) {
+        if let Some(pc) = self.chip8.take_pc_hook() {
+            self.scripts.on_pc(&mut self.chip8, pc);
+        }
+        if self.chip8.get_frame_count() != frame {
+            self.scripts.on_frame(&mut self.chip8);
+        }
+
+        for path in self.scripts.take_screenshots() {
+            self.save_screenshot(&path);
+        }
+        if self.scripts.take_pause_request() {
+            self.exec_state = ExecutionState::Pause;
+            self.debug_mode = true;
+        }
+    }
+
+    /// Runs the whole 60 Hz frames elapsed during `delta_time`, used when the machine
+    /// paces itself with COSMAC VIP machine cycles.
+    fn run_frames(&mut self, delta_time: f64) {
+        let frames = delta_time * 60.0;
+        self.pending_frames = (self.pending_frames + frames).min(MAX_PENDING_FRAMES.max(frames));
+        while self.pending_frames >= 1.0 && self.exec_state == ExecutionState::Continue {
+            self.pending_frames -= 1.0;
+            if let Err(e) = self.run_frame() {
+                self.halt(e);
+                break;
+            }
+        }
+    }
+
+    /// Runs whole frames until `UNCAPPED_TIME_BUDGET` is spent.
+    fn run_uncapped(&mut self) {
+        let timer = Timer::new();
+        while timer.elapsed().as_secs_f64() < UNCAPPED_TIME_BUDGET && self.exec_state == ExecutionState::Continue {
+            if let Err(e) = self.run_frame() {
+                self.halt(e);
+                break;
+            }
+        }
+        self.pending_frames = 0.0;
+    }
+
+    /// Runs the program for `delta_time` seconds of real time, scaled by the current
+    /// speed. The timers are emulated, so they follow the same speed as the CPU.
+    fn run_continue(&mut self, delta_time: f64) {
+        let frame_count = self.chip8.get_frame_count();
+        let emulated_time = delta_time * self.speed();
+
+        if self.turbo && self.turbo_speed == TurboSpeed::Uncapped {
+            self.run_uncapped();
+        }
+        else if self.chip8.get_timing_mode() == TimingMode::CosmacVip {
+            self.run_frames(emulated_time);
+        }
+        else {
+            let nb_cycles = (self.game_freq * emulated_time).round() as u32;
+            for _ in 0..nb_cycles {
+                self.run_program();
+                if self.exec_state != ExecutionState::Continue {
+                    break;
+                }
+            }
+        }
+
+        if delta_time > 0.0 {
+            let frames = (self.chip8.get_frame_count() - frame_count) as f64;
+            let speed = frames / (delta_time * 60.0);
+            self.measured_speed += (speed - self.measured_speed) * 0.1;
         }
     }
 
+    /// Speed factor applied to the emulated time, uncapped fast forward excepted.
+    fn speed(&self) -> f64 {
+        match self.turbo_speed {
+            TurboSpeed::Multiplier(multiplier) if self.turbo => multiplier,
+            _ => self.slow_motion,
+        }
+    }
+
+    fn handle_speed_hotkeys(&mut self, ctx: &egui::Context) {
+        let (turbo, next_slow_motion) = ctx.input(|i| (
+            i.key_down(TURBO_KEY),
+            i.key_pressed(SLOW_MOTION_KEY),
+        ));
+
+        self.turbo = turbo;
+        if next_slow_motion {
+            let index = SLOW_MOTION_SPEEDS.iter()
+            .position(|speed| *speed == self.slow_motion)
+            .unwrap_or(0);
+            self.slow_motion = SLOW_MOTION_SPEEDS[(index + 1) % SLOW_MOTION_SPEEDS.len()];
+        }
+    }
+
+    fn speed_menu(&mut self, ui: &mut egui::Ui) {
+        ui.label("Slow motion (F9)");
+        for speed in SLOW_MOTION_SPEEDS {
+            ui.radio_value(&mut self.slow_motion, speed, format!("x{}", speed));
+        }
+        ui.separator();
+        ui.label("Fast forward (hold Tab)");
+        for multiplier in TURBO_MULTIPLIERS {
+            let turbo_speed = TurboSpeed::Multiplier(multiplier);
+            ui.radio_value(&mut self.turbo_speed, turbo_speed, turbo_speed.name());
+        }
+        ui.radio_value(&mut self.turbo_speed, TurboSpeed::Uncapped, TurboSpeed::Uncapped.name());
+    }
+
+    /// Shows the speed in the corner of the screen when it is not the normal one.
+    fn draw_speed_indicator(&self, ui: &egui::Ui, screen_rect: egui::Rect) {
+        let text = if self.turbo {
+            match self.turbo_speed {
+                TurboSpeed::Multiplier(multiplier) => format!(">> x{}", multiplier),
+                TurboSpeed::Uncapped => format!(">> x{:.1}", self.measured_speed),
+            }
+        }
+        else if self.slow_motion != 1.0 {
+            format!("> x{}", self.slow_motion)
+        }
+        else {
+            return;
+        };
+
+        let running = self.exec_state == ExecutionState::Continue;
+        let color = if running { egui::Color32::YELLOW } else { egui::Color32::GRAY };
+        ui.painter().text(
+            screen_rect.right_top() + egui::vec2(-8.0, 8.0),
+            egui::Align2::RIGHT_TOP,
+            text,
+            egui::FontId::monospace(18.0),
+            color
+        );
+    }
+
+    fn halt(&mut self, error: Chip8Error) {
+        self.exec_state = ExecutionState::Halt;
+        self.run_program_result = Some(error);
+        self.debug_mode = true;
+    }
+
     fn draw_screen(&mut self, ui: &mut egui::Ui) {
 
+        if let Some(recorder) = &mut self.recorder {
+            if self.chip8.get_screen().updated {
+                recorder.update(self.chip8.get_screen());
+            }
+        }
         self.chip8.update_framebuffer(&mut self.framebuffer);
+        if self.framebuffer.is_fading() {
+            self.framebuffer.update(self.chip8.get_screen());
+        }
 
         let width = ui.available_width();
+        let height = if self.debug_mode {
+            let aspect_ratio = SCREEN_WIDTH as f32 / SCREEN_HEIGHT as f32;
+            width / aspect_ratio
+        }
+        else {
+            ui.available_height()
+        };
 
-        let tex_size = self.framebuffer.texture.size_vec2();
-        let aspect_ratio = tex_size.x / tex_size.y;
-        let height = width / aspect_ratio;
-
-        ui.image(&self.framebuffer.texture, egui::vec2(width, height));
+        let screen_rect = self.framebuffer.draw(ui, egui::vec2(width, height));
+        if self.sprite_viewer.open {
+            self.sprite_viewer.draw_highlight(ui, &self.chip8, screen_rect);
+        }
+        self.draw_speed_indicator(ui, screen_rect);
     }
 
     fn handle_input(&mut self, ctx: &egui::Context) {
@@ -338,6 +1105,71 @@ impl MainApp {
         });
     }
 
+    fn next_instruction(&self) -> Option<Instruction> {
+        self.chip8.get_ram().read16(self.chip8.get_cpu().pc)
+        .ok()
+        .map(Instruction::new)
+    }
+
+    fn step_over(&mut self) {
+        let is_call = self.next_instruction()
+        .is_some_and(|instruction| instruction.extract(0xF000, 12) == 0x2);
+        let cpu = self.chip8.get_cpu();
+
+        self.exec_state = if is_call {
+            ExecutionState::StepOver { return_address: cpu.pc.wrapping_add(2), depth: cpu.stack.sp }
+        }
+        else {
+            ExecutionState::RunNext
+        };
+    }
+
+    fn run_until<F>(&mut self, nb_cycles: u32, stop: F)
+    where
+        F: Fn(&CPU) -> bool
+    {
+        for _ in 0..nb_cycles {
+            if !self.chip8.can_run() {
+                self.exec_state = ExecutionState::Pause;
+                break;
+            }
+
+            self.run_program();
+            if matches!(self.exec_state, ExecutionState::Halt | ExecutionState::Pause) {
+                break;
+            }
+            if stop(self.chip8.get_cpu()) {
+                self.exec_state = ExecutionState::Pause;
+                break;
+            }
+        }
+    }
+
+    /// Runs until the 60 Hz frame `frame` is over or the screen is drawn or cleared.
+    fn run_next_frame(&mut self, nb_cycles: u32, frame: u64) {
+        for _ in 0..nb_cycles {
+            if !self.chip8.can_run() {
+                self.exec_state = ExecutionState::Pause;
+                break;
+            }
+
+            let draws = self.next_instruction()
+            .is_some_and(|instruction| {
+                instruction.extract(0xF000, 12) == 0xD || instruction.opcode() == 0x00E0
+            });
+            let waiting_for_vblank = self.chip8.get_cpu().is_waiting_for_vblank();
+
+            self.run_program();
+            if matches!(self.exec_state, ExecutionState::Halt | ExecutionState::Pause) {
+                break;
+            }
+            if (draws && !waiting_for_vblank) || self.chip8.get_frame_count() != frame {
+                self.exec_state = ExecutionState::Pause;
+                break;
+            }
+        }
+    }
+
     fn draw_debug_control(&mut self, ui: &mut egui::Ui) {
         match self.exec_state {
             ExecutionState::Pause => {
@@ -345,11 +1177,37 @@ impl MainApp {
                     self.exec_state = ExecutionState::RunNext;
                 }
 
+                if ui.button("Step over").clicked() {
+                    self.step_over();
+                }
+
+                let in_subroutine = self.chip8.get_cpu().stack.sp > 0;
+                if ui.add_enabled(in_subroutine, egui::Button::new("Step out")).clicked() {
+                    self.exec_state = ExecutionState::StepOut { depth: self.chip8.get_cpu().stack.sp };
+                }
+
+                if ui.button("Next frame").clicked() {
+                    self.exec_state = ExecutionState::NextFrame { frame: self.chip8.get_frame_count() };
+                }
+
+                let cursor = self.disassembly.cursor;
+                let run_to_cursor = ui.add_enabled(cursor.is_some(), egui::Button::new("Run to cursor"))
+                .on_hover_text("Select a line in the program panel");
+                if run_to_cursor.clicked() {
+                    if let Some(address) = cursor {
+                        self.exec_state = ExecutionState::RunToCursor { address };
+                    }
+                }
+
                 if ui.button("Continue").clicked() {
                     self.exec_state = ExecutionState::Continue;
                 }
             },
-            ExecutionState::Continue => {
+            ExecutionState::Continue
+            | ExecutionState::StepOver { .. }
+            | ExecutionState::StepOut { .. }
+            | ExecutionState::NextFrame { .. }
+            | ExecutionState::RunToCursor { .. } => {
                 if ui.button("Pause").clicked() {
                     self.exec_state = ExecutionState::Pause;
                 }
@@ -370,25 +1228,50 @@ impl super::App for MainApp {
     fn update(&mut self, ctx: &egui::Context, frame: &mut sfml::graphics::RenderWindow) {
         let delta_time = self.update_timer.restart().as_secs_f64();
 
-        let nb_cycles = (self.game_freq * delta_time).round() as u32;
-
-        self.handle_input(ctx);
-        if self.chip8.can_play_sound() {
-            self.buzzer.play();
+        // Keys typed in the consoles and the text fields are not meant for the game,
+        // the keys held when one of them takes the focus are released.
+        let typing = ctx.wants_keyboard_input();
+        if typing && !self.typing {
+            self.chip8.get_keypad_mut().reset();
+            self.turbo = false;
         }
+        self.typing = typing;
+        if !typing {
+            self.handle_input(ctx);
+            self.handle_capture_hotkeys(ctx);
+            self.handle_speed_hotkeys(ctx);
+        }
+        self.poll_remote_servers(frame);
+
+        let nb_cycles = (self.game_freq * delta_time * self.speed()).round() as u32;
         match self.exec_state {
             ExecutionState::Continue => {
-                for _ in 0..nb_cycles{
-                    self.run_program();
-                }
+                self.run_continue(delta_time);
             },
             ExecutionState::RunNext => {
                 self.run_program();
-                self.exec_state = ExecutionState::Pause;
+                if self.exec_state == ExecutionState::RunNext {
+                    self.exec_state = ExecutionState::Pause;
+                }
+            },
+            ExecutionState::StepOver { return_address, depth } => {
+                self.run_until(nb_cycles, |cpu| cpu.pc == return_address && cpu.stack.sp == depth);
+            },
+            ExecutionState::StepOut { depth } => {
+                self.run_until(nb_cycles, |cpu| cpu.stack.sp < depth);
+            },
+            ExecutionState::NextFrame { frame } => {
+                self.run_next_frame(nb_cycles, frame);
+            },
+            ExecutionState::RunToCursor { address } => {
+                self.run_until(nb_cycles, |cpu| cpu.pc == address);
             },
             _ => {},
 
         }
+        self.report_remote_state();
+
+        self.update_buzzer();
 
         // Rendering
         let mut close_exec_error_window = false;
@@ -398,7 +1281,9 @@ impl super::App for MainApp {
             .anchor(egui::Align2::CENTER_CENTER, (0.0, -5.0))
             .show(ctx, |ui| {
                 ui.vertical_centered(|ui| {
-                    ui.label(format!("{}", e));
+                    ui.label(format!("{}", e.kind));
+                    ui.separator();
+                    draw_error_context(ui, e);
                     if ui.button("Ok").clicked() {
                         close_exec_error_window = true;
                     }
@@ -422,6 +1307,21 @@ impl super::App for MainApp {
                         ui.close_menu();
                     }
                 });
+                ui.menu_button("Capture", |ui| {
+                    if self.capture_menu(ui) {
+                        ui.close_menu();
+                    }
+                });
+                ui.menu_button("Sound", |ui| {
+                    let mut muted = self.buzzer.is_muted();
+                    if ui.checkbox(&mut muted, "Mute").changed() {
+                        self.buzzer.set_muted(muted);
+                        ui.close_menu();
+                    }
+                });
+                ui.menu_button("Speed", |ui| {
+                    self.speed_menu(ui);
+                });
                 ui.menu_button("Mode", |ui| {
                     if ui.button("Debug mode").clicked() {
                         self.debug_mode = true;
@@ -432,6 +1332,23 @@ impl super::App for MainApp {
                         self.exec_state = ExecutionState::Continue;
                         ui.close_menu();
                     };
+                    ui.separator();
+                    if ui.button("Sprite viewer").clicked() {
+                        self.sprite_viewer.open = true;
+                        ui.close_menu();
+                    }
+                    if ui.button("Profiler").clicked() {
+                        self.profiler_view.open = true;
+                        ui.close_menu();
+                    }
+                    if ui.button("Memory and coverage").clicked() {
+                        self.hex_viewer.open = true;
+                        ui.close_menu();
+                    }
+                    if ui.button("Scripts").clicked() {
+                        self.scripts.open = true;
+                        ui.close_menu();
+                    }
                 });
             });
         });
@@ -447,6 +1364,16 @@ impl super::App for MainApp {
             });
         }
 
+        if self.debug_mode {
+            egui::TopBottomPanel::bottom("console")
+            .resizable(true)
+            .default_height(160.0)
+            .show(ctx, |ui| {
+                self.console.draw(ui, &mut self.chip8, &self.disassembly.symbols);
+            });
+            self.apply_console_requests(frame);
+        }
+
         egui::CentralPanel::default()
         .show(ctx, |ui| {
             self.draw_screen(ui);
@@ -468,37 +1395,180 @@ impl super::App for MainApp {
         if self.show_settings_window {
             show_settings_window(ctx, self);
         }
+        if self.sprite_viewer.open {
+            self.sprite_viewer.show(ctx, &self.chip8);
+        }
+        if self.profiler_view.open {
+            if let Some((action, e)) = self.profiler_view.show(ctx, &mut self.chip8, &self.disassembly.symbols) {
+                self.show_file_error(action, e);
+            }
+        }
+        if self.hex_viewer.open {
+            if let Some((action, e)) = self.hex_viewer.show(ctx, &mut self.chip8, &self.disassembly.symbols, self.rom_path.as_deref()) {
+                self.show_file_error(action, e);
+            }
+        }
+        if self.scripts.open {
+            if let Some((action, e)) = self.scripts.show(ctx, &mut self.chip8) {
+                self.show_file_error(action, e);
+            }
+        }
+        if let Some(file_error_window) = &self.file_error_window {
+            if show_file_error_window(ctx, file_error_window) {
+                self.file_error_window = None;
+            }
+        }
 
         
     }
 }
 
+fn server_status(connected: bool, address: std::io::Result<std::net::SocketAddr>) -> String {
+    match (connected, address) {
+        (true, _) => String::from("Debugger attached"),
+        (false, Ok(address)) => format!("Listening on {}", address),
+        (false, Err(_)) => String::from("Listening"),
+    }
+}
+
+fn timing_mode_name(timing_mode: TimingMode) -> &'static str {
+    match timing_mode {
+        TimingMode::Instructions => "Fixed frequency",
+        TimingMode::CosmacVip => "COSMAC VIP cycles",
+    }
+}
+
 fn show_settings_window(ctx: &egui::Context, app: &mut MainApp) {
     egui::Window::new("Settings")
     .show(ctx, |ui| {
-        let drag_value = egui::DragValue::new(&mut app.game_freq)
-        .clamp_range(100.0..=1000.0);
+        let mut timing_mode = app.chip8.get_timing_mode();
         ui.horizontal(|ui| {
-            ui.label("CPU frequency");
-            ui.add(drag_value);
-            ui.label("Hz");
+            ui.label("Timing");
+            egui::ComboBox::from_id_source("timing_mode")
+            .selected_text(timing_mode_name(timing_mode))
+            .show_ui(ui, |ui| {
+                for mode in [TimingMode::Instructions, TimingMode::CosmacVip] {
+                    ui.selectable_value(&mut timing_mode, mode, timing_mode_name(mode));
+                }
+            });
         });
+        if timing_mode != app.chip8.get_timing_mode() {
+            app.chip8.set_timing_mode(timing_mode);
+            app.pending_frames = 0.0;
+        }
+
+        let drag_value = egui::DragValue::new(&mut app.game_freq)
+        .clamp_range(100.0..=1000.0);
+        let fixed_frequency = timing_mode == TimingMode::Instructions;
+        let freq_changed = ui.add_enabled_ui(fixed_frequency, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("CPU frequency");
+                let changed = ui.add(drag_value).changed();
+                ui.label("Hz");
+                changed
+            }).inner
+        }).inner;
+        if freq_changed {
+            app.chip8.set_cpu_frequency(app.game_freq);
+        }
         ui.group(|ui| {
             ui.vertical_centered(|ui| {
                 ui.heading("Screen colors");
             });
             ui.separator();
+            if app.framebuffer.palette.draw_editor(ui) {
+                app.framebuffer.update(app.chip8.get_screen());
+            }
+            app.draw_palette_files(ui);
+            ui.separator();
             ui.horizontal(|ui| {
-                ui.label("Background color");
-                if ui.color_edit_button_srgb(&mut app.framebuffer.background_color).changed() {
-                    app.framebuffer.update(app.chip8.get_screen());
-                }
-    
-                ui.label("Foreground color");
-                if ui.color_edit_button_srgb(&mut app.framebuffer.foreground_color).changed() {
+                ui.label("Border color");
+                ui.color_edit_button_srgb(&mut app.framebuffer.border_color);
+
+                ui.label("Scaling");
+                egui::ComboBox::from_id_source("scaling_mode")
+                .selected_text(format!("{:?}", app.framebuffer.scaling_mode))
+                .show_ui(ui, |ui| {
+                    for mode in [ScalingMode::Stretch, ScalingMode::AspectFit, ScalingMode::Integer] {
+                        ui.selectable_value(&mut app.framebuffer.scaling_mode, mode, format!("{:?}", mode));
+                    }
+                });
+            });
+            ui.horizontal(|ui| {
+                let mut changed = ui.checkbox(&mut app.framebuffer.pixel_grid, "Pixel grid").changed();
+                changed |= ui.checkbox(&mut app.framebuffer.scanlines, "Scanlines").changed();
+                changed |= ui.checkbox(&mut app.framebuffer.bloom, "Bloom").changed();
+                if changed {
                     app.framebuffer.update(app.chip8.get_screen());
                 }
             });
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut app.framebuffer.ghosting, "Phosphor persistence");
+                ui.add_enabled(
+                    app.framebuffer.ghosting,
+                    egui::Slider::new(&mut app.framebuffer.decay_rate, 0.05..=0.95).text("Decay rate")
+                );
+            });
+        });
+        ui.group(|ui| {
+            ui.vertical_centered(|ui| {
+                ui.heading("Machine");
+            });
+            ui.separator();
+            app.draw_machine_settings(ui);
+        });
+        ui.group(|ui| {
+            ui.vertical_centered(|ui| {
+                ui.heading("Font");
+            });
+            ui.separator();
+            app.draw_font_settings(ui);
+        });
+        ui.group(|ui| {
+            ui.vertical_centered(|ui| {
+                ui.heading("Quirks");
+            });
+            ui.separator();
+            let mut quirks = *app.chip8.get_quirks();
+            let mut changed = false;
+            changed |= ui.checkbox(&mut quirks.cosmac_minimum_beep, "COSMAC minimum beep (sound timer >= 2)").changed();
+            changed |= ui.checkbox(&mut quirks.fx0a_wait_release, "FX0A waits for the key release").changed();
+            changed |= ui.checkbox(&mut quirks.display_wait, "Display wait (DXYN waits for the vertical blank)").changed();
+            if changed {
+                app.chip8.set_quirks(quirks);
+            }
+        });
+        ui.group(|ui| {
+            ui.vertical_centered(|ui| {
+                ui.heading("Error handling");
+            });
+            ui.separator();
+            let mut policies = *app.chip8.get_error_policies();
+            let mut changed = false;
+            egui::Grid::new("error_policies")
+            .show(ui, |ui| {
+                changed |= error_policy_combo(ui, "Memory access", "Wrap addresses", &mut policies.memory);
+                changed |= error_policy_combo(ui, "Stack", "Wrap stack pointer", &mut policies.stack);
+                changed |= error_policy_combo(ui, "Unknown instruction", "Treat as NOP", &mut policies.unknown_instruction);
+                changed |= error_policy_combo(ui, "Invalid key", "Use low nibble", &mut policies.invalid_key);
+            });
+            if changed {
+                app.chip8.set_error_policies(policies);
+            }
+        });
+        ui.group(|ui| {
+            ui.vertical_centered(|ui| {
+                ui.heading("GDB server");
+            });
+            ui.separator();
+            app.draw_gdb_settings(ui);
+        });
+        ui.group(|ui| {
+            ui.vertical_centered(|ui| {
+                ui.heading("DAP server");
+            });
+            ui.separator();
+            app.draw_dap_settings(ui);
         });
         ui.group(|ui| {
             ui.vertical_centered(|ui| {
@@ -536,5 +1606,24 @@ fn show_game_loading_error_window(ctx: &egui::Context, game_loading_window_error
         });
     });
 
+    close
+}
+
+fn show_file_error_window(ctx: &egui::Context, file_error_window: &FileErrorWindow) -> bool {
+    let mut close = false;
+    egui::Window::new("File error")
+    .resizable(false)
+    .collapsible(false)
+    .anchor(egui::Align2::CENTER_CENTER, (0.0, 0.0))
+    .show(ctx, |ui| {
+        let FileErrorWindow { error, action } = file_error_window;
+        ui.label(format!("Error while {}: {}", action, error));
+        ui.vertical_centered(|ui| {
+            if ui.button("Ok").clicked() {
+                close = true;
+            }
+        });
+    });
+
     close
 }
\ No newline at end of file
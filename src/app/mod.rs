@@ -10,6 +10,15 @@ use egui_sfml::SfEgui;
 
 pub mod main_app;
 pub mod audio;
+pub mod framebuffer;
+pub mod capture;
+pub mod palette;
+pub mod disassembly;
+pub mod sprite_viewer;
+pub mod profiler_view;
+pub mod hex_viewer;
+pub mod scripting;
+pub mod console;
 
 pub struct AppSpec {
     pub title: String,
@@ -0,0 +1,199 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+pub const MAX_PALETTE_COLORS: usize = 16;
+const MIN_PALETTE_COLORS: usize = 2;
+
+pub const PALETTE_EXTENSION: &str = "pal";
+
+/// Screen colors, indexed by the value of a pixel: 0 is the background and 1 the
+/// foreground. Platforms with several bit planes use the following entries.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Palette {
+    pub name: String,
+    pub colors: Vec<[u8; 3]>,
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self::new("Classic", &[[0x00, 0x00, 0x00], [0xFF, 0xFF, 0xFF]])
+    }
+}
+
+impl Palette {
+    pub fn new(name: &str, colors: &[[u8; 3]]) -> Self {
+        Self {
+            name: String::from(name),
+            colors: colors.to_vec(),
+        }
+    }
+
+    pub fn themes() -> Vec<Palette> {
+        vec![
+            Palette::default(),
+            Palette::new("Green phosphor", &[[0x0A, 0x14, 0x0A], [0x33, 0xFF, 0x66]]),
+            Palette::new("Amber", &[[0x1A, 0x0F, 0x00], [0xFF, 0xB0, 0x00]]),
+            Palette::new("LCD", &[[0x9B, 0xBC, 0x0F], [0x0F, 0x38, 0x0F], [0x30, 0x62, 0x30], [0x8B, 0xAC, 0x0F]]),
+            Palette::new("Octo", &[[0x99, 0x66, 0x00], [0xFF, 0xCC, 0x00], [0xFF, 0x66, 0x00], [0x66, 0x22, 0x00]]),
+        ]
+    }
+
+    pub fn background(&self) -> [u8; 3] {
+        self.colors[0]
+    }
+
+    pub fn foreground(&self) -> [u8; 3] {
+        self.colors[1]
+    }
+
+    /// Palette file stored next to a ROM, loaded with the ROM when it exists.
+    pub fn rom_palette_path(rom: &Path) -> PathBuf {
+        rom.with_extension(PALETTE_EXTENSION)
+    }
+
+    /// Reads a palette file: an optional `name = ...` line followed by one `#RRGGBB`
+    /// color per line. Lines starting with `;` are comments.
+    pub fn load(path: &Path) -> io::Result<Palette> {
+        let content = fs::read_to_string(path)?;
+        let name = path.file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+        Self::parse(&content, name)
+    }
+
+    /// Reads the content of a palette file, `name` is used when it has no name line.
+    fn parse(content: &str, mut name: String) -> io::Result<Palette> {
+        let invalid_data = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+        let mut colors = Vec::new();
+
+        for line in content.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with(';') {
+                continue;
+            }
+            if let Some(value) = line.strip_prefix("name") {
+                name = value.trim_start().trim_start_matches('=').trim().to_string();
+                continue;
+            }
+
+            let color = parse_color(line)
+            .ok_or_else(|| invalid_data(format!("invalid color '{}'", line)))?;
+            colors.push(color);
+        }
+
+        if colors.len() < MIN_PALETTE_COLORS || colors.len() > MAX_PALETTE_COLORS {
+            return Err(invalid_data(format!(
+                "a palette has between {} and {} colors, found {}",
+                MIN_PALETTE_COLORS, MAX_PALETTE_COLORS, colors.len()
+            )));
+        }
+
+        Ok(Palette { name, colors })
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, self.to_file_content())
+    }
+
+    fn to_file_content(&self) -> String {
+        let mut content = format!("name = {}\n", self.name);
+        for [r, g, b] in self.colors.iter() {
+            content += &format!("#{:02X}{:02X}{:02X}\n", r, g, b);
+        }
+        content
+    }
+
+    /// Draws the palette editor, returns true when a color changed.
+    pub fn draw_editor(&mut self, ui: &mut egui::Ui) -> bool {
+        let mut changed = false;
+
+        ui.horizontal(|ui| {
+            ui.label("Theme");
+            egui::ComboBox::from_id_source("palette_theme")
+            .selected_text(&self.name)
+            .show_ui(ui, |ui| {
+                for theme in Palette::themes() {
+                    if ui.selectable_label(self.name == theme.name, &theme.name).clicked() {
+                        *self = theme;
+                        changed = true;
+                    }
+                }
+            });
+        });
+
+        egui::Grid::new("palette_colors")
+        .show(ui, |ui| {
+            for (index, color) in self.colors.iter_mut().enumerate() {
+                let label = match index {
+                    0 => String::from("0 (background)"),
+                    1 => String::from("1 (foreground)"),
+                    _ => format!("{}", index),
+                };
+                ui.label(label);
+                changed |= ui.color_edit_button_srgb(color).changed();
+                if index % 4 == 3 {
+                    ui.end_row();
+                }
+            }
+        });
+
+        ui.horizontal(|ui| {
+            let can_add = self.colors.len() < MAX_PALETTE_COLORS;
+            if ui.add_enabled(can_add, egui::Button::new("Add color")).clicked() {
+                self.colors.push(self.foreground());
+            }
+
+            let can_remove = self.colors.len() > MIN_PALETTE_COLORS;
+            if ui.add_enabled(can_remove, egui::Button::new("Remove color")).clicked() {
+                self.colors.pop();
+            }
+        });
+
+        changed
+    }
+}
+
+fn parse_color(text: &str) -> Option<[u8; 3]> {
+    let hex = text.strip_prefix('#').unwrap_or(text);
+    if hex.len() != 6 {
+        return None;
+    }
+
+    let value = u32::from_str_radix(hex, 16).ok()?;
+    Some([(value >> 16) as u8, (value >> 8) as u8, value as u8])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_reads_the_name_and_the_colors() {
+        let content = "; Game Boy\nname = LCD\n#9BBC0F\n\n0F380F\n";
+        let palette = Palette::parse(content, String::from("file")).unwrap();
+
+        assert_eq!(palette.name, "LCD");
+        assert_eq!(palette.colors, vec![[0x9B, 0xBC, 0x0F], [0x0F, 0x38, 0x0F]]);
+    }
+
+    #[test]
+    fn parse_uses_the_file_name_without_a_name_line() {
+        let palette = Palette::parse("#000000\n#FFFFFF\n", String::from("file")).unwrap();
+        assert_eq!(palette.name, "file");
+    }
+
+    #[test]
+    fn parse_rejects_invalid_colors_and_counts() {
+        assert!(Palette::parse("#000000\n#FFFFF\n", String::new()).is_err());
+        assert!(Palette::parse("#000000\n", String::new()).is_err());
+        assert!(Palette::parse(&"#000000\n".repeat(MAX_PALETTE_COLORS + 1), String::new()).is_err());
+    }
+
+    #[test]
+    fn saved_palettes_parse_back() {
+        for palette in Palette::themes() {
+            assert_eq!(Palette::parse(&palette.to_file_content(), String::new()).unwrap(), palette);
+        }
+    }
+}
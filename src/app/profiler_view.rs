@@ -0,0 +1,163 @@
+use std::io;
+
+use super::capture::export_file;
+use crate::chip8_core::chip8::Chip8;
+use crate::chip8_core::instruction::Instruction;
+use crate::chip8_core::profiler::Profiler;
+use crate::chip8_core::symbols::SymbolTable;
+
+const DEFAULT_TOP_COUNT: usize = 10;
+
+/// Window showing the profile recorded by `Profiler`.
+pub struct ProfilerView {
+    pub open: bool,
+    /// Colors the disassembly rows by their number of executions.
+    pub show_heatmap: bool,
+
+    top_count: usize,
+}
+
+impl ProfilerView {
+    pub fn new() -> Self {
+        Self {
+            open: false,
+            show_heatmap: true,
+            top_count: DEFAULT_TOP_COUNT,
+        }
+    }
+
+    /// Returns the action and the error when an export failed.
+    pub fn show(&mut self, ctx: &egui::Context, chip8: &mut Chip8, symbols: &SymbolTable) -> Option<(&'static str, io::Error)> {
+        let mut open = self.open;
+        let mut error = None;
+
+        egui::Window::new("Profiler")
+        .open(&mut open)
+        .show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                let mut recording = chip8.get_profiler().enabled;
+                if ui.checkbox(&mut recording, "Record").changed() {
+                    chip8.set_profiling(recording);
+                }
+                if ui.button("Clear").clicked() {
+                    chip8.clear_profile();
+                }
+                ui.checkbox(&mut self.show_heatmap, "Heatmap in the program panel");
+            });
+            ui.horizontal(|ui| {
+                ui.label("Top");
+                ui.add(egui::DragValue::new(&mut self.top_count).clamp_range(1..=100));
+            });
+            ui.horizontal(|ui| {
+                if ui.button("Export CSV").clicked() {
+                    let label = |address| symbols.label(address).unwrap_or_default().to_string();
+                    error = export_file("Profile", "csv", "profile.csv", "exporting the profile", |writer| {
+                        chip8.get_profiler().write_csv(writer, chip8.get_ram(), label)
+                    });
+                }
+                if ui.button("Export folded stacks").clicked() {
+                    error = export_file("Profile", "folded", "profile.folded", "exporting the folded stacks", |writer| {
+                        chip8.get_profiler().write_folded(writer, chip8.get_ram(), |address| symbols.describe(address))
+                    });
+                }
+            });
+            ui.separator();
+
+            let profiler = chip8.get_profiler();
+            let total = profiler.total_executions();
+            ui.label(format!("{} instructions executed", total));
+            if total == 0 {
+                return;
+            }
+
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                ui.collapsing("Opcode classes", |ui| {
+                    draw_classes(ui, profiler);
+                });
+                ui.collapsing("Hot addresses", |ui| {
+                    draw_hot_addresses(ui, chip8, symbols, self.top_count);
+                });
+                ui.collapsing("Hot loops", |ui| {
+                    draw_hot_loops(ui, profiler, symbols, self.top_count);
+                });
+            });
+        });
+
+        self.open = open;
+        error
+    }
+}
+
+fn percent(count: u64, total: u64) -> f32 {
+    count as f32 / total.max(1) as f32
+}
+
+fn draw_classes(ui: &mut egui::Ui, profiler: &Profiler) {
+    let total = profiler.total_executions();
+    egui::Grid::new("profiler_classes")
+    .show(ui, |ui| {
+        for (class, count) in profiler.classes() {
+            ui.monospace(class);
+            ui.add(egui::ProgressBar::new(percent(count, total))
+                .desired_width(150.0)
+                .text(format!("{:.1}%", 100.0 * percent(count, total))));
+            ui.label(format!("{}", count));
+            ui.end_row();
+        }
+    });
+}
+
+fn draw_hot_addresses(ui: &mut egui::Ui, chip8: &Chip8, symbols: &SymbolTable, top_count: usize) {
+    let profiler = chip8.get_profiler();
+    let total = profiler.total_executions();
+    egui::Grid::new("profiler_addresses")
+    .striped(true)
+    .show(ui, |ui| {
+        ui.strong("Address");
+        ui.strong("Instruction");
+        ui.strong("Executions");
+        ui.strong("Cycles");
+        ui.strong("%");
+        ui.end_row();
+
+        for (address, profile) in profiler.hot_addresses().into_iter().take(top_count) {
+            let instruction = chip8.get_ram().read16(address)
+            .map(|opcode| Instruction::new(opcode).to_string())
+            .unwrap_or_default();
+
+            ui.monospace(symbols.describe(address));
+            ui.monospace(instruction);
+            ui.label(format!("{}", profile.executions));
+            ui.label(format!("{}", profile.cycles));
+            ui.label(format!("{:.1}", 100.0 * percent(profile.executions, total)));
+            ui.end_row();
+        }
+    });
+}
+
+fn draw_hot_loops(ui: &mut egui::Ui, profiler: &Profiler, symbols: &SymbolTable, top_count: usize) {
+    let total = profiler.total_executions();
+    let loops = profiler.hot_loops(top_count);
+    if loops.is_empty() {
+        ui.label("No backward jump executed");
+        return;
+    }
+
+    egui::Grid::new("profiler_loops")
+    .striped(true)
+    .show(ui, |ui| {
+        ui.strong("Loop");
+        ui.strong("Iterations");
+        ui.strong("Instructions");
+        ui.strong("%");
+        ui.end_row();
+
+        for hot_loop in loops {
+            ui.monospace(format!("{} .. {:#05X}", symbols.describe(hot_loop.start), hot_loop.end));
+            ui.label(format!("{}", hot_loop.iterations));
+            ui.label(format!("{}", hot_loop.executions));
+            ui.label(format!("{:.1}", 100.0 * percent(hot_loop.executions, total)));
+            ui.end_row();
+        }
+    });
+}
@@ -0,0 +1,495 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, VecDeque};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use rhai::{CallFnOptions, Dynamic, Engine, EvalAltResult, Scope, AST};
+
+use crate::chip8_core::chip8::Chip8;
+use crate::chip8_core::graphics::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::chip8_core::keypad::Key;
+use crate::chip8_core::machine::MachineConfig;
+
+const LOG_CAPACITY: usize = 500;
+/// Script function called at the end of every 60 Hz frame with the frame number.
+const FRAME_CALLBACK: &str = "on_frame";
+/// Operations a script may run per call, an endless loop is aborted instead of freezing
+/// the emulator.
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+struct LogLine {
+    text: String,
+    error: bool,
+}
+
+/// What the native functions registered in the engine work on.
+struct ScriptState {
+    /// The emulated machine, swapped with the one of `MainApp` while a script runs.
+    chip8: Chip8,
+    /// Script function called when the PC reaches each address.
+    pc_hooks: BTreeMap<u16, String>,
+    log: VecDeque<LogLine>,
+    screenshots: Vec<PathBuf>,
+    pause_requested: bool,
+    failed_assertions: u32,
+}
+
+impl ScriptState {
+    fn log(&mut self, text: String, error: bool) {
+        if self.log.len() >= LOG_CAPACITY {
+            self.log.pop_front();
+        }
+        self.log.push_back(LogLine { text, error });
+    }
+}
+
+/// Runs a Rhai script against the emulator: its top level when loaded, `on_frame(frame)`
+/// after every frame and the functions given to `on_pc(address, name)` before the
+/// instruction at `address`. The console window shows the script output and evaluates
+/// lines typed by the user.
+pub struct ScriptHost {
+    pub open: bool,
+
+    engine: Engine,
+    ast: Option<AST>,
+    scope: Scope<'static>,
+    state: Rc<RefCell<ScriptState>>,
+    script_path: Option<PathBuf>,
+    has_frame_callback: bool,
+    input: String,
+}
+
+impl ScriptHost {
+    pub fn new() -> Self {
+        let state = Rc::new(RefCell::new(ScriptState {
+            chip8: Chip8::new(MachineConfig::default()),
+            pc_hooks: BTreeMap::new(),
+            log: VecDeque::new(),
+            screenshots: Vec::new(),
+            pause_requested: false,
+            failed_assertions: 0,
+        }));
+
+        Self {
+            open: false,
+            engine: create_engine(&state),
+            ast: None,
+            scope: Scope::new(),
+            state,
+            script_path: None,
+            has_frame_callback: false,
+            input: String::new(),
+        }
+    }
+
+    pub fn is_loaded(&self) -> bool {
+        self.ast.is_some()
+    }
+
+    /// Compiles the script and runs its top level. Syntax and runtime errors go to the
+    /// console, only reading the file can fail.
+    pub fn load(&mut self, path: &Path, chip8: &mut Chip8) -> io::Result<()> {
+        let source = fs::read_to_string(path)?;
+        self.unload(chip8);
+        self.script_path = Some(path.to_path_buf());
+        self.log(format!("Loaded {}", path.display()), false);
+
+        match self.engine.compile(source) {
+            Ok(ast) => {
+                self.has_frame_callback = ast.iter_functions()
+                .any(|function| function.name == FRAME_CALLBACK && function.params.len() == 1);
+                let ast = self.ast.insert(ast);
+                run_script(&self.engine, &mut self.scope, &self.state, chip8, ast, |engine, scope, ast| {
+                    engine.run_ast_with_scope(scope, ast)
+                });
+            },
+            Err(e) => self.log(e.to_string(), true),
+        }
+        Ok(())
+    }
+
+    pub fn reload(&mut self, chip8: &mut Chip8) -> io::Result<()> {
+        match self.script_path.clone() {
+            Some(path) => self.load(&path, chip8),
+            None => Ok(()),
+        }
+    }
+
+    /// Forgets the script, its variables and its hooks.
+    pub fn unload(&mut self, chip8: &mut Chip8) {
+        self.ast = None;
+        self.scope.clear();
+        self.has_frame_callback = false;
+
+        let mut state = self.state.borrow_mut();
+        state.pc_hooks.clear();
+        state.failed_assertions = 0;
+        chip8.set_pc_hooks(Default::default());
+    }
+
+    pub fn on_frame(&mut self, chip8: &mut Chip8) {
+        if self.has_frame_callback {
+            let frame = chip8.get_frame_count() as i64;
+            self.call(chip8, FRAME_CALLBACK, frame);
+        }
+    }
+
+    /// Calls the hook of the address the PC reached, see `Chip8::take_pc_hook`.
+    pub fn on_pc(&mut self, chip8: &mut Chip8, pc: u16) {
+        let hook = self.state.borrow().pc_hooks.get(&pc).cloned();
+        if let Some(function) = hook {
+            self.call(chip8, &function, pc as i64);
+        }
+    }
+
+    /// Screenshots asked by `screenshot(path)` since the last call.
+    pub fn take_screenshots(&mut self) -> Vec<PathBuf> {
+        std::mem::take(&mut self.state.borrow_mut().screenshots)
+    }
+
+    /// Whether `pause()` was called since the last call.
+    pub fn take_pause_request(&mut self) -> bool {
+        std::mem::take(&mut self.state.borrow_mut().pause_requested)
+    }
+
+    fn log(&mut self, text: String, error: bool) {
+        self.state.borrow_mut().log(text, error);
+    }
+
+    fn call(&mut self, chip8: &mut Chip8, function: &str, argument: i64) {
+        if let Some(ast) = &self.ast {
+            run_script(&self.engine, &mut self.scope, &self.state, chip8, ast, |engine, scope, ast| {
+                let options = CallFnOptions::new().eval_ast(false).rewind_scope(true);
+                engine.call_fn_with_options::<Dynamic>(options, scope, ast, function, (argument,))
+                .map(|_| ())
+            });
+        }
+    }
+
+    /// Evaluates a console line, with access to the functions and variables of the script.
+    fn evaluate(&mut self, chip8: &mut Chip8, line: &str) {
+        self.log(format!("> {}", line), false);
+        let line_ast = match self.engine.compile(line) {
+            Ok(line_ast) => line_ast,
+            Err(e) => return self.log(e.to_string(), true),
+        };
+
+        let ast = self.ast.as_ref()
+        .map(AST::clone_functions_only)
+        .unwrap_or_default()
+        .merge(&line_ast);
+        let state = &self.state;
+        run_script(&self.engine, &mut self.scope, state, chip8, &ast, |engine, scope, ast| {
+            let value = engine.eval_ast_with_scope::<Dynamic>(scope, ast)?;
+            if !value.is_unit() {
+                state.borrow_mut().log(value.to_string(), false);
+            }
+            Ok(())
+        });
+    }
+
+    /// Returns the action and the error when the script could not be read.
+    pub fn show(&mut self, ctx: &egui::Context, chip8: &mut Chip8) -> Option<(&'static str, io::Error)> {
+        let mut open = self.open;
+        let mut error = None;
+
+        egui::Window::new("Scripts")
+        .open(&mut open)
+        .default_height(300.0)
+        .show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                if ui.button("Load script").clicked() {
+                    let file = rfd::FileDialog::new()
+                    .add_filter("Rhai script", &["rhai"])
+                    .pick_file();
+                    if let Some(file) = file {
+                        error = self.load(&file, chip8).err().map(|e| ("loading the script", e));
+                    }
+                }
+                if ui.add_enabled(self.script_path.is_some(), egui::Button::new("Reload")).clicked() {
+                    error = self.reload(chip8).err().map(|e| ("loading the script", e));
+                }
+                if ui.add_enabled(self.is_loaded(), egui::Button::new("Unload")).clicked() {
+                    self.unload(chip8);
+                    self.script_path = None;
+                }
+                if ui.button("Clear").clicked() {
+                    self.state.borrow_mut().log.clear();
+                }
+            });
+            self.draw_status(ui);
+            ui.separator();
+
+            let input_height = ui.spacing().interact_size.y + ui.spacing().item_spacing.y * 2.0;
+            egui::ScrollArea::vertical()
+            .max_height((ui.available_height() - input_height).max(0.0))
+            .auto_shrink([false, true])
+            .stick_to_bottom(true)
+            .show(ui, |ui| {
+                for line in self.state.borrow().log.iter() {
+                    let text = egui::RichText::new(&line.text).monospace();
+                    ui.label(if line.error { text.color(ui.visuals().error_fg_color) } else { text });
+                }
+            });
+
+            ui.separator();
+            let input = ui.add(egui::TextEdit::singleline(&mut self.input)
+                .desired_width(f32::INFINITY)
+                .font(egui::TextStyle::Monospace)
+                .hint_text("v(0) + 1"));
+            if input.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+                let line = std::mem::take(&mut self.input);
+                if !line.trim().is_empty() {
+                    self.evaluate(chip8, line.trim());
+                }
+                input.request_focus();
+            }
+        });
+
+        self.open = open;
+        error
+    }
+
+    fn draw_status(&self, ui: &mut egui::Ui) {
+        let state = self.state.borrow();
+        if let Some(path) = &self.script_path {
+            ui.label(path.display().to_string());
+        }
+
+        let hooks: Vec<String> = state.pc_hooks.iter()
+        .map(|(address, function)| format!("{:#05X} {}", address, function))
+        .collect();
+        ui.horizontal_wrapped(|ui| {
+            ui.label(format!("{}: {}", FRAME_CALLBACK, if self.has_frame_callback { "yes" } else { "no" }));
+            ui.label(format!("PC hooks: {}", if hooks.is_empty() { String::from("none") } else { hooks.join(", ") }));
+            if state.failed_assertions > 0 {
+                ui.colored_label(ui.visuals().error_fg_color, format!("{} failed assertions", state.failed_assertions));
+            }
+        });
+    }
+}
+
+/// Runs `script` with `chip8` as the machine of the native functions, then updates
+/// the PC hooks of `chip8` from the ones the script registered.
+fn run_script<F>(engine: &Engine, scope: &mut Scope<'static>, state: &RefCell<ScriptState>, chip8: &mut Chip8, ast: &AST, script: F)
+where
+    F: FnOnce(&Engine, &mut Scope<'static>, &AST) -> ScriptResult<()>
+{
+    std::mem::swap(chip8, &mut state.borrow_mut().chip8);
+    let result = script(engine, scope, ast);
+    std::mem::swap(chip8, &mut state.borrow_mut().chip8);
+
+    let mut state = state.borrow_mut();
+    if let Err(e) = result {
+        state.log(e.to_string(), true);
+    }
+    chip8.set_pc_hooks(state.pc_hooks.keys().copied().collect());
+}
+
+fn error<T>(message: String) -> ScriptResult<T> {
+    Err(message.into())
+}
+
+fn register(index: i64) -> ScriptResult<usize> {
+    match index {
+        0..=0xF => Ok(index as usize),
+        _ => error(format!("no register V{:X}", index)),
+    }
+}
+
+fn byte(value: i64) -> ScriptResult<u8> {
+    u8::try_from(value).or_else(|_| error(format!("{} does not fit in a byte", value)))
+}
+
+fn address(value: i64) -> ScriptResult<u16> {
+    u16::try_from(value).or_else(|_| error(format!("{:#X} is not an address", value)))
+}
+
+fn key(value: i64) -> ScriptResult<Key> {
+    u8::try_from(value).ok()
+    .and_then(|value| Key::try_from(value).ok())
+    .map_or_else(|| error(format!("no key {:X}", value)), Ok)
+}
+
+/// Registers the functions scripts use to drive the machine in `state`.
+fn create_engine(state: &Rc<RefCell<ScriptState>>) -> Engine {
+    let mut engine = Engine::new();
+    engine.set_max_operations(MAX_OPERATIONS);
+
+    let log = state.clone();
+    engine.on_print(move |text| log.borrow_mut().log(text.to_string(), false));
+    let log = state.clone();
+    engine.on_debug(move |text, _, position| log.borrow_mut().log(format!("{}: {}", position, text), false));
+
+    // Registers and timers
+    let s = state.clone();
+    engine.register_fn("v", move |x: i64| -> ScriptResult<i64> {
+        Ok(s.borrow().chip8.get_cpu().v[register(x)?] as i64)
+    });
+    let s = state.clone();
+    engine.register_fn("set_v", move |x: i64, value: i64| -> ScriptResult<()> {
+        s.borrow_mut().chip8.get_cpu_mut().v[register(x)?] = byte(value)?;
+        Ok(())
+    });
+    let s = state.clone();
+    engine.register_fn("i", move || s.borrow().chip8.get_cpu().i as i64);
+    let s = state.clone();
+    engine.register_fn("set_i", move |value: i64| -> ScriptResult<()> {
+        s.borrow_mut().chip8.get_cpu_mut().i = address(value)?;
+        Ok(())
+    });
+    let s = state.clone();
+    engine.register_fn("pc", move || s.borrow().chip8.get_cpu().pc as i64);
+    let s = state.clone();
+    engine.register_fn("set_pc", move |value: i64| -> ScriptResult<()> {
+        s.borrow_mut().chip8.get_cpu_mut().pc = address(value)?;
+        Ok(())
+    });
+    let s = state.clone();
+    engine.register_fn("sp", move || s.borrow().chip8.get_cpu().stack.sp as i64);
+    let s = state.clone();
+    engine.register_fn("dt", move || s.borrow().chip8.get_cpu().delay_timer as i64);
+    let s = state.clone();
+    engine.register_fn("set_dt", move |value: i64| -> ScriptResult<()> {
+        s.borrow_mut().chip8.get_cpu_mut().delay_timer = byte(value)?;
+        Ok(())
+    });
+    let s = state.clone();
+    engine.register_fn("st", move || s.borrow().chip8.get_cpu().sound_timer as i64);
+    let s = state.clone();
+    engine.register_fn("set_st", move |value: i64| -> ScriptResult<()> {
+        s.borrow_mut().chip8.get_cpu_mut().sound_timer = byte(value)?;
+        Ok(())
+    });
+    let s = state.clone();
+    engine.register_fn("frame", move || s.borrow().chip8.get_frame_count() as i64);
+
+    // Memory
+    let s = state.clone();
+    engine.register_fn("peek", move |at: i64| -> ScriptResult<i64> {
+        s.borrow().chip8.get_ram().read8(address(at)?)
+        .map(|value| value as i64)
+        .or_else(|e| error(e.to_string()))
+    });
+    let s = state.clone();
+    engine.register_fn("poke", move |at: i64, value: i64| -> ScriptResult<()> {
+        s.borrow_mut().chip8.get_ram_mut().write8(address(at)?, byte(value)?)
+        .or_else(|e| error(e.to_string()))
+    });
+
+    // Keypad
+    let s = state.clone();
+    engine.register_fn("press", move |value: i64| -> ScriptResult<()> {
+        s.borrow_mut().chip8.get_keypad_mut().set_key_pressed(key(value)?, true);
+        Ok(())
+    });
+    let s = state.clone();
+    engine.register_fn("release", move |value: i64| -> ScriptResult<()> {
+        s.borrow_mut().chip8.get_keypad_mut().set_key_pressed(key(value)?, false);
+        Ok(())
+    });
+
+    // Screen
+    let s = state.clone();
+    engine.register_fn("pixel", move |x: i64, y: i64| {
+        (0..SCREEN_WIDTH as i64).contains(&x) && (0..SCREEN_HEIGHT as i64).contains(&y)
+        && s.borrow().chip8.get_screen().is_pixel_set(x as usize, y as usize)
+    });
+    let s = state.clone();
+    engine.register_fn("lit_pixels", move || {
+        let state = s.borrow();
+        let screen = state.chip8.get_screen();
+        (0..SCREEN_HEIGHT)
+        .flat_map(|y| (0..SCREEN_WIDTH).map(move |x| (x, y)))
+        .filter(|(x, y)| screen.is_pixel_set(*x, *y))
+        .count() as i64
+    });
+    let s = state.clone();
+    engine.register_fn("screenshot", move |path: &str| {
+        s.borrow_mut().screenshots.push(PathBuf::from(path));
+    });
+
+    // Control
+    let s = state.clone();
+    engine.register_fn("on_pc", move |at: i64, function: &str| -> ScriptResult<()> {
+        s.borrow_mut().pc_hooks.insert(address(at)?, function.to_string());
+        Ok(())
+    });
+    let s = state.clone();
+    engine.register_fn("pause", move || {
+        s.borrow_mut().pause_requested = true;
+    });
+    let s = state.clone();
+    engine.register_fn("assert", move |condition: bool, message: &str| {
+        if !condition {
+            let mut state = s.borrow_mut();
+            let frame = state.chip8.get_frame_count();
+            state.failed_assertions += 1;
+            state.log(format!("Assertion failed at frame {}: {}", frame, message), true);
+        }
+    });
+
+    engine
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn log_lines(host: &ScriptHost) -> Vec<String> {
+        host.state.borrow().log.iter().map(|line| line.text.clone()).collect()
+    }
+
+    #[test]
+    fn peek_and_poke_access_the_memory() {
+        let mut host = ScriptHost::new();
+        let mut chip8 = Chip8::new(MachineConfig::default());
+
+        host.evaluate(&mut chip8, "poke(0x300, 0xAB)");
+        assert_eq!(chip8.get_ram().read8(0x300).unwrap(), 0xAB);
+        host.evaluate(&mut chip8, "peek(0x300) + 1");
+        assert_eq!(log_lines(&host).last().unwrap(), "172");
+
+        host.evaluate(&mut chip8, "poke(0x300, 256)");
+        assert!(host.state.borrow().log.back().unwrap().error);
+    }
+
+    #[test]
+    fn press_and_release_drive_the_keypad() {
+        let mut host = ScriptHost::new();
+        let mut chip8 = Chip8::new(MachineConfig::default());
+
+        host.evaluate(&mut chip8, "press(0xA)");
+        assert!(chip8.get_keypad().status()[0xA]);
+        host.evaluate(&mut chip8, "release(0xA)");
+        assert!(!chip8.get_keypad().status()[0xA]);
+
+        host.evaluate(&mut chip8, "press(16)");
+        assert!(host.state.borrow().log.back().unwrap().error);
+    }
+
+    #[test]
+    fn failed_assertions_are_counted_and_logged() {
+        let mut host = ScriptHost::new();
+        let mut chip8 = Chip8::new(MachineConfig::default());
+
+        host.evaluate(&mut chip8, "assert(peek(0x300) == 0, \"cleared\")");
+        host.evaluate(&mut chip8, "assert(v(0) == 1, \"V0 set\")");
+
+        assert_eq!(host.state.borrow().failed_assertions, 1);
+        assert_eq!(log_lines(&host).last().unwrap(), "Assertion failed at frame 0: V0 set");
+    }
+
+    #[test]
+    fn endless_scripts_are_aborted() {
+        let mut host = ScriptHost::new();
+        let mut chip8 = Chip8::new(MachineConfig::default());
+
+        host.evaluate(&mut chip8, "loop {}");
+        assert!(host.state.borrow().log.back().unwrap().error);
+    }
+}
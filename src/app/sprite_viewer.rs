@@ -0,0 +1,234 @@
+use crate::chip8_core::chip8::Chip8;
+use crate::chip8_core::cpu::SpriteDraw;
+use crate::chip8_core::graphics::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::chip8_core::fonts::{BIG_GLYPH_HEIGHT, FONT_GLYPHS, SMALL_FONT_SIZE, SMALL_GLYPH_HEIGHT};
+use crate::chip8_core::memory::Ram;
+use crate::chip8_core::symbols::parse_hex;
+use crate::chip8_core::FONT_START_ADDRESS;
+
+const SPRITE_WIDTH: usize = 8;
+const MAX_SPRITE_HEIGHT: u8 = 15;
+
+const SPRITE_SCALE: f32 = 12.0;
+const FONT_SCALE: f32 = 4.0;
+const SHEET_SCALE: f32 = 4.0;
+const SHEET_COLUMNS: usize = 8;
+const SHEET_ROWS: usize = 4;
+
+const SET_PIXEL_COLOR: egui::Color32 = egui::Color32::WHITE;
+const CLEAR_PIXEL_COLOR: egui::Color32 = egui::Color32::from_gray(30);
+const HIGHLIGHT_COLOR: egui::Color32 = egui::Color32::YELLOW;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SpriteSource {
+    RegisterI,
+    Address,
+}
+
+/// Shows memory decoded as 8xN sprites: the sprite at `I` or at a chosen address, the
+/// font glyphs, the last sprite drawn by `DXYN` and the RAM as a sprite sheet.
+pub struct SpriteViewer {
+    pub open: bool,
+    /// Outlines on the screen the sprite drawn by the last `DXYN`.
+    pub highlight_last_draw: bool,
+
+    source: SpriteSource,
+    address: u16,
+    address_text: String,
+    height: u8,
+    sheet_address: u16,
+}
+
+impl SpriteViewer {
+    pub fn new() -> Self {
+        Self {
+            open: false,
+            highlight_last_draw: true,
+            source: SpriteSource::RegisterI,
+            address: FONT_START_ADDRESS as u16,
+            address_text: String::new(),
+            height: SMALL_GLYPH_HEIGHT as u8,
+            sheet_address: FONT_START_ADDRESS as u16,
+        }
+    }
+
+    pub fn show(&mut self, ctx: &egui::Context, chip8: &Chip8) {
+        let mut open = self.open;
+        egui::Window::new("Sprites")
+        .open(&mut open)
+        .show(ctx, |ui| {
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                self.draw_sprite_group(ui, chip8);
+                ui.separator();
+                draw_last_draw_group(ui, chip8.get_cpu().get_last_draw(), chip8.get_ram(), &mut self.highlight_last_draw);
+                ui.separator();
+                self.draw_font_group(ui, chip8.get_ram(), chip8.get_font_address());
+                ui.separator();
+                self.draw_sheet_group(ui, chip8.get_ram());
+            });
+        });
+        self.open = open;
+    }
+
+    fn draw_sprite_group(&mut self, ui: &mut egui::Ui, chip8: &Chip8) {
+        ui.heading("Sprite");
+        ui.horizontal(|ui| {
+            ui.radio_value(&mut self.source, SpriteSource::RegisterI, "At I");
+            ui.radio_value(&mut self.source, SpriteSource::Address, "At address");
+            let address_edit = ui.add_enabled(
+                self.source == SpriteSource::Address,
+                egui::TextEdit::singleline(&mut self.address_text).desired_width(60.0).hint_text("0x200")
+            );
+            if address_edit.changed() {
+                if let Some(address) = parse_hex(self.address_text.trim()) {
+                    self.address = address.min((chip8.get_ram().size() - 1) as u16);
+                }
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.label("Height");
+            ui.add(egui::Slider::new(&mut self.height, 1..=MAX_SPRITE_HEIGHT));
+        });
+
+        let address = match self.source {
+            SpriteSource::RegisterI => chip8.get_cpu().i,
+            SpriteSource::Address => self.address,
+        };
+        ui.label(format!("{:#05X}", address));
+        draw_sprite(ui, chip8.get_ram(), address, self.height, SPRITE_SCALE);
+    }
+
+    fn draw_font_group(&mut self, ui: &mut egui::Ui, ram: &Ram, font_address: u16) {
+        ui.heading("Font");
+        self.draw_glyphs(ui, ram, font_address, SMALL_GLYPH_HEIGHT);
+        self.draw_glyphs(ui, ram, font_address + SMALL_FONT_SIZE as u16, BIG_GLYPH_HEIGHT);
+    }
+
+    fn draw_glyphs(&mut self, ui: &mut egui::Ui, ram: &Ram, start: u16, glyph_height: usize) {
+        ui.horizontal_wrapped(|ui| {
+            for glyph in 0..FONT_GLYPHS {
+                let address = start + (glyph * glyph_height) as u16;
+                ui.vertical(|ui| {
+                    ui.label(format!("{:X}", glyph));
+                    let response = draw_sprite(ui, ram, address, glyph_height as u8, FONT_SCALE)
+                    .on_hover_text(format!("{:#05X}", address));
+                    if response.clicked() {
+                        self.show_address(address, glyph_height as u8);
+                    }
+                });
+            }
+        });
+    }
+
+    /// Consecutive sprites of the chosen height, starting from any byte of the RAM.
+    fn draw_sheet_group(&mut self, ui: &mut egui::Ui, ram: &Ram) {
+        let last_address = (ram.size() - 1) as u16;
+        ui.heading("Sprite sheet");
+        ui.horizontal(|ui| {
+            ui.label("Start");
+            ui.add(egui::Slider::new(&mut self.sheet_address, 0..=last_address).hexadecimal(3, false, true));
+            if ui.small_button("-").clicked() {
+                self.sheet_address = self.sheet_address.saturating_sub(1);
+            }
+            if ui.small_button("+").clicked() {
+                self.sheet_address = self.sheet_address.saturating_add(1).min(last_address);
+            }
+        });
+        self.sheet_address = self.sheet_address.min(last_address);
+
+        egui::Grid::new("sprite_sheet")
+        .show(ui, |ui| {
+            for row in 0..SHEET_ROWS {
+                for column in 0..SHEET_COLUMNS {
+                    let index = (row * SHEET_COLUMNS + column) as u16;
+                    let address = self.sheet_address as usize + index as usize * self.height as usize;
+                    if address >= ram.size() {
+                        break;
+                    }
+
+                    let response = draw_sprite(ui, ram, address as u16, self.height, SHEET_SCALE)
+                    .on_hover_text(format!("{:#05X}", address));
+                    if response.clicked() {
+                        self.show_address(address as u16, self.height);
+                    }
+                }
+                ui.end_row();
+            }
+        });
+    }
+
+    fn show_address(&mut self, address: u16, height: u8) {
+        self.source = SpriteSource::Address;
+        self.address = address;
+        self.address_text = format!("{:#05X}", address);
+        self.height = height;
+    }
+
+    /// Outlines the last drawn sprite on the screen image drawn in `screen_rect`.
+    pub fn draw_highlight(&self, ui: &egui::Ui, chip8: &Chip8, screen_rect: egui::Rect) {
+        if !self.highlight_last_draw {
+            return;
+        }
+        let draw = match chip8.get_cpu().get_last_draw() {
+            Some(draw) => draw,
+            None => return,
+        };
+
+        let pixel_size = egui::vec2(
+            screen_rect.width() / SCREEN_WIDTH as f32,
+            screen_rect.height() / SCREEN_HEIGHT as f32
+        );
+        let min = screen_rect.min + egui::vec2(draw.x as f32 * pixel_size.x, draw.y as f32 * pixel_size.y);
+        let size = egui::vec2(SPRITE_WIDTH as f32 * pixel_size.x, draw.height as f32 * pixel_size.y);
+        // Sprites are clipped at the screen edges.
+        let rect = egui::Rect::from_min_size(min, size).intersect(screen_rect);
+
+        ui.painter().rect_stroke(rect, 0.0, egui::Stroke::new(2.0, HIGHLIGHT_COLOR));
+    }
+}
+
+fn draw_last_draw_group(ui: &mut egui::Ui, last_draw: Option<&SpriteDraw>, ram: &Ram, highlight: &mut bool) {
+    ui.heading("Last DXYN");
+    ui.checkbox(highlight, "Highlight on screen");
+    match last_draw {
+        Some(draw) => {
+            ui.label(format!(
+                "{} rows from {:#05X} at ({}, {}){}",
+                draw.height, draw.address, draw.x, draw.y,
+                if draw.collision { ", collision" } else { "" }
+            ));
+            draw_sprite(ui, ram, draw.address, draw.height, SPRITE_SCALE);
+        },
+        None => {
+            ui.label("No sprite drawn yet");
+        },
+    }
+}
+
+/// Paints `height` bytes of memory as an 8 pixels wide sprite.
+fn draw_sprite(ui: &mut egui::Ui, ram: &Ram, address: u16, height: u8, scale: f32) -> egui::Response {
+    let size = egui::vec2(SPRITE_WIDTH as f32 * scale, height as f32 * scale);
+    let (rect, response) = ui.allocate_exact_size(size, egui::Sense::click());
+
+    let painter = ui.painter();
+    painter.rect_filled(rect, 0.0, CLEAR_PIXEL_COLOR);
+    for row in 0..height as u16 {
+        let byte = match ram.read8(address.wrapping_add(row)) {
+            Ok(byte) => byte,
+            Err(_) => break,
+        };
+
+        for column in 0..SPRITE_WIDTH {
+            if byte & (0x80 >> column) != 0 {
+                let min = rect.min + egui::vec2(column as f32 * scale, row as f32 * scale);
+                painter.rect_filled(egui::Rect::from_min_size(min, egui::vec2(scale, scale)), 0.0, SET_PIXEL_COLOR);
+            }
+        }
+    }
+
+    if response.hovered() {
+        painter.rect_stroke(rect, 0.0, egui::Stroke::new(1.0, HIGHLIGHT_COLOR));
+    }
+
+    response
+}
@@ -1,53 +1,53 @@
-use super::chip8_errors::Chip8ErrorKind;
+use super::chip8_errors::{Chip8Error, Chip8ErrorKind, ErrorPolicies, ErrorPolicy};
+use super::fonts::{Font, FONT_SIZE};
+use super::quirks::Quirks;
+use super::timing::{TimingMode, COSMAC_FRAME_BUDGET};
 use super::cpu::CPU;
 use super::memory::Ram;
-use super::{FONT_START_ADDRESS, PROGRAM_START_ADDRESS};
+use super::machine::MachineConfig;
+use super::profiler::Profiler;
+use super::coverage::Coverage;
+use super::symbols::Register;
+use std::collections::{BTreeSet, VecDeque};
 use std::fs::File;
 use std::io::Read;
 use std::path::PathBuf;
-use crate::timer::Timer;
 use super::keypad::KeyPad;
 use super::graphics::{Screen, FrameBuffer};
-use super::memory::RAM_SIZE;
-
-const CLOCK_FREQ: f64 = 1.0 / 60.0;
-
-const FONT_DATA:[u8; 80] = [
-    0xF0, 0x90, 0x90, 0x90, 0xF0,//0
-    0x20, 0x60, 0x20, 0x20, 0x70,//1
-    0xF0, 0x10, 0xF0, 0x80, 0xF0,//2
-    0xF0, 0x10, 0xF0, 0x10, 0xF0,//3
-    0x90, 0x90, 0xF0, 0x10, 0x10,//4
-    0xF0, 0x80, 0xF0, 0x10, 0xF0,//5
-    0xF0, 0x80, 0xF0, 0x90, 0xF0,//6
-    0xF0, 0x10, 0x20, 0x40, 0x40,//7
-    0xF0, 0x90, 0xF0, 0x90, 0xF0,//8
-    0xF0, 0x90, 0xF0, 0x10, 0xF0,//9
-    0xF0, 0x90, 0xF0, 0x90, 0x90,//A
-    0xE0, 0x90, 0xE0, 0x90, 0xE0,//B
-    0xF0, 0x80, 0x80, 0x80, 0xF0,//C
-    0xE0, 0x90, 0x90, 0x90, 0xE0,//D
-    0xF0, 0x80, 0xF0, 0x80, 0xF0,//E
-    0xF0, 0x80, 0xF0, 0x80, 0x80,//F
-];
+
+const TIMERS_FREQ: f64 = 60.0;
+const DEFAULT_CPU_FREQ: f64 = 500.0;
+const ERROR_LOG_CAPACITY: usize = 100;
+
+/// What a debugger watch reads: a register or a byte of memory.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Location {
+    Register(Register),
+    Byte(u16),
+}
 
 pub(crate) struct Devices {
     pub ram: Ram,
     pub keypad: KeyPad,
     pub screen: Screen,
+    /// Address of the small font, the big font follows it.
+    pub font_address: u16,
+    pub coverage: Coverage,
 }
 
 impl Devices {
-    fn new() -> Self {
+    fn new(config: &MachineConfig, font: &Font) -> Self {
 
-        let mut ram = Ram::new();
+        let mut ram = Ram::new(config.ram_size, config.program_start as usize);
         
-        ram.write_bytes(FONT_START_ADDRESS as u16, &FONT_DATA).unwrap();
+        ram.write_bytes(config.font_address, &font.bytes()).unwrap();
 
         Self {
             ram,
             keypad: KeyPad::new(),
             screen: Screen::new(),
+            font_address: config.font_address,
+            coverage: Coverage::new(config.ram_size),
         }
     }
 }
@@ -55,30 +55,83 @@ impl Devices {
 pub struct Chip8 {
     cpu: CPU,
     devices: Devices,
+    config: MachineConfig,
 
     read_program_length: usize,
 
-    clock_update_timer: Timer,
+    timing_mode: TimingMode,
+    instructions_per_frame: f64,
+    frame_cycles: f64,
+    frame_count: u64,
+    frame_sound: bool,
+
+    error_log: VecDeque<Chip8Error>,
+
+    quirks: Quirks,
+    font: Font,
+
+    pc_hooks: BTreeSet<u16>,
+    pc_hook_hit: Option<u16>,
+    breakpoints: BTreeSet<u16>,
+    breakpoint_hit: Option<u16>,
+    /// Address of the last hook or breakpoint taken, executed once without stopping
+    /// again so that resuming from it makes progress.
+    resume_address: Option<u16>,
+    /// Watched locations and their value after the last instruction.
+    watches: Vec<(Location, u16)>,
+    watch_hit: bool,
 }
 
 impl Chip8 {
-    pub fn new() -> Self {
+    /// Creates a machine with the given memory layout, which has to pass
+    /// `MachineConfig::validate`.
+    pub fn new(config: MachineConfig) -> Self {
+        let font = Font::default();
         Self {
-            cpu: CPU::new(),
-            devices: Devices::new(),
+            cpu: CPU::new(config.program_start),
+            devices: Devices::new(&config, &font),
+            config,
             read_program_length: 0,
-            clock_update_timer: Timer::new(),
+            timing_mode: TimingMode::Instructions,
+            instructions_per_frame: DEFAULT_CPU_FREQ / TIMERS_FREQ,
+            frame_cycles: 0.0,
+            frame_count: 0,
+            frame_sound: false,
+            error_log: VecDeque::new(),
+            quirks: Quirks::default(),
+            font,
+            pc_hooks: BTreeSet::new(),
+            pc_hook_hit: None,
+            breakpoints: BTreeSet::new(),
+            breakpoint_hit: None,
+            resume_address: None,
+            watches: Vec::new(),
+            watch_hit: false,
         }
     }
 
+    /// The memory layout, `set_font_address` keeps its font address up to date.
+    pub fn get_config(&self) -> &MachineConfig {
+        &self.config
+    }
+
     pub fn get_cpu(&self) -> &CPU {
         &self.cpu
     }
 
+    /// Registers can be edited directly, such as from scripts or a debugger.
+    pub fn get_cpu_mut(&mut self) -> &mut CPU {
+        &mut self.cpu
+    }
+
     pub fn get_ram(&self) -> &Ram {
         &self.devices.ram
     }
 
+    pub fn get_ram_mut(&mut self) -> &mut Ram {
+        &mut self.devices.ram
+    }
+
     pub fn get_program_length(&self) -> usize {
         self.read_program_length
     }
@@ -95,32 +148,255 @@ impl Chip8 {
         &self.devices.keypad
     }
 
+    /// Number of instructions per second, the 60 Hz timers tick every
+    /// `frequency / 60` instructions.
+    pub fn set_cpu_frequency(&mut self, frequency: f64) {
+        self.instructions_per_frame = (frequency / TIMERS_FREQ).max(1.0);
+    }
+
+    pub fn get_timing_mode(&self) -> TimingMode {
+        self.timing_mode
+    }
+
+    pub fn set_timing_mode(&mut self, timing_mode: TimingMode) {
+        self.timing_mode = timing_mode;
+        self.frame_cycles = 0.0;
+    }
+
+    /// Cycles available in a 60 Hz frame: instructions, or COSMAC VIP machine cycles
+    /// left once the display took its share.
+    fn frame_budget(&self) -> f64 {
+        match self.timing_mode {
+            TimingMode::Instructions => self.instructions_per_frame,
+            TimingMode::CosmacVip => COSMAC_FRAME_BUDGET as f64,
+        }
+    }
+
+    /// Number of 60 Hz frames elapsed since the machine was created.
+    pub fn get_frame_count(&self) -> u64 {
+        self.frame_count
+    }
+
+    pub fn get_quirks(&self) -> &Quirks {
+        &self.quirks
+    }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+    }
+
+    pub fn get_font(&self) -> &Font {
+        &self.font
+    }
+
+    /// Writes the font at the font address, `FX29` and `FX30` use it right away.
+    pub fn set_font(&mut self, font: Font) {
+        self.devices.ram.write_bytes(self.devices.font_address, &font.bytes()).unwrap();
+        self.font = font;
+    }
+
+    pub fn get_font_address(&self) -> u16 {
+        self.devices.font_address
+    }
+
+    /// Moves the font, it has to fit in the interpreter area before the program.
+    pub fn set_font_address(&mut self, address: u16) -> Result<(), Chip8ErrorKind> {
+        if address as usize + FONT_SIZE > self.config.program_start as usize {
+            return Err(Chip8ErrorKind::SegmentationFault(address));
+        }
+
+        let old_address = self.devices.font_address as usize;
+        self.devices.ram.reset_range(old_address, old_address + FONT_SIZE);
+        self.devices.font_address = address;
+        self.config.font_address = address;
+        self.devices.ram.write_bytes(address, &self.font.bytes())
+    }
+
+    pub fn get_error_policies(&self) -> &ErrorPolicies {
+        self.cpu.get_error_policies()
+    }
+
+    pub fn set_error_policies(&mut self, error_policies: ErrorPolicies) {
+        self.cpu.set_error_policies(error_policies);
+        self.devices.ram.wrap_addresses = error_policies.memory == ErrorPolicy::Emulate;
+    }
+
+    pub fn get_profiler(&self) -> &Profiler {
+        self.cpu.get_profiler()
+    }
+
+    /// Starts or stops counting the executed instructions, the counts are kept.
+    pub fn set_profiling(&mut self, enabled: bool) {
+        self.cpu.get_profiler_mut().enabled = enabled;
+    }
+
+    pub fn clear_profile(&mut self) {
+        self.cpu.get_profiler_mut().clear();
+    }
+
+    pub fn get_coverage(&self) -> &Coverage {
+        &self.devices.coverage
+    }
+
+    /// Starts or stops tagging the executed, read and written bytes, the tags are kept.
+    pub fn set_coverage_enabled(&mut self, enabled: bool) {
+        self.devices.coverage.enabled = enabled;
+    }
+
+    pub fn clear_coverage(&mut self) {
+        self.devices.coverage.clear();
+    }
+
+    /// Addresses at which `run_frame` returns early, before executing them, so the
+    /// caller can run its hooks. See `take_pc_hook`.
+    pub fn set_pc_hooks(&mut self, addresses: BTreeSet<u16>) {
+        self.pc_hooks = addresses;
+    }
+
+    pub fn get_pc_hooks(&self) -> &BTreeSet<u16> {
+        &self.pc_hooks
+    }
+
+    /// The hooked address the PC reached, if any. The next instruction runs from it
+    /// without stopping again.
+    pub fn take_pc_hook(&mut self) -> Option<u16> {
+        let hit = self.pc_hook_hit.take();
+        self.resume_address = hit.or(self.resume_address);
+        hit
+    }
+
+    /// Returns whether the breakpoint is new.
+    pub fn add_breakpoint(&mut self, address: u16) -> bool {
+        self.breakpoints.insert(address)
+    }
+
+    /// Returns whether there was a breakpoint at this address.
+    pub fn remove_breakpoint(&mut self, address: u16) -> bool {
+        self.breakpoints.remove(&address)
+    }
+
+    pub fn get_breakpoints(&self) -> &BTreeSet<u16> {
+        &self.breakpoints
+    }
+
+    /// The breakpoint the PC reached, if any. Like PC hooks, breakpoints make
+    /// `run_frame` return before executing their address, the next instruction runs
+    /// from it without stopping again.
+    pub fn take_breakpoint_hit(&mut self) -> Option<u16> {
+        let hit = self.breakpoint_hit.take();
+        self.resume_address = hit.or(self.resume_address);
+        hit
+    }
+
+    pub fn read_location(&self, location: Location) -> u16 {
+        match location {
+            Location::Register(register) => register.read(&self.cpu),
+            Location::Byte(address) => self.devices.ram.read8(address).unwrap_or(0) as u16,
+        }
+    }
+
+    /// Watches the locations from their current values, replacing the previous ones.
+    pub fn set_watches(&mut self, locations: Vec<Location>) {
+        self.watches = locations.into_iter()
+        .map(|location| (location, self.read_location(location)))
+        .collect();
+        self.watch_hit = false;
+    }
+
+    pub fn get_watches(&self) -> Vec<Location> {
+        self.watches.iter().map(|(location, _)| *location).collect()
+    }
+
+    /// Whether the last instruction changed a watched location. Like breakpoints,
+    /// watches make `run_frame` return right after the change.
+    pub fn take_watch_hit(&mut self) -> bool {
+        std::mem::take(&mut self.watch_hit)
+    }
+
+    fn update_watches(&mut self) {
+        for index in 0..self.watches.len() {
+            let (location, value) = self.watches[index];
+            let new_value = self.read_location(location);
+            if new_value != value {
+                self.watches[index].1 = new_value;
+                self.watch_hit = true;
+            }
+        }
+    }
+
+    /// Errors that were logged and skipped because of an `ErrorPolicy::Ignore` policy.
+    pub fn get_error_log(&self) -> &VecDeque<Chip8Error> {
+        &self.error_log
+    }
+
+    pub fn clear_error_log(&mut self) {
+        self.error_log.clear();
+    }
+
     pub fn reset(&mut self) {
         self.cpu.reset();
+        self.frame_sound = false;
+        self.pc_hook_hit = None;
+        self.breakpoint_hit = None;
+        self.resume_address = None;
         self.devices.ram.reset();
         self.devices.keypad.reset();
         self.devices.screen.clear();
+        self.set_watches(self.get_watches());
     }
 
     pub fn reload_program(&mut self) {
         self.cpu.reset();
-        self.devices.ram.reset_range(PROGRAM_START_ADDRESS + self.read_program_length, RAM_SIZE);
+        self.frame_sound = false;
+        self.pc_hook_hit = None;
+        self.breakpoint_hit = None;
+        self.resume_address = None;
+        let program_end = self.config.program_start as usize + self.read_program_length;
+        self.devices.ram.reset_range(program_end, self.devices.ram.size());
         self.devices.keypad.reset();
         self.devices.screen.clear();
+        self.set_watches(self.get_watches());
     }
 
     pub fn load_program(&mut self, program_file: &PathBuf) -> std::io::Result<()> {
         let mut file = File::open(program_file)?;
         let mut buf = Vec::new();
-        self.read_program_length = file.read_to_end(&mut buf)?;
+        file.read_to_end(&mut buf)?;
 
-        self.devices.ram.write_bytes(PROGRAM_START_ADDRESS as u16, buf.as_slice()).unwrap();
+        self.load_program_bytes(buf.as_slice())
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+    }
 
-        Ok(())
+    /// Writes the program at the program start, it has to fit in the RAM.
+    pub fn load_program_bytes(&mut self, program: &[u8]) -> Result<(), String> {
+        let available = self.devices.ram.size().saturating_sub(self.config.program_start as usize);
+        if program.len() > available {
+            return Err(format!(
+                "the program is {} bytes, only {} are available from {:#X}",
+                program.len(), available, self.config.program_start,
+            ));
+        }
+
+        self.read_program_length = program.len();
+        self.devices.ram.write_bytes(self.config.program_start, program)
+        .map_err(|e| e.to_string())
+    }
+
+    /// Whether a program is loaded and the PC still points inside of it.
+    pub fn can_run(&self) -> bool {
+        self.read_program_length > 0 &&
+        self.read_program_length + self.config.program_start as usize - 2 >= self.cpu.pc as usize
     }
 
     pub fn can_play_sound(&self) -> bool {
-        self.cpu.sound_timer > 1
+        let minimum_sound_timer = if self.quirks.cosmac_minimum_beep { 2 } else { 1 };
+        self.cpu.sound_timer >= minimum_sound_timer
+    }
+
+    /// Whether the buzzer sounded until the end of the last frame, before its timer tick.
+    /// A sound timer set to n sounds for n frames.
+    pub fn played_sound_last_frame(&self) -> bool {
+        self.frame_sound
     }
 
     pub fn update_framebuffer(&mut self, framebuffer: &mut dyn FrameBuffer) {
@@ -130,17 +406,566 @@ impl Chip8 {
         }
     }
 
-    pub fn run_instruction(&mut self) -> Result<(), Chip8ErrorKind> {
-        let mut elapsed = self.clock_update_timer.elapsed().as_secs_f64();
-        let mut restart = false;
-        while elapsed >= CLOCK_FREQ {
-            self.cpu.update_timers();
-            elapsed -= CLOCK_FREQ;
-            restart = true;
+    /// Runs one instruction cycle, when the CPU waits for the vertical blank the cycle
+    /// is spent without executing anything. With `TimingMode::CosmacVip` the instruction
+    /// takes its machine cycles from the frame budget and waiting for the vertical blank
+    /// uses up the rest of the frame.
+    pub fn run_instruction(&mut self) -> Result<(), Chip8Error> {
+        // The PC may start on a hook or a breakpoint, after a reset or a jump
+        if self.resume_address != Some(self.cpu.pc) && self.check_stops() {
+            return Ok(());
+        }
+
+        let budget = self.frame_budget();
+        let waiting = self.cpu.is_waiting_for_vblank();
+        let result = if waiting {
+            Ok(())
+        }
+        else {
+            self.step()
+        };
+
+        self.frame_cycles += match self.timing_mode {
+            TimingMode::Instructions => 1.0,
+            TimingMode::CosmacVip if waiting => (budget - self.frame_cycles).max(0.0),
+            TimingMode::CosmacVip => self.cpu.get_last_cycles() as f64,
+        };
+        while self.frame_cycles >= budget {
+            self.frame_cycles -= budget;
+            self.end_frame();
+        }
+
+        result
+    }
+
+    /// Runs instructions until the next 60 Hz frame boundary, until the PC reaches
+    /// one of the `set_pc_hooks` addresses or a breakpoint, or until a watched location
+    /// changes. Calling it again finishes the frame.
+    pub fn run_frame(&mut self) -> Result<(), Chip8Error> {
+        let frame = self.frame_count;
+        while self.frame_count == frame && self.pc_hook_hit.is_none() && self.breakpoint_hit.is_none() && !self.watch_hit {
+            if self.can_run() {
+                self.run_instruction()?;
+            }
+            else {
+                self.frame_cycles = 0.0;
+                self.end_frame();
+            }
+        }
+
+        Ok(())
+    }
+
+    fn end_frame(&mut self) {
+        self.frame_sound = self.can_play_sound();
+        self.tick_timers();
+        self.cpu.vblank();
+        self.frame_count += 1;
+    }
+
+    /// Decrements the delay and sound timers as a 60 Hz tick would.
+    pub fn tick_timers(&mut self) {
+        self.cpu.update_timers();
+    }
+
+    /// Flags the hook and the breakpoint at the PC, returns whether there was any.
+    fn check_stops(&mut self) -> bool {
+        let pc = self.cpu.pc;
+        if self.pc_hooks.contains(&pc) {
+            self.pc_hook_hit = Some(pc);
+        }
+        if self.breakpoints.contains(&pc) {
+            self.breakpoint_hit = Some(pc);
+        }
+        self.pc_hook_hit.is_some() || self.breakpoint_hit.is_some()
+    }
+
+    /// Executes one instruction without updating the timers.
+    pub fn step(&mut self) -> Result<(), Chip8Error> {
+        let result = self.cpu.clock(&mut self.devices, &self.quirks);
+        self.resume_address = None;
+        self.check_stops();
+        self.update_watches();
+
+        match result {
+            // Without an opcode the fetch failed, there is no instruction to skip so the
+            // machine halts whatever the policy.
+            Err(e) if e.opcode.is_some() && self.get_error_policies().get(e.kind.class()) != ErrorPolicy::Halt => {
+                if self.error_log.len() >= ERROR_LOG_CAPACITY {
+                    self.error_log.pop_front();
+                }
+                self.error_log.push_back(e);
+                Ok(())
+            },
+            result => result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8_core::cpu::KeyWait;
+    use crate::chip8_core::keypad::Key;
+    use crate::chip8_core::coverage::{EXECUTED, READ, WRITTEN};
+
+    fn chip8_with_program(program: &[u8]) -> Chip8 {
+        let mut chip8 = Chip8::new(MachineConfig::default());
+        chip8.load_program_bytes(program).unwrap();
+        chip8
+    }
+
+    fn run_steps(chip8: &mut Chip8, count: usize) {
+        for _ in 0..count {
+            chip8.step().unwrap();
+        }
+    }
+
+    fn tick_frames(chip8: &mut Chip8, count: usize) {
+        for _ in 0..count {
+            chip8.tick_timers();
+        }
+    }
+
+    #[test]
+    fn timers_start_at_zero() {
+        let chip8 = chip8_with_program(&[]);
+
+        assert_eq!(chip8.get_cpu().sound_timer, 0);
+        assert_eq!(chip8.get_cpu().delay_timer, 0);
+        assert!(!chip8.can_play_sound());
+    }
+
+    #[test]
+    fn fx18_beeps_until_the_sound_timer_reaches_zero() {
+        // LD V0, 3; LD ST, V0
+        let mut chip8 = chip8_with_program(&[0x60, 0x03, 0xF0, 0x18]);
+        run_steps(&mut chip8, 2);
+
+        for remaining in (1..=3).rev() {
+            assert_eq!(chip8.get_cpu().sound_timer, remaining);
+            assert!(chip8.can_play_sound());
+            tick_frames(&mut chip8, 1);
         }
-        if restart {
-            self.clock_update_timer.restart();
+
+        assert_eq!(chip8.get_cpu().sound_timer, 0);
+        assert!(!chip8.can_play_sound());
+
+        tick_frames(&mut chip8, 1);
+        assert_eq!(chip8.get_cpu().sound_timer, 0);
+    }
+
+    #[test]
+    fn fx18_with_one_beeps_for_one_frame() {
+        // LD V0, 1; LD ST, V0
+        let mut chip8 = chip8_with_program(&[0x60, 0x01, 0xF0, 0x18]);
+        run_steps(&mut chip8, 2);
+
+        assert!(chip8.can_play_sound());
+        tick_frames(&mut chip8, 1);
+        assert!(!chip8.can_play_sound());
+    }
+
+    #[test]
+    fn cosmac_minimum_beep_ignores_a_sound_timer_of_one() {
+        // LD V0, 1; LD ST, V0; LD V0, 2; LD ST, V0
+        let mut chip8 = chip8_with_program(&[0x60, 0x01, 0xF0, 0x18, 0x60, 0x02, 0xF0, 0x18]);
+        chip8.set_quirks(Quirks { cosmac_minimum_beep: true, ..Quirks::default() });
+
+        run_steps(&mut chip8, 2);
+        assert_eq!(chip8.get_cpu().sound_timer, 1);
+        assert!(!chip8.can_play_sound());
+
+        run_steps(&mut chip8, 2);
+        assert!(chip8.can_play_sound());
+        tick_frames(&mut chip8, 1);
+        assert_eq!(chip8.get_cpu().sound_timer, 1);
+        assert!(!chip8.can_play_sound());
+    }
+
+    #[test]
+    fn fx07_reads_the_delay_timer_set_by_fx15_across_frames() {
+        // LD V0, 10; LD DT, V0; LD V1, DT; LD V2, DT
+        let mut chip8 = chip8_with_program(&[0x60, 0x0A, 0xF0, 0x15, 0xF1, 0x07, 0xF2, 0x07]);
+        run_steps(&mut chip8, 2);
+
+        tick_frames(&mut chip8, 3);
+        run_steps(&mut chip8, 1);
+        assert_eq!(chip8.get_cpu().v[1], 7);
+
+        tick_frames(&mut chip8, 20);
+        run_steps(&mut chip8, 1);
+        assert_eq!(chip8.get_cpu().v[2], 0);
+    }
+
+    #[test]
+    fn fx0a_waits_for_the_key_release() {
+        // LD V0, K
+        let mut chip8 = chip8_with_program(&[0xF0, 0x0A]);
+
+        run_steps(&mut chip8, 1);
+        chip8.get_keypad_mut().set_key_pressed(Key::Num5, true);
+        run_steps(&mut chip8, 1);
+        assert_eq!(chip8.get_cpu().get_key_wait(), Some(&KeyWait::Release(Key::Num5)));
+
+        chip8.get_keypad_mut().set_key_pressed(Key::Num5, false);
+        run_steps(&mut chip8, 1);
+        assert_eq!(chip8.get_cpu().get_key_wait(), None);
+        assert_eq!(chip8.get_cpu().v[0], 0x5);
+        assert_eq!(chip8.get_cpu().pc, chip8.get_config().program_start + 2);
+    }
+
+    #[test]
+    fn fx0a_ignores_keys_held_before_the_wait() {
+        // LD V0, K
+        let mut chip8 = chip8_with_program(&[0xF0, 0x0A]);
+        chip8.set_quirks(Quirks { fx0a_wait_release: false, ..Quirks::default() });
+        chip8.get_keypad_mut().set_key_pressed(Key::Num1, true);
+
+        run_steps(&mut chip8, 2);
+        assert_eq!(chip8.get_cpu().pc, chip8.get_config().program_start);
+
+        chip8.get_keypad_mut().set_key_pressed(Key::A, true);
+        run_steps(&mut chip8, 1);
+        assert_eq!(chip8.get_cpu().v[0], 0xA);
+        assert_eq!(chip8.get_cpu().pc, chip8.get_config().program_start + 2);
+    }
+
+    #[test]
+    fn fx0a_wait_is_dropped_by_other_instructions() {
+        // LD V0, K; LD V1, 1; JP 0x200
+        let mut chip8 = chip8_with_program(&[0xF0, 0x0A, 0x61, 0x01, 0x12, 0x00]);
+        chip8.get_keypad_mut().set_key_pressed(Key::Num5, true);
+        run_steps(&mut chip8, 1);
+        chip8.get_keypad_mut().set_key_pressed(Key::Num1, true);
+        run_steps(&mut chip8, 1);
+        assert_eq!(chip8.get_cpu().get_key_wait(), Some(&KeyWait::Release(Key::Num1)));
+
+        chip8.get_cpu_mut().pc += 2;
+        run_steps(&mut chip8, 1);
+        assert_eq!(chip8.get_cpu().get_key_wait(), None);
+
+        // Back on FX0A, the keys still held are ignored again
+        run_steps(&mut chip8, 2);
+        assert_eq!(chip8.get_cpu().get_key_wait(), Some(&KeyWait::Press { ignored: chip8.get_keypad().status() }));
+    }
+
+    #[test]
+    fn display_wait_stalls_until_the_next_frame() {
+        // DRW V0, V0, 1; LD V1, 5
+        let mut chip8 = chip8_with_program(&[0xD0, 0x01, 0x61, 0x05]);
+        chip8.set_quirks(Quirks { display_wait: true, ..Quirks::default() });
+        chip8.set_cpu_frequency(600.0);
+
+        chip8.run_instruction().unwrap();
+        assert!(chip8.get_cpu().is_waiting_for_vblank());
+
+        let frame = chip8.get_frame_count();
+        while chip8.get_frame_count() == frame {
+            assert_eq!(chip8.get_cpu().v[1], 0);
+            chip8.run_instruction().unwrap();
         }
-        self.cpu.clock(&mut self.devices)
+        assert!(!chip8.get_cpu().is_waiting_for_vblank());
+
+        chip8.run_instruction().unwrap();
+        assert_eq!(chip8.get_cpu().v[1], 5);
+    }
+
+    #[test]
+    fn reset_clears_the_timers() {
+        // LD V0, 5; LD ST, V0; LD DT, V0
+        let mut chip8 = chip8_with_program(&[0x60, 0x05, 0xF0, 0x18, 0xF0, 0x15]);
+        run_steps(&mut chip8, 3);
+
+        chip8.reset();
+        assert_eq!(chip8.get_cpu().sound_timer, 0);
+        assert_eq!(chip8.get_cpu().delay_timer, 0);
+        assert!(!chip8.can_play_sound());
+    }
+
+    #[test]
+    fn cosmac_timing_runs_a_frame_of_machine_cycles() {
+        // loop: ADD V0, 1; JP loop
+        let mut chip8 = chip8_with_program(&[0x70, 0x01, 0x12, 0x00]);
+        chip8.set_timing_mode(TimingMode::CosmacVip);
+        chip8.run_frame().unwrap();
+
+        // 50 cycles for ADD and 52 for JP, the frame ends once 2598 cycles are spent.
+        assert_eq!(chip8.get_cpu().v[0], 26);
+        assert_eq!(chip8.get_frame_count(), 1);
+    }
+
+    #[test]
+    fn dxyn_records_the_last_draw() {
+        // LD V0, 60; LD V1, 3; LD F, V1; DRW V0, V1, 5; DRW V0, V1, 5
+        let mut chip8 = chip8_with_program(&[0x60, 0x3C, 0x61, 0x03, 0xF1, 0x29, 0xD0, 0x15, 0xD0, 0x15]);
+        run_steps(&mut chip8, 4);
+
+        let draw = *chip8.get_cpu().get_last_draw().unwrap();
+        assert_eq!((draw.address, draw.x, draw.y, draw.height), (chip8.get_font_address() + 15, 60, 3, 5));
+        assert!(!draw.collision);
+
+        run_steps(&mut chip8, 1);
+        assert!(chip8.get_cpu().get_last_draw().unwrap().collision);
+    }
+
+    #[test]
+    fn fx29_and_fx30_follow_the_font_address() {
+        // LD V0, 3; LD F, V0; LD HF, V0
+        let mut chip8 = chip8_with_program(&[0x60, 0x03, 0xF0, 0x29, 0xF0, 0x30]);
+        chip8.set_font_address(0x50).unwrap();
+        assert_eq!(chip8.get_config().font_address, 0x50);
+
+        run_steps(&mut chip8, 2);
+        assert_eq!(chip8.get_cpu().i, 0x50 + 3 * 5);
+        run_steps(&mut chip8, 1);
+        assert_eq!(chip8.get_cpu().i, 0x50 + 80 + 3 * 10);
+        assert_eq!(chip8.get_ram().read8(0x50).unwrap(), chip8.get_font().small[0]);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn coverage_tags_executed_read_and_written_bytes() {
+        // LD I, 0x300; LD B, V0; DRW V0, V0, 2; LD V1, [I]
+        let mut chip8 = chip8_with_program(&[0xA3, 0x00, 0xF0, 0x33, 0xD0, 0x02, 0xF1, 0x65]);
+        chip8.set_coverage_enabled(true);
+
+        run_steps(&mut chip8, 4);
+        let coverage = chip8.get_coverage();
+        assert_eq!(coverage.flags(0x200), EXECUTED);
+        assert_eq!(coverage.flags(0x207), EXECUTED);
+        assert_eq!(coverage.flags(0x208), 0);
+        assert_eq!(coverage.flags(0x300), WRITTEN | READ);
+        assert_eq!(coverage.flags(0x302), WRITTEN);
+        assert_eq!(coverage.flags(0x303), 0);
+    }
+
+    #[test]
+    fn run_frame_stops_before_a_hooked_address() {
+        // LD V0, 1; LD V1, 2; JP 0x204
+        let mut chip8 = chip8_with_program(&[0x60, 0x01, 0x61, 0x02, 0x12, 0x04]);
+        chip8.set_pc_hooks(BTreeSet::from([0x202]));
+
+        chip8.run_frame().unwrap();
+        assert_eq!(chip8.take_pc_hook(), Some(0x202));
+        assert_eq!(chip8.get_cpu().v[1], 0);
+        assert_eq!(chip8.get_frame_count(), 0);
+
+        chip8.run_frame().unwrap();
+        assert_eq!(chip8.take_pc_hook(), None);
+        assert_eq!(chip8.get_cpu().v[1], 2);
+        assert_eq!(chip8.get_frame_count(), 1);
+    }
+
+    #[test]
+    fn watches_stop_run_frame_after_each_change() {
+        // LD V0, 1; LD V1, 2; loop: ADD V1, 1; JP loop
+        let mut chip8 = chip8_with_program(&[0x60, 0x01, 0x61, 0x02, 0x71, 0x01, 0x12, 0x04]);
+        chip8.set_watches(vec![Location::Register(Register::V(1)), Location::Byte(0x300)]);
+
+        chip8.run_frame().unwrap();
+        assert!(chip8.take_watch_hit());
+        assert_eq!(chip8.get_cpu().pc, 0x204);
+
+        chip8.run_frame().unwrap();
+        assert!(chip8.take_watch_hit());
+        assert_eq!((chip8.get_cpu().pc, chip8.get_cpu().v[1]), (0x206, 3));
+        assert_eq!(chip8.get_frame_count(), 0);
+
+        chip8.set_watches(vec![Location::Byte(0x300)]);
+        chip8.run_frame().unwrap();
+        assert!(!chip8.take_watch_hit());
+        assert_eq!(chip8.get_frame_count(), 1);
+    }
+
+    #[test]
+    fn breakpoints_stop_run_frame_until_removed() {
+        // LD V0, 1; JP 0x200
+        let mut chip8 = chip8_with_program(&[0x60, 0x01, 0x12, 0x00]);
+        assert!(chip8.add_breakpoint(0x202));
+
+        chip8.run_frame().unwrap();
+        assert_eq!(chip8.take_breakpoint_hit(), Some(0x202));
+        assert_eq!(chip8.get_cpu().pc, 0x202);
+
+        assert!(chip8.remove_breakpoint(0x202));
+        chip8.run_frame().unwrap();
+        assert_eq!(chip8.take_breakpoint_hit(), None);
+        assert_eq!(chip8.get_frame_count(), 1);
+    }
+
+    #[test]
+    fn breakpoints_stop_on_the_current_pc_once() {
+        // LD V0, 1; JP 0x200
+        let mut chip8 = chip8_with_program(&[0x60, 0x01, 0x12, 0x00]);
+        chip8.add_breakpoint(0x200);
+        chip8.set_pc_hooks(BTreeSet::from([0x200]));
+
+        chip8.run_frame().unwrap();
+        assert_eq!(chip8.take_pc_hook(), Some(0x200));
+        assert_eq!(chip8.take_breakpoint_hit(), Some(0x200));
+        assert_eq!(chip8.get_cpu().v[0], 0);
+
+        chip8.run_frame().unwrap();
+        assert_eq!(chip8.take_breakpoint_hit(), Some(0x200));
+        assert_eq!(chip8.get_cpu().v[0], 1);
+        assert_eq!(chip8.get_cpu().pc, 0x200);
+        assert_eq!(chip8.take_pc_hook(), Some(0x200));
+
+        chip8.reload_program();
+        chip8.run_instruction().unwrap();
+        assert_eq!(chip8.take_breakpoint_hit(), Some(0x200));
+        assert_eq!(chip8.get_cpu().v[0], 0);
+        chip8.run_instruction().unwrap();
+        assert_eq!(chip8.get_cpu().v[0], 1);
+    }
+
+    #[test]
+    fn fetch_errors_halt_even_when_ignored() {
+        // JP 0xFFF
+        let mut chip8 = chip8_with_program(&[0x1F, 0xFF]);
+        chip8.set_error_policies(ErrorPolicies { memory: ErrorPolicy::Ignore, ..ErrorPolicies::default() });
+
+        chip8.step().unwrap();
+        let error = chip8.step().unwrap_err();
+        assert!(matches!(error.kind, Chip8ErrorKind::ProgramCounterOutOfBounds(0xFFF)));
+        assert!(chip8.get_error_log().is_empty());
+    }
+
+    #[test]
+    fn machine_configs_are_validated() {
+        for (_, config) in MachineConfig::presets() {
+            assert!(config.validate().is_ok());
+        }
+        assert!(MachineConfig { ram_size: 0x10000, ..MachineConfig::eti_660() }.validate().is_ok());
+
+        let error = MachineConfig { ram_size: 0, ..MachineConfig::default() }.validate().unwrap_err();
+        assert!(error.contains("RAM size"));
+        let error = MachineConfig { ram_size: 0x10001, ..MachineConfig::default() }.validate().unwrap_err();
+        assert!(error.contains("RAM size"));
+        let error = MachineConfig { program_start: 0x800, ram_size: 2048, ..MachineConfig::default() }.validate().unwrap_err();
+        assert!(error.contains("program start 0x800"));
+        let error = MachineConfig { font_address: 0x1A0, ..MachineConfig::default() }.validate().unwrap_err();
+        assert!(error.contains("fonts at 0x1A0"));
+    }
+
+    #[test]
+    fn ram_reset_keeps_the_interpreter_area() {
+        let mut ram = Ram::new(0x10000, 0x600);
+        assert_eq!(ram.size(), 0x10000);
+        ram.write_bytes(0x5FF, &[1, 2]).unwrap();
+        ram.write8(0xFFFF, 3).unwrap();
+
+        ram.reset();
+        assert_eq!(ram.read8(0x5FF).unwrap(), 1);
+        assert_eq!(ram.read8(0x600).unwrap(), 0);
+        assert_eq!(ram.read8(0xFFFF).unwrap(), 0);
+
+        let ram = Ram::new(2048, 0x200);
+        assert!(matches!(ram.read8(0x800), Err(Chip8ErrorKind::SegmentationFault(0x800))));
+    }
+
+    #[test]
+    fn programs_must_fit_after_the_program_start() {
+        let config = MachineConfig { ram_size: 0x10000, ..MachineConfig::eti_660() };
+        let mut chip8 = Chip8::new(config);
+        assert_eq!(chip8.get_cpu().pc, 0x600);
+
+        let error = chip8.load_program_bytes(&vec![0; 0x10000 - 0x600 + 1]).unwrap_err();
+        assert_eq!(error, "the program is 64001 bytes, only 64000 are available from 0x600");
+
+        let mut program = vec![0; 0x10000 - 0x600];
+        program[0] = 0x60;
+        program[1] = 0x05;
+        chip8.load_program_bytes(&program).unwrap();
+        assert_eq!(chip8.get_ram().read16(0x600).unwrap(), 0x6005);
+        assert!(chip8.can_run());
+        run_steps(&mut chip8, 1);
+        assert_eq!(chip8.get_cpu().v[0], 5);
+    }
+
+    fn emulate_all_errors(chip8: &mut Chip8) {
+        chip8.set_error_policies(ErrorPolicies {
+            memory: ErrorPolicy::Emulate,
+            stack: ErrorPolicy::Emulate,
+            unknown_instruction: ErrorPolicy::Emulate,
+            invalid_key: ErrorPolicy::Emulate,
+        });
+    }
+
+    #[test]
+    fn ignored_errors_are_logged_and_skipped() {
+        // Unknown 0xF0FF; LD V0, 5
+        let mut chip8 = chip8_with_program(&[0xF0, 0xFF, 0x60, 0x05]);
+        chip8.set_error_policies(ErrorPolicies { unknown_instruction: ErrorPolicy::Ignore, ..ErrorPolicies::default() });
+
+        run_steps(&mut chip8, 2);
+        assert_eq!(chip8.get_cpu().v[0], 5);
+        let error = chip8.get_error_log().front().unwrap();
+        assert!(matches!(error.kind, Chip8ErrorKind::UnknownInstruction(_)));
+        assert_eq!((error.pc, error.opcode), (0x200, Some(0xF0FF)));
+    }
+
+    #[test]
+    fn the_error_log_keeps_the_latest_errors() {
+        // loop: unknown 0xF0FF; JP loop
+        let mut chip8 = chip8_with_program(&[0xF0, 0xFF, 0x12, 0x00]);
+        chip8.set_error_policies(ErrorPolicies { unknown_instruction: ErrorPolicy::Ignore, ..ErrorPolicies::default() });
+
+        run_steps(&mut chip8, 2 * (ERROR_LOG_CAPACITY + 10));
+        assert_eq!(chip8.get_error_log().len(), ERROR_LOG_CAPACITY);
+        chip8.clear_error_log();
+        assert!(chip8.get_error_log().is_empty());
+    }
+
+    #[test]
+    fn emulated_memory_accesses_wrap_around() {
+        // LD V0, 1; LD V1, 2; LD I, 0xFFF; LD [I], V1
+        let mut chip8 = chip8_with_program(&[0x60, 0x01, 0x61, 0x02, 0xAF, 0xFF, 0xF1, 0x55]);
+        emulate_all_errors(&mut chip8);
+
+        run_steps(&mut chip8, 4);
+        assert_eq!(chip8.get_ram().read8(0xFFF).unwrap(), 1);
+        assert_eq!(chip8.get_ram().read8(0x1000).unwrap(), 2);
+        assert_eq!(chip8.get_ram().read8(0x000).unwrap(), 2);
+    }
+
+    #[test]
+    fn emulated_stack_overflows_wrap_around() {
+        // loop: CALL loop
+        let mut chip8 = chip8_with_program(&[0x22, 0x00]);
+        run_steps(&mut chip8, 16);
+        assert!(matches!(chip8.step().unwrap_err().kind, Chip8ErrorKind::StackOverflow));
+
+        let mut chip8 = chip8_with_program(&[0x22, 0x00]);
+        emulate_all_errors(&mut chip8);
+        run_steps(&mut chip8, 17);
+        assert_eq!(chip8.get_cpu().stack.sp, 1);
+        assert!(chip8.get_error_log().is_empty());
+    }
+
+    #[test]
+    fn emulated_unknown_opcodes_are_nops() {
+        // Unknown 0xF0FF; LD V0, 5
+        let mut chip8 = chip8_with_program(&[0xF0, 0xFF, 0x60, 0x05]);
+        emulate_all_errors(&mut chip8);
+
+        run_steps(&mut chip8, 2);
+        assert_eq!(chip8.get_cpu().v[0], 5);
+        assert!(chip8.get_error_log().is_empty());
+    }
+
+    #[test]
+    fn emulated_keys_use_the_low_nibble() {
+        // LD V0, 0x1A; SKP V0; LD V1, 1; SKNP V0; LD V2, 1
+        let program = [0x60, 0x1A, 0xE0, 0x9E, 0x61, 0x01, 0xE0, 0xA1, 0x62, 0x01];
+        let mut chip8 = chip8_with_program(&program);
+        chip8.step().unwrap();
+        assert!(matches!(chip8.step().unwrap_err().kind, Chip8ErrorKind::InvalidKey(0x1A)));
+
+        let mut chip8 = chip8_with_program(&program);
+        emulate_all_errors(&mut chip8);
+        chip8.get_keypad_mut().set_key_pressed(Key::try_from(0xA).unwrap(), true);
+        run_steps(&mut chip8, 4);
+        assert_eq!((chip8.get_cpu().v[1], chip8.get_cpu().v[2]), (0, 1));
+    }
+}
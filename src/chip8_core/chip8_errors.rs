@@ -3,17 +3,19 @@ use super::instruction::Instruction;
 
 #[derive(Debug)]
 pub enum Chip8ErrorKind {
-    SegmentationFault,
+    SegmentationFault(u16),
     UnknownInstruction(Instruction),
     EmptyStack,
     StackOverflow,
+    InvalidKey(u8),
+    ProgramCounterOutOfBounds(u16),
 }
 
 impl std::fmt::Display for Chip8ErrorKind {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Chip8ErrorKind::SegmentationFault => {
-                write!(f, "Segmentation fault")
+            Chip8ErrorKind::SegmentationFault(addr) => {
+                write!(f, "Segmentation fault, address {:#X}", addr)
             },
             Chip8ErrorKind::UnknownInstruction(instr) => {
                 write!(f, "Unknown instruction, opcode {:#X}", instr.opcode())
@@ -26,6 +28,96 @@ impl std::fmt::Display for Chip8ErrorKind {
                 write!(f, "Stack overflow")
 
             },
+            Chip8ErrorKind::InvalidKey(key) => {
+                write!(f, "Invalid key index {:#X}", key)
+            },
+            Chip8ErrorKind::ProgramCounterOutOfBounds(pc) => {
+                write!(f, "Program counter {:#X} ran past the end of RAM", pc)
+            },
+        }
+    }
+}
+
+/// An execution error together with the state of the machine at the faulting instruction.
+#[derive(Debug)]
+pub struct Chip8Error {
+    pub kind: Chip8ErrorKind,
+    pub pc: u16,
+    pub opcode: Option<u16>,
+    pub i: u16,
+    pub stack_depth: u8,
+}
+
+impl std::fmt::Display for Chip8Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} (PC = {:#X}", self.kind, self.pc)?;
+        if let Some(opcode) = self.opcode {
+            write!(f, ", opcode = {:#06X}", opcode)?;
+        }
+        write!(f, ", I = {:#X}, stack depth = {})", self.i, self.stack_depth)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ErrorClass {
+    Memory,
+    Stack,
+    UnknownInstruction,
+    InvalidKey,
+}
+
+impl Chip8ErrorKind {
+    pub fn class(&self) -> ErrorClass {
+        match self {
+            Chip8ErrorKind::SegmentationFault(_)
+            | Chip8ErrorKind::ProgramCounterOutOfBounds(_) => ErrorClass::Memory,
+            Chip8ErrorKind::EmptyStack
+            | Chip8ErrorKind::StackOverflow => ErrorClass::Stack,
+            Chip8ErrorKind::UnknownInstruction(_) => ErrorClass::UnknownInstruction,
+            Chip8ErrorKind::InvalidKey(_) => ErrorClass::InvalidKey,
         }
     }
-}
\ No newline at end of file
+}
+
+/// What the machine does when an error of a given class occurs.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ErrorPolicy {
+    /// Stop the execution and report the error.
+    Halt,
+    /// Log the error, abort the faulting instruction and keep running. An instruction
+    /// that cannot be fetched still halts.
+    Ignore,
+    /// Behave like the COSMAC VIP: addresses and the stack pointer wrap around,
+    /// unknown opcodes are NOPs and key indexes only use their low nibble.
+    Emulate,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ErrorPolicies {
+    pub memory: ErrorPolicy,
+    pub stack: ErrorPolicy,
+    pub unknown_instruction: ErrorPolicy,
+    pub invalid_key: ErrorPolicy,
+}
+
+impl ErrorPolicies {
+    pub fn get(&self, class: ErrorClass) -> ErrorPolicy {
+        match class {
+            ErrorClass::Memory => self.memory,
+            ErrorClass::Stack => self.stack,
+            ErrorClass::UnknownInstruction => self.unknown_instruction,
+            ErrorClass::InvalidKey => self.invalid_key,
+        }
+    }
+}
+
+impl Default for ErrorPolicies {
+    fn default() -> Self {
+        Self {
+            memory: ErrorPolicy::Halt,
+            stack: ErrorPolicy::Halt,
+            unknown_instruction: ErrorPolicy::Halt,
+            invalid_key: ErrorPolicy::Halt,
+        }
+    }
+}
@@ -0,0 +1,171 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+use super::instruction::Instruction;
+use super::symbols::parse_hex;
+
+pub const EXECUTED: u8 = 0b001;
+pub const READ: u8 = 0b010;
+pub const WRITTEN: u8 = 0b100;
+
+/// Tags every RAM byte that was executed as code, read as data or written.
+pub struct Coverage {
+    pub enabled: bool,
+    flags: Vec<u8>,
+}
+
+impl Coverage {
+    pub fn new(ram_size: usize) -> Self {
+        Self {
+            enabled: false,
+            flags: vec![0; ram_size],
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.flags.iter_mut().for_each(|flags| *flags = 0);
+    }
+
+    /// `EXECUTED`, `READ` and `WRITTEN` flags of the byte.
+    pub fn flags(&self, address: u16) -> u8 {
+        self.flags.get(address as usize).copied().unwrap_or(0)
+    }
+
+    fn mark(&mut self, start: u16, length: u16, flag: u8) {
+        let size = self.flags.len();
+        for offset in 0..length {
+            let address = start.wrapping_add(offset) as usize % size;
+            self.flags[address] |= flag;
+        }
+    }
+
+    /// Tags the bytes used by `instruction`, executed at `pc` with `i` the value of I
+    /// before the instruction.
+    pub(crate) fn record(&mut self, pc: u16, instruction: &Instruction, i: u16) {
+        self.mark(pc, 2, EXECUTED);
+
+        let x = instruction.x() as u16;
+        match (instruction.extract(0xF000, 12), instruction.nn()) {
+            (0xD, _) => self.mark(i, instruction.n() as u16, READ),
+            (0xF, 0x33) => self.mark(i, 3, WRITTEN),
+            (0xF, 0x55) => self.mark(i, x + 1, WRITTEN),
+            (0xF, 0x65) => self.mark(i, x + 1, READ),
+            _ => {},
+        }
+    }
+
+    /// Bytes with at least one of the given flags.
+    pub fn count(&self, flag: u8) -> usize {
+        self.flags.iter().filter(|flags| **flags & flag != 0).count()
+    }
+
+    /// lcov report with the ROM as source file and one line per instruction of the
+    /// program, the line number being the address. Bytes only ever read as data are
+    /// not counted as code.
+    pub fn write_lcov_by_address<W: Write>(&self, writer: &mut W, rom_name: &str, program_start: u16, program_length: usize) -> io::Result<()> {
+        let program_end = (program_start as usize + program_length).min(self.flags.len());
+        let mut lines = BTreeMap::new();
+
+        let mut address = program_start as usize;
+        while address < program_end {
+            let flags = self.flags[address];
+            let next_executed = self.flags.get(address + 1).is_some_and(|next| next & EXECUTED != 0);
+            if flags & EXECUTED != 0 {
+                lines.insert(address, 1);
+            }
+            else if flags & READ == 0 && !next_executed {
+                lines.insert(address, 0);
+            }
+            else {
+                // Data, or code reached through the next odd address.
+                address += 1;
+                continue;
+            }
+            address += 2;
+        }
+
+        write_lcov_record(writer, rom_name, &lines)
+    }
+
+    /// lcov report with one record per source file of the map, a line is covered when
+    /// one of its addresses was executed.
+    pub fn write_lcov_by_line<W: Write>(&self, writer: &mut W, source_map: &SourceMap) -> io::Result<()> {
+        let mut files: BTreeMap<&str, BTreeMap<usize, u32>> = BTreeMap::new();
+        for (address, (file, line)) in source_map.entries.iter() {
+            let hit = (self.flags(*address) & EXECUTED != 0) as u32;
+            let lines = files.entry(file.as_str()).or_default();
+            let count = lines.entry(*line as usize).or_insert(0);
+            *count = (*count).max(hit);
+        }
+
+        for (file, lines) in files {
+            write_lcov_record(writer, file, &lines)?;
+        }
+        Ok(())
+    }
+}
+
+fn write_lcov_record<W: Write>(writer: &mut W, source_file: &str, lines: &BTreeMap<usize, u32>) -> io::Result<()> {
+    writeln!(writer, "TN:")?;
+    writeln!(writer, "SF:{}", source_file)?;
+    for (line, count) in lines {
+        writeln!(writer, "DA:{},{}", line, count)?;
+    }
+    writeln!(writer, "LH:{}", lines.values().filter(|count| **count > 0).count())?;
+    writeln!(writer, "LF:{}", lines.len())?;
+    writeln!(writer, "end_of_record")
+}
+
+/// Source line of each instruction, written by an assembler as one `address file:line`
+/// entry per line, such as `0x200 game.8o:12`.
+#[derive(Debug, Clone, Default)]
+pub struct SourceMap {
+    entries: BTreeMap<u16, (String, u32)>,
+}
+
+impl SourceMap {
+    pub fn load(path: &Path) -> io::Result<SourceMap> {
+        let content = fs::read_to_string(path)?;
+        let mut entries = BTreeMap::new();
+
+        for (line_index, line) in content.lines().map(str::trim).enumerate() {
+            if line.is_empty() || line.starts_with(';') {
+                continue;
+            }
+
+            let entry = line.split_once(char::is_whitespace)
+            .and_then(|(address, location)| {
+                let address = parse_hex(address)?;
+                let (file, source_line) = location.trim().rsplit_once(':')?;
+                Some((address, (file.to_string(), source_line.parse::<u32>().ok()?)))
+            })
+            .ok_or_else(|| io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("line {}: expected 'address file:line', found '{}'", line_index + 1, line)
+            ))?;
+
+            entries.insert(entry.0, entry.1);
+        }
+
+        Ok(SourceMap { entries })
+    }
+
+    /// Source file and line of the instruction at this address.
+    pub fn location(&self, address: u16) -> Option<(&str, u32)> {
+        self.entries.get(&address).map(|(file, line)| (file.as_str(), *line))
+    }
+
+    /// First address of a source line, `matches` tells whether a file of the map is the
+    /// wanted source.
+    pub fn address_of<F: Fn(&str) -> bool>(&self, line: u32, matches: F) -> Option<u16> {
+        self.entries.iter()
+        .find(|(_, (file, entry_line))| *entry_line == line && matches(file))
+        .map(|(address, _)| *address)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+}
@@ -1,12 +1,17 @@
-use super::chip8_errors::Chip8ErrorKind;
-use super::{PROGRAM_START_ADDRESS, FONT_START_ADDRESS};
+use super::chip8_errors::{Chip8Error, Chip8ErrorKind, ErrorPolicies, ErrorPolicy};
+use super::fonts::{SMALL_FONT_SIZE, SMALL_GLYPH_HEIGHT, BIG_GLYPH_HEIGHT};
 use super::instruction::Instruction;
 use super::chip8::Devices;
 use super::graphics::{SCREEN_HEIGHT, SCREEN_WIDTH};
 use super::keypad::Key;
+use super::quirks::Quirks;
+use super::timing;
+use super::profiler::Profiler;
+
 pub struct Stack {
     pub sp: u8,
     pub stack: [u16; 16],
+    wrap: bool,
 }
 
 impl Stack {
@@ -14,12 +19,16 @@ impl Stack {
         Stack {
             sp: 0,
             stack: [0; 16],
+            wrap: false,
         }
     }
 
     fn push(&mut self, value: u16) -> Result<(), Chip8ErrorKind> {
         if self.sp >= self.stack.len() as u8 {
-            return Err(Chip8ErrorKind::StackOverflow);
+            if !self.wrap {
+                return Err(Chip8ErrorKind::StackOverflow);
+            }
+            self.sp = 0;
         }
         self.stack[self.sp as usize] = value;
         self.sp += 1;
@@ -29,7 +38,10 @@ impl Stack {
 
     fn pop(&mut self) -> Result<u16, Chip8ErrorKind> {
         if self.sp == 0 {
-            return Err(Chip8ErrorKind::EmptyStack)
+            if !self.wrap {
+                return Err(Chip8ErrorKind::EmptyStack)
+            }
+            self.sp = self.stack.len() as u8;
         }
         
         self.sp -= 1;
@@ -40,6 +52,28 @@ impl Stack {
         self.sp = 0;
         self.stack = [0; 16];
     }
+
+    pub fn frames(&self) -> &[u16] {
+        &self.stack[..self.sp as usize]
+    }
+}
+
+/// Progress of an `FX0A` instruction. Only keys pressed while waiting count, keys
+/// already held when the wait started are ignored until they are released.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum KeyWait {
+    Press { ignored: [bool; 16] },
+    Release(Key),
+}
+
+/// Where the last `DXYN` drew its sprite.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpriteDraw {
+    pub address: u16,
+    pub x: u8,
+    pub y: u8,
+    pub height: u8,
+    pub collision: bool,
 }
 
 pub struct CPU {
@@ -50,39 +84,126 @@ pub struct CPU {
     pub sound_timer: u8,
     pub delay_timer: u8,
 
-    key_pressed: Option<Key>,
+    key_wait: Option<KeyWait>,
+    waiting_for_vblank: bool,
+    error_policies: ErrorPolicies,
+    last_cycles: u32,
+    last_draw: Option<SpriteDraw>,
+    program_start: u16,
+    profiler: Profiler,
 }
 
 impl CPU {
-    pub fn new() -> Self {
+    pub fn new(program_start: u16) -> Self {
         Self {
             v: [0; 16],
             i: 0,
-            pc: PROGRAM_START_ADDRESS as u16,
+            pc: program_start,
             stack: Stack::new(),
-            sound_timer: 0x1,
+            sound_timer: 0x0,
             delay_timer: 0x0,
-            key_pressed: None,
+            key_wait: None,
+            waiting_for_vblank: false,
+            error_policies: ErrorPolicies::default(),
+            last_cycles: 0,
+            last_draw: None,
+            program_start,
+            profiler: Profiler::new(),
         }
     }
 
+    pub fn get_error_policies(&self) -> &ErrorPolicies {
+        &self.error_policies
+    }
+
+    pub(crate) fn set_error_policies(&mut self, error_policies: ErrorPolicies) {
+        self.error_policies = error_policies;
+        self.stack.wrap = error_policies.stack == ErrorPolicy::Emulate;
+    }
+
     pub(crate) fn reset(&mut self) {
         self.v = [0; 16];
         self.i = 0;
-        self.pc = PROGRAM_START_ADDRESS as u16;
+        self.pc = self.program_start;
         self.stack.reset();
-        self.sound_timer = 0x1;
+        self.sound_timer = 0x0;
         self.delay_timer = 0x0;
+        self.key_wait = None;
+        self.waiting_for_vblank = false;
+        self.last_cycles = 0;
+        self.last_draw = None;
+    }
+
+    /// Whether the CPU is stalled after a `DXYN` until the next frame, see `Quirks::display_wait`.
+    pub fn is_waiting_for_vblank(&self) -> bool {
+        self.waiting_for_vblank
     }
 
-    pub(crate) fn clock(&mut self, devices: &mut Devices) -> Result<(), Chip8ErrorKind> {
-        let instruction = self.fetch(devices)?;
+    pub(crate) fn vblank(&mut self) {
+        self.waiting_for_vblank = false;
+    }
+
+    pub fn get_last_draw(&self) -> Option<&SpriteDraw> {
+        self.last_draw.as_ref()
+    }
+
+    /// The key the CPU waits for when it is executing `FX0A`.
+    pub fn get_key_wait(&self) -> Option<&KeyWait> {
+        self.key_wait.as_ref()
+    }
+
+    pub fn get_profiler(&self) -> &Profiler {
+        &self.profiler
+    }
+
+    pub(crate) fn get_profiler_mut(&mut self) -> &mut Profiler {
+        &mut self.profiler
+    }
+
+    /// COSMAC VIP machine cycles taken by the last executed instruction.
+    pub fn get_last_cycles(&self) -> u32 {
+        self.last_cycles
+    }
+
+    pub(crate) fn clock(&mut self, devices: &mut Devices, quirks: &Quirks) -> Result<(), Chip8Error> {
+        let pc = self.pc;
+        let instruction = self.fetch(devices)
+        .map_err(|kind| self.make_error(kind, pc, None))?;
+
+        // A wait left by a jump of the debugger must not leak into the next FX0A
+        if instruction.opcode() & 0xF0FF != 0xF00A {
+            self.key_wait = None;
+        }
+
+        let vx = self.v[instruction.x() as usize];
+        let i = self.i;
+        self.execute(&instruction, devices, quirks)
+        .map_err(|kind| self.make_error(kind, pc, Some(instruction.opcode())))?;
+
+        let skipped = self.pc == pc.wrapping_add(4);
+        self.last_cycles = timing::cosmac_cycles(&instruction, vx, skipped);
+        if self.profiler.enabled {
+            self.profiler.record(pc, &instruction, self.last_cycles, self.stack.frames());
+        }
+        if devices.coverage.enabled {
+            devices.coverage.record(pc, &instruction, i);
+        }
+
+        Ok(())
+    }
 
-        self.execute(&instruction, devices)
+    fn make_error(&self, kind: Chip8ErrorKind, pc: u16, opcode: Option<u16>) -> Chip8Error {
+        Chip8Error {
+            kind,
+            pc,
+            opcode,
+            i: self.i,
+            stack_depth: self.stack.sp,
+        }
     }
 
     pub(crate) fn update_timers(&mut self) {
-        if self.sound_timer > 1 { // The original device's audio works for sound timer >= 0x2
+        if self.sound_timer > 0 {
             self.sound_timer -= 1;
         }
 
@@ -92,13 +213,20 @@ impl CPU {
     }
 
     fn fetch(&mut self, devices: &Devices) -> Result<Instruction, Chip8ErrorKind> {
+        let ram_size = devices.ram.size();
+        if self.pc as usize + 1 >= ram_size {
+            if self.error_policies.memory != ErrorPolicy::Emulate {
+                return Err(Chip8ErrorKind::ProgramCounterOutOfBounds(self.pc));
+            }
+            self.pc = (self.pc as usize % ram_size) as u16;
+        }
         let opcode = devices.ram.read16(self.pc)?;
-        self.pc += 2;
+        self.pc = self.pc.wrapping_add(2);
 
         Ok(Instruction::new(opcode))
     }
 
-    fn execute(&mut self, instruction: &Instruction, devices: &mut Devices) -> Result<(), Chip8ErrorKind> {
+    fn execute(&mut self, instruction: &Instruction, devices: &mut Devices, quirks: &Quirks) -> Result<(), Chip8ErrorKind> {
         let nnn = instruction.nnn();
         let nn = instruction.nn();
         let n = instruction.n();
@@ -134,21 +262,25 @@ impl CPU {
             (0xA, _, _, _) => self.instr_annn(nnn),
             (0xB, _, _, _) => self.instr_bnnn(nnn),
             (0xC, _, _, _) => self.instr_cxnn(x, nn),
-            (0xD, _, _, _) => self.instr_dxyn(x, y, n, devices),
+            (0xD, _, _, _) => self.instr_dxyn(x, y, n, devices, quirks),
 
             (0xE, _, _, 0xE) => self.instr_ex9e(x, devices),
             (0xE, _, _, 0x1) => self.instr_exa1(x, devices),
 
             (0xF, _, _, 0x7) => self.instr_fx07(x),
-            (0xF, _, _, 0xA) => self.instr_fx0a(x, devices),
+            (0xF, _, _, 0xA) => self.instr_fx0a(x, devices, quirks),
             (0xF, _, 0x1, 0x5) => self.instr_fx15(x),
             (0xF, _, _, 0x8) => self.instr_fx18(x),
             (0xF, _, _, 0xE) => self.instr_fx1e(x),
-            (0xF, _, _, 0x9) => self.instr_fx29(x),
+            (0xF, _, _, 0x9) => self.instr_fx29(x, devices),
+            (0xF, _, 0x3, 0x0) => self.instr_fx30(x, devices),
             (0xF, _, _, 0x3) => self.instr_fx33(x, devices),
             (0xF, _, 0x5, 0x5) => self.instr_fx55(x, devices),
             (0xF, _, 0x6, 0x5) => self.instr_fx65(x, devices),
             (_, _, _, _) => {
+                if self.error_policies.unknown_instruction == ErrorPolicy::Emulate {
+                    return Ok(());
+                }
                 return Err(Chip8ErrorKind::UnknownInstruction(Instruction::new(instruction.opcode())));
             }
         }
@@ -321,7 +453,9 @@ impl CPU {
         Ok(())
     }
 
-    fn instr_dxyn(&mut self, x: u8, y: u8, n: u8, devices: &mut Devices) -> Result<(), Chip8ErrorKind> {
+    fn instr_dxyn(&mut self, x: u8, y: u8, n: u8, devices: &mut Devices, quirks: &Quirks) -> Result<(), Chip8ErrorKind> {
+        self.waiting_for_vblank = quirks.display_wait;
+
         let startx = self.v[x as usize] % SCREEN_WIDTH as u8;
         let starty = self.v[y as usize] % SCREEN_HEIGHT as u8;
 
@@ -358,11 +492,19 @@ impl CPU {
             }
         }
 
+        self.last_draw = Some(SpriteDraw {
+            address: self.i,
+            x: startx,
+            y: starty,
+            height: n,
+            collision: self.v[0xF] == 1,
+        });
+
         Ok(())
     }
 
     fn instr_ex9e(&mut self, x: u8, devices: &Devices) -> Result<(), Chip8ErrorKind> {
-        let key = self.v[x as usize].try_into().unwrap();
+        let key = self.key_from_register(x)?;
         if devices.keypad.key_pressed(key) {
             self.pc += 2;
         }
@@ -371,7 +513,7 @@ impl CPU {
     }
 
     fn instr_exa1(&mut self, x: u8, devices: &Devices) -> Result<(), Chip8ErrorKind> {
-        let key = self.v[x as usize].try_into().unwrap();
+        let key = self.key_from_register(x)?;
         if !devices.keypad.key_pressed(key) {
             self.pc += 2;
         }
@@ -379,24 +521,64 @@ impl CPU {
         Ok(())
     }
 
+    fn key_from_register(&self, x: u8) -> Result<Key, Chip8ErrorKind> {
+        let mut vx = self.v[x as usize];
+        if self.error_policies.invalid_key == ErrorPolicy::Emulate {
+            vx &= 0xF;
+        }
+
+        Key::try_from(vx)
+        .map_err(|_| Chip8ErrorKind::InvalidKey(vx))
+    }
+
     fn instr_fx07(&mut self, x: u8) -> Result<(), Chip8ErrorKind> {
         self.v[x as usize] = self.delay_timer;
 
         Ok(())
     }
 
-    fn instr_fx0a(&mut self, x: u8, devices: &Devices) -> Result<(), Chip8ErrorKind> {
+    fn instr_fx0a(&mut self, x: u8, devices: &Devices, quirks: &Quirks) -> Result<(), Chip8ErrorKind> {
+        let keys_status = devices.keypad.status();
+        let key_wait = self.key_wait
+        .take()
+        .unwrap_or(KeyWait::Press { ignored: keys_status });
 
-        if let Some(key) = devices.keypad.get_key_pressed() {
-            self.key_pressed = Some(key);
-            self.pc -= 2;
-        }
-        else if let Some(key) = self.key_pressed {
-            self.v[x as usize] = key as u8;
-            self.key_pressed = None;
-        }
-        else {
-            self.pc -= 2;
+        let completed_key = match key_wait {
+            KeyWait::Press { mut ignored } => {
+                for (ignored, pressed) in ignored.iter_mut().zip(keys_status) {
+                    *ignored &= pressed;
+                }
+
+                let new_key = (0..16u8)
+                .find(|key| keys_status[*key as usize] && !ignored[*key as usize])
+                .map(|key| Key::try_from(key).unwrap());
+
+                match new_key {
+                    Some(key) if quirks.fx0a_wait_release => {
+                        self.key_wait = Some(KeyWait::Release(key));
+                        None
+                    },
+                    Some(key) => Some(key),
+                    None => {
+                        self.key_wait = Some(KeyWait::Press { ignored });
+                        None
+                    },
+                }
+            },
+            KeyWait::Release(key) => {
+                if devices.keypad.key_pressed(key) {
+                    self.key_wait = Some(KeyWait::Release(key));
+                    None
+                }
+                else {
+                    Some(key)
+                }
+            },
+        };
+
+        match completed_key {
+            Some(key) => self.v[x as usize] = key as u8,
+            None => self.pc -= 2,
         }
         
         Ok(())
@@ -420,9 +602,17 @@ impl CPU {
         Ok(())
     }
 
-    fn instr_fx29(&mut self, x: u8) -> Result<(), Chip8ErrorKind> {
-        self.i = (FONT_START_ADDRESS as u16)
-        .wrapping_add(self.v[x as usize].wrapping_mul(5) as u16);
+    fn instr_fx29(&mut self, x: u8, devices: &Devices) -> Result<(), Chip8ErrorKind> {
+        self.i = devices.font_address
+        .wrapping_add(self.v[x as usize].wrapping_mul(SMALL_GLYPH_HEIGHT as u8) as u16);
+
+        Ok(())
+    }
+
+    /// SCHIP big digit, 10 bytes per glyph after the small font.
+    fn instr_fx30(&mut self, x: u8, devices: &Devices) -> Result<(), Chip8ErrorKind> {
+        let digit = (self.v[x as usize] & 0xF) as u16;
+        self.i = devices.font_address + (SMALL_FONT_SIZE + BIG_GLYPH_HEIGHT * digit as usize) as u16;
 
         Ok(())
     }
@@ -0,0 +1,174 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+pub const FONT_GLYPHS: usize = 16;
+pub const SMALL_GLYPH_HEIGHT: usize = 5;
+pub const BIG_GLYPH_HEIGHT: usize = 10;
+pub const SMALL_FONT_SIZE: usize = FONT_GLYPHS * SMALL_GLYPH_HEIGHT;
+pub const BIG_FONT_SIZE: usize = FONT_GLYPHS * BIG_GLYPH_HEIGHT;
+/// Bytes taken in memory by a font: the small glyphs followed by the big ones.
+pub const FONT_SIZE: usize = SMALL_FONT_SIZE + BIG_FONT_SIZE;
+
+const DEFAULT_SMALL_FONT: [u8; SMALL_FONT_SIZE] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0,//0
+    0x20, 0x60, 0x20, 0x20, 0x70,//1
+    0xF0, 0x10, 0xF0, 0x80, 0xF0,//2
+    0xF0, 0x10, 0xF0, 0x10, 0xF0,//3
+    0x90, 0x90, 0xF0, 0x10, 0x10,//4
+    0xF0, 0x80, 0xF0, 0x10, 0xF0,//5
+    0xF0, 0x80, 0xF0, 0x90, 0xF0,//6
+    0xF0, 0x10, 0x20, 0x40, 0x40,//7
+    0xF0, 0x90, 0xF0, 0x90, 0xF0,//8
+    0xF0, 0x90, 0xF0, 0x10, 0xF0,//9
+    0xF0, 0x90, 0xF0, 0x90, 0x90,//A
+    0xE0, 0x90, 0xE0, 0x90, 0xE0,//B
+    0xF0, 0x80, 0x80, 0x80, 0xF0,//C
+    0xE0, 0x90, 0x90, 0x90, 0xE0,//D
+    0xF0, 0x80, 0xF0, 0x80, 0xF0,//E
+    0xF0, 0x80, 0xF0, 0x80, 0x80,//F
+];
+
+const VIP_SMALL_FONT: [u8; SMALL_FONT_SIZE] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0,//0
+    0x60, 0x20, 0x20, 0x20, 0x70,//1
+    0xF0, 0x10, 0xF0, 0x80, 0xF0,//2
+    0xF0, 0x10, 0xF0, 0x10, 0xF0,//3
+    0xA0, 0xA0, 0xF0, 0x20, 0x20,//4
+    0xF0, 0x80, 0xF0, 0x10, 0xF0,//5
+    0xF0, 0x80, 0xF0, 0x90, 0xF0,//6
+    0xF0, 0x10, 0x10, 0x10, 0x10,//7
+    0xF0, 0x90, 0xF0, 0x90, 0xF0,//8
+    0xF0, 0x90, 0xF0, 0x10, 0xF0,//9
+    0xF0, 0x90, 0xF0, 0x90, 0x90,//A
+    0xF0, 0x50, 0x70, 0x50, 0xF0,//B
+    0xF0, 0x80, 0x80, 0x80, 0xF0,//C
+    0xF0, 0x50, 0x50, 0x50, 0xF0,//D
+    0xF0, 0x80, 0xF0, 0x80, 0xF0,//E
+    0xF0, 0x80, 0xF0, 0x80, 0x80,//F
+];
+
+const DREAM_6800_SMALL_FONT: [u8; SMALL_FONT_SIZE] = [
+    0xE0, 0xA0, 0xA0, 0xA0, 0xE0,//0
+    0x40, 0x40, 0x40, 0x40, 0x40,//1
+    0xE0, 0x20, 0xE0, 0x80, 0xE0,//2
+    0xE0, 0x20, 0xE0, 0x20, 0xE0,//3
+    0x80, 0xA0, 0xA0, 0xE0, 0x20,//4
+    0xE0, 0x80, 0xE0, 0x20, 0xE0,//5
+    0xE0, 0x80, 0xE0, 0xA0, 0xE0,//6
+    0xE0, 0x20, 0x20, 0x20, 0x20,//7
+    0xE0, 0xA0, 0xE0, 0xA0, 0xE0,//8
+    0xE0, 0xA0, 0xE0, 0x20, 0xE0,//9
+    0xE0, 0xA0, 0xE0, 0xA0, 0xA0,//A
+    0xC0, 0xA0, 0xE0, 0xA0, 0xC0,//B
+    0xE0, 0x80, 0x80, 0x80, 0xE0,//C
+    0xC0, 0xA0, 0xA0, 0xA0, 0xC0,//D
+    0xE0, 0x80, 0xE0, 0x80, 0xE0,//E
+    0xE0, 0x80, 0xC0, 0x80, 0x80,//F
+];
+
+const ETI_660_SMALL_FONT: [u8; SMALL_FONT_SIZE] = [
+    0xE0, 0xA0, 0xA0, 0xA0, 0xE0,//0
+    0x20, 0x20, 0x20, 0x20, 0x20,//1
+    0xE0, 0x20, 0xE0, 0x80, 0xE0,//2
+    0xE0, 0x20, 0xE0, 0x20, 0xE0,//3
+    0xA0, 0xA0, 0xE0, 0x20, 0x20,//4
+    0xE0, 0x80, 0xE0, 0x20, 0xE0,//5
+    0xE0, 0x80, 0xE0, 0xA0, 0xE0,//6
+    0xE0, 0x20, 0x20, 0x20, 0x20,//7
+    0xE0, 0xA0, 0xE0, 0xA0, 0xE0,//8
+    0xE0, 0xA0, 0xE0, 0x20, 0xE0,//9
+    0xE0, 0xA0, 0xE0, 0xA0, 0xA0,//A
+    0x80, 0x80, 0xE0, 0xA0, 0xE0,//B
+    0xE0, 0x80, 0x80, 0x80, 0xE0,//C
+    0x20, 0x20, 0xE0, 0xA0, 0xE0,//D
+    0xE0, 0x80, 0xE0, 0x80, 0xE0,//E
+    0xE0, 0x80, 0xC0, 0x80, 0x80,//F
+];
+
+/// SCHIP 1.1 big digits, completed with the A-F glyphs used by Octo.
+const SCHIP_BIG_FONT: [u8; BIG_FONT_SIZE] = [
+    0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF,//0
+    0x0C, 0x0C, 0x3C, 0x3C, 0x0C, 0x0C, 0x0C, 0x0C, 0x3F, 0x3F,//1
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF,//2
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF,//3
+    0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0x03, 0x03,//4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF,//5
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF,//6
+    0xFF, 0xFF, 0x03, 0x03, 0x06, 0x06, 0x0C, 0x0C, 0x18, 0x18,//7
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF,//8
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF,//9
+    0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3,//A
+    0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC,//B
+    0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C,//C
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC,//D
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF,//E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0,//F
+];
+
+/// Hexadecimal digits drawn by `FX29` (small) and `FX30` (big).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Font {
+    pub name: String,
+    pub small: [u8; SMALL_FONT_SIZE],
+    pub big: [u8; BIG_FONT_SIZE],
+}
+
+impl Default for Font {
+    fn default() -> Self {
+        Self::new("Default", DEFAULT_SMALL_FONT)
+    }
+}
+
+impl Font {
+    pub fn new(name: &str, small: [u8; SMALL_FONT_SIZE]) -> Self {
+        Self {
+            name: String::from(name),
+            small,
+            big: SCHIP_BIG_FONT,
+        }
+    }
+
+    /// Fonts of the original interpreters.
+    pub fn built_in() -> Vec<Font> {
+        vec![
+            Font::default(),
+            Font::new("COSMAC VIP", VIP_SMALL_FONT),
+            Font::new("DREAM 6800", DREAM_6800_SMALL_FONT),
+            Font::new("ETI-660", ETI_660_SMALL_FONT),
+        ]
+    }
+
+    /// Reads a raw font file: the 80 bytes of the small font, optionally followed by
+    /// the 160 bytes of the big font. The default big font is used when it is missing.
+    pub fn load(path: &Path) -> io::Result<Font> {
+        let data = fs::read(path)?;
+        let name = path.file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+        let mut font = Font::new(&name, DEFAULT_SMALL_FONT);
+        match data.len() {
+            SMALL_FONT_SIZE => {
+                font.small.copy_from_slice(&data);
+            },
+            FONT_SIZE => {
+                font.small.copy_from_slice(&data[..SMALL_FONT_SIZE]);
+                font.big.copy_from_slice(&data[SMALL_FONT_SIZE..]);
+            },
+            size => return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("a font file is {} or {} bytes long, found {}", SMALL_FONT_SIZE, FONT_SIZE, size)
+            )),
+        }
+
+        Ok(font)
+    }
+
+    /// The font as laid out in memory.
+    pub fn bytes(&self) -> Vec<u8> {
+        let mut bytes = self.small.to_vec();
+        bytes.extend_from_slice(&self.big);
+        bytes
+    }
+}
@@ -83,6 +83,7 @@ impl Instruction {
             (0xF, _, _, 0x8) => format!("LD ST, V{:X}", x),
             (0xF, _, _, 0xE) => format!("ADD I, V{:X}", x),
             (0xF, _, _, 0x9) => format!("LD F, V{:X}", x),
+            (0xF, _, 0x3, 0x0) => format!("LD HF, V{:X}", x),
             (0xF, _, _, 0x3) => format!("LD B, V{:X}", x),
             (0xF, _, 0x5, 0x5) => format!("LD [I], V{:X}", x),
             (0xF, _, 0x6, 0x5) => format!("LD V{:X}, [I]", x),
@@ -1,4 +1,4 @@
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 #[repr(u8)]
 pub enum Key {
     Num0 = 0x0,
@@ -76,12 +76,7 @@ impl KeyPad {
         self.keys_status[key as usize] = pressed;
     }
 
-    pub fn get_key_pressed(&self) -> Option<Key> {
-        for (i, pressed) in self.keys_status.iter().enumerate() {
-            if *pressed {
-                return Some(Key::try_from(i as u8).unwrap());
-            }
-        }
-        None
+    pub fn status(&self) -> [bool; 16] {
+        self.keys_status
     }
 }
\ No newline at end of file
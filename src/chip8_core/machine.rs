@@ -0,0 +1,60 @@
+use super::fonts::FONT_SIZE;
+use super::memory::{MAX_RAM_SIZE, RAM_SIZE};
+use super::{FONT_START_ADDRESS, PROGRAM_START_ADDRESS};
+
+/// Memory layout of the emulated machine.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MachineConfig {
+    /// Where programs are loaded and where the PC starts.
+    pub program_start: u16,
+    /// Where the small font is written, the big font follows it.
+    pub font_address: u16,
+    pub ram_size: usize,
+}
+
+impl Default for MachineConfig {
+    fn default() -> Self {
+        Self {
+            program_start: PROGRAM_START_ADDRESS as u16,
+            font_address: FONT_START_ADDRESS as u16,
+            ram_size: RAM_SIZE,
+        }
+    }
+}
+
+impl MachineConfig {
+    /// ETI-660 programs are loaded at 0x600.
+    pub fn eti_660() -> Self {
+        Self {
+            program_start: 0x600,
+            ..Self::default()
+        }
+    }
+
+    pub fn presets() -> Vec<(&'static str, MachineConfig)> {
+        vec![
+            ("CHIP-8", MachineConfig::default()),
+            ("ETI-660", MachineConfig::eti_660()),
+        ]
+    }
+
+    /// Checks that the program starts inside the RAM and that the font fits before it.
+    /// The error names the broken rule.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.ram_size == 0 || self.ram_size > MAX_RAM_SIZE {
+            return Err(format!("the RAM size must be between 1 and {} bytes, not {}", MAX_RAM_SIZE, self.ram_size));
+        }
+        if self.program_start as usize >= self.ram_size {
+            return Err(format!("the program start {:#X} is past the end of the {} bytes of RAM", self.program_start, self.ram_size));
+        }
+
+        if self.font_address as usize + FONT_SIZE > self.program_start as usize {
+            return Err(format!(
+                "the fonts at {:#X} need {} bytes and overlap the program start {:#X}",
+                self.font_address, FONT_SIZE, self.program_start,
+            ));
+        }
+
+        Ok(())
+    }
+}
@@ -1,21 +1,33 @@
-use super::PROGRAM_START_ADDRESS;
 use super::chip8_errors::Chip8ErrorKind;
 
+/// Default RAM size.
 pub const RAM_SIZE: usize = 1024 * 4;
+/// Everything a 16 bits address can reach.
+pub const MAX_RAM_SIZE: usize = 0x10000;
 
 pub struct Ram {
-    memory: [u8; RAM_SIZE],
+    memory: Vec<u8>,
+    program_start: usize,
+    pub(crate) wrap_addresses: bool,
 }
 
 impl Ram {
-    pub fn new() -> Self {
+    pub fn new(size: usize, program_start: usize) -> Self {
         Self {
-            memory: [0; RAM_SIZE],
+            memory: vec![0; size],
+            program_start,
+            wrap_addresses: false,
         }
     }
 
+    pub fn size(&self) -> usize {
+        self.memory.len()
+    }
+
+    /// Clears the memory from the program start, the interpreter area and its font
+    /// are kept.
     pub fn reset(&mut self) {
-        self.reset_range(PROGRAM_START_ADDRESS, RAM_SIZE);
+        self.reset_range(self.program_start, self.size());
     }
 
     pub fn reset_range(&mut self, start: usize, end: usize) {
@@ -24,42 +36,43 @@ impl Ram {
         }
     }
 
-    pub fn read8(&self, addr: u16) -> Result<u8, Chip8ErrorKind> {
-        if addr >= RAM_SIZE as u16{
-            return Err(Chip8ErrorKind::SegmentationFault);
+    fn translate(&self, addr: u16) -> Result<usize, Chip8ErrorKind> {
+        let index = addr as usize;
+        if index < self.size() {
+            Ok(index)
         }
-        Ok(self.memory[addr as usize])
+        else if self.wrap_addresses {
+            Ok(index % self.size())
+        }
+        else {
+            Err(Chip8ErrorKind::SegmentationFault(addr))
+        }
+    }
+
+    pub fn read8(&self, addr: u16) -> Result<u8, Chip8ErrorKind> {
+        let index = self.translate(addr)?;
+        Ok(self.memory[index])
     }
 
     pub fn write8(&mut self, addr: u16, val: u8) -> Result<(), Chip8ErrorKind>{
-        if addr >= RAM_SIZE as u16{
-            return Err(Chip8ErrorKind::SegmentationFault);
-        }
-        self.memory[addr as usize] = val;
+        let index = self.translate(addr)?;
+        self.memory[index] = val;
         Ok(())
     }
 
     pub fn read16(&self, addr: u16) -> Result<u16, Chip8ErrorKind> {
 
         let hi = self.read8(addr)? as u16;
-        let lo = self.read8(addr + 1)? as u16;
+        let lo = self.read8(addr.wrapping_add(1))? as u16;
 
         Ok((hi << 8) | lo)
     }
 
     pub fn write_bytes(&mut self, start_addr: u16, bytes: &[u8]) -> Result<(), Chip8ErrorKind>{
         for (i, byte) in bytes.iter().enumerate() {
-            self.write8(start_addr + i as u16, *byte)?;
+            self.write8(start_addr.wrapping_add(i as u16), *byte)?;
         }
 
         Ok(())
     }
-
-    pub fn read_bytes(&self, start_addr: u16, end_addr: u16) -> Result<&[u8], Chip8ErrorKind> {
-        let ram_size = RAM_SIZE as u16;
-        if start_addr >= ram_size || end_addr >= ram_size || start_addr >= end_addr {
-            return Err(Chip8ErrorKind::SegmentationFault)
-        }
-        Ok(&self.memory[start_addr as usize..end_addr as usize])
-    }
 }
\ No newline at end of file
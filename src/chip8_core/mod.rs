@@ -1,4 +1,6 @@
+/// Default font address, see `machine::MachineConfig`.
 pub const FONT_START_ADDRESS: usize = 0x0;
+/// Default program start, see `machine::MachineConfig`.
 pub const PROGRAM_START_ADDRESS: usize = 0x200;
 
 
@@ -9,3 +11,10 @@ pub mod keypad;
 pub mod graphics;
 pub mod chip8;
 pub mod chip8_errors;
+pub mod quirks;
+pub mod timing;
+pub mod symbols;
+pub mod fonts;
+pub mod machine;
+pub mod profiler;
+pub mod coverage;
@@ -0,0 +1,318 @@
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+use super::instruction::Instruction;
+use super::memory::Ram;
+
+/// Executions and COSMAC VIP machine cycles spent at an address.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct AddressProfile {
+    pub executions: u64,
+    pub cycles: u64,
+}
+
+/// A backward jump: the code between `start` and `end` ran `iterations` times.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HotLoop {
+    pub start: u16,
+    pub end: u16,
+    pub iterations: u64,
+    /// Instructions executed between `start` and `end`, jump included.
+    pub executions: u64,
+}
+
+/// Counts the executed instructions per address, per opcode class and per call stack.
+pub struct Profiler {
+    pub enabled: bool,
+
+    total_executions: u64,
+    addresses: HashMap<u16, AddressProfile>,
+    classes: HashMap<&'static str, u64>,
+    backward_jumps: HashMap<(u16, u16), u64>,
+    /// Executions per call stack, keyed by the return addresses of `Stack`.
+    stacks: HashMap<Vec<u16>, u64>,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Self {
+            enabled: false,
+            total_executions: 0,
+            addresses: HashMap::new(),
+            classes: HashMap::new(),
+            backward_jumps: HashMap::new(),
+            stacks: HashMap::new(),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.total_executions = 0;
+        self.addresses.clear();
+        self.classes.clear();
+        self.backward_jumps.clear();
+        self.stacks.clear();
+    }
+
+    pub(crate) fn record(&mut self, pc: u16, instruction: &Instruction, cycles: u32, frames: &[u16]) {
+        self.total_executions += 1;
+
+        let address = self.addresses.entry(pc).or_default();
+        address.executions += 1;
+        address.cycles += cycles as u64;
+
+        *self.classes.entry(opcode_class(instruction)).or_default() += 1;
+
+        if instruction.extract(0xF000, 12) == 0x1 && instruction.nnn() <= pc {
+            *self.backward_jumps.entry((instruction.nnn(), pc)).or_default() += 1;
+        }
+
+        match self.stacks.get_mut(frames) {
+            Some(count) => *count += 1,
+            None => {
+                self.stacks.insert(frames.to_vec(), 1);
+            },
+        }
+    }
+
+    pub fn total_executions(&self) -> u64 {
+        self.total_executions
+    }
+
+    pub fn address(&self, address: u16) -> Option<&AddressProfile> {
+        self.addresses.get(&address)
+    }
+
+    /// Executions of the most executed address, used to scale heatmaps.
+    pub fn max_executions(&self) -> u64 {
+        self.addresses.values()
+        .map(|address| address.executions)
+        .max()
+        .unwrap_or(0)
+    }
+
+    /// Addresses sorted from the most executed.
+    pub fn hot_addresses(&self) -> Vec<(u16, AddressProfile)> {
+        let mut addresses: Vec<(u16, AddressProfile)> = self.addresses.iter()
+        .map(|(address, profile)| (*address, *profile))
+        .collect();
+        addresses.sort_by(|a, b| b.1.executions.cmp(&a.1.executions).then(a.0.cmp(&b.0)));
+        addresses
+    }
+
+    /// Opcode classes sorted from the most executed.
+    pub fn classes(&self) -> Vec<(&'static str, u64)> {
+        let mut classes: Vec<(&'static str, u64)> = self.classes.iter()
+        .map(|(class, count)| (*class, *count))
+        .collect();
+        classes.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        classes
+    }
+
+    /// The `count` loops in which the most instructions were executed.
+    pub fn hot_loops(&self, count: usize) -> Vec<HotLoop> {
+        let mut loops: Vec<HotLoop> = self.backward_jumps.iter()
+        .map(|((start, end), iterations)| HotLoop {
+            start: *start,
+            end: *end,
+            iterations: *iterations,
+            executions: self.addresses.iter()
+            .filter(|(address, _)| (*start..=*end).contains(*address))
+            .map(|(_, profile)| profile.executions)
+            .sum(),
+        })
+        .collect();
+        loops.sort_by(|a, b| b.executions.cmp(&a.executions).then(a.start.cmp(&b.start)));
+        loops.truncate(count);
+        loops
+    }
+
+    /// One line per executed address: address, name, opcode, instruction, executions,
+    /// cycles and share of the executions.
+    pub fn write_csv<W: Write, F: Fn(u16) -> String>(&self, writer: &mut W, ram: &Ram, name: F) -> io::Result<()> {
+        writeln!(writer, "address,name,opcode,instruction,executions,cycles,percent")?;
+        for (address, profile) in self.hot_addresses() {
+            let opcode = ram.read16(address).unwrap_or(0);
+            writeln!(
+                writer,
+                "{:#05X},{},{:04X},{},{},{},{:.3}",
+                address,
+                csv_field(&name(address)),
+                opcode,
+                Instruction::new(opcode).to_string().replace(',', ""),
+                profile.executions,
+                profile.cycles,
+                100.0 * profile.executions as f64 / self.total_executions.max(1) as f64
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Folded stacks as read by flamegraph tools: `main;sub_a;sub_b count`. Functions
+    /// are named after the target of the `CALL` preceding each return address.
+    pub fn write_folded<W: Write, F: Fn(u16) -> String>(&self, writer: &mut W, ram: &Ram, name: F) -> io::Result<()> {
+        let mut lines: Vec<(String, u64)> = self.stacks.iter()
+        .map(|(frames, count)| {
+            let mut stack = String::from("main");
+            for return_address in frames {
+                let function = ram.read16(return_address.wrapping_sub(2))
+                .map(|opcode| folded_frame(&name(Instruction::new(opcode).nnn())))
+                .unwrap_or_else(|_| format!("{:#05X}", return_address));
+                stack += ";";
+                stack += &function;
+            }
+            (stack, *count)
+        })
+        .collect();
+        lines.sort();
+
+        for (stack, count) in lines {
+            writeln!(writer, "{} {}", stack, count)?;
+        }
+        Ok(())
+    }
+}
+
+/// Quotes a CSV field holding a separator, a quote or a line break.
+fn csv_field(field: &str) -> String {
+    if field.contains(|c| matches!(c, ',' | '"' | '\n' | '\r')) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    }
+    else {
+        field.to_string()
+    }
+}
+
+/// Folded stacks split the frames on `;` and the count on the last space.
+fn folded_frame(name: &str) -> String {
+    name.replace(|c: char| c == ';' || c.is_whitespace(), "_")
+}
+
+/// Mnemonic family of an instruction, `LD` covers every load.
+pub fn opcode_class(instruction: &Instruction) -> &'static str {
+    match (instruction.extract(0xF000, 12), instruction.n(), instruction.nn()) {
+        (0x0, _, 0xE0) => "CLS",
+        (0x0, _, 0xEE) => "RET",
+        (0x0, _, _) => "SYS",
+        (0x1, _, _) => "JP",
+        (0x2, _, _) => "CALL",
+        (0x3, _, _) | (0x5, _, _) => "SE",
+        (0x4, _, _) | (0x9, _, _) => "SNE",
+        (0x6, _, _) | (0x8, 0x0, _) | (0xA, _, _) => "LD",
+        (0x7, _, _) | (0x8, 0x4, _) => "ADD",
+        (0x8, 0x1, _) => "OR",
+        (0x8, 0x2, _) => "AND",
+        (0x8, 0x3, _) => "XOR",
+        (0x8, 0x5, _) | (0x8, 0x7, _) => "SUB",
+        (0x8, 0x6, _) | (0x8, 0xE, _) => "SHIFT",
+        (0xB, _, _) => "JP V0",
+        (0xC, _, _) => "RND",
+        (0xD, _, _) => "DRW",
+        (0xE, _, _) => "SKP",
+        (0xF, _, 0x1E) => "ADD",
+        (0xF, _, 0x0A) => "LD K",
+        (0xF, _, 0x55) | (0xF, _, 0x65) => "LD [I]",
+        (0xF, _, 0x33) => "LD B",
+        (0xF, _, _) => "LD",
+        _ => "UNK",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 0x200: LD V0, 1; loop: CALL sub; JP loop; sub: RET
+    const PROGRAM: [u8; 8] = [0x60, 0x01, 0x22, 0x06, 0x12, 0x02, 0x00, 0xEE];
+
+    fn profile_program() -> (Profiler, Ram) {
+        let mut ram = Ram::new(0x1000, 0x200);
+        ram.write_bytes(0x200, &PROGRAM).unwrap();
+
+        let mut profiler = Profiler::new();
+        profiler.record(0x200, &Instruction::new(0x6001), 6, &[]);
+        for _ in 0..3 {
+            profiler.record(0x202, &Instruction::new(0x2206), 20, &[]);
+            profiler.record(0x206, &Instruction::new(0x00EE), 10, &[0x204]);
+            profiler.record(0x204, &Instruction::new(0x1202), 12, &[]);
+        }
+        (profiler, ram)
+    }
+
+    fn name(address: u16) -> String {
+        if address == 0x206 { String::from("sub") } else { String::new() }
+    }
+
+    #[test]
+    fn record_counts_executions_and_cycles() {
+        let (profiler, _) = profile_program();
+
+        assert_eq!(profiler.total_executions(), 10);
+        assert_eq!(profiler.address(0x202), Some(&AddressProfile { executions: 3, cycles: 60 }));
+        assert_eq!(profiler.address(0x208), None);
+        assert_eq!(profiler.max_executions(), 3);
+        assert_eq!(profiler.classes(), vec![("CALL", 3), ("JP", 3), ("RET", 3), ("LD", 1)]);
+
+        let hot_addresses: Vec<u16> = profiler.hot_addresses().iter().map(|(address, _)| *address).collect();
+        assert_eq!(hot_addresses, vec![0x202, 0x204, 0x206, 0x200]);
+    }
+
+    #[test]
+    fn hot_loops_come_from_backward_jumps() {
+        let (profiler, _) = profile_program();
+
+        assert_eq!(profiler.hot_loops(5), vec![HotLoop { start: 0x202, end: 0x204, iterations: 3, executions: 6 }]);
+        assert!(profiler.hot_loops(0).is_empty());
+    }
+
+    #[test]
+    fn opcode_classes_group_the_mnemonics() {
+        assert_eq!(opcode_class(&Instruction::new(0x00E0)), "CLS");
+        assert_eq!(opcode_class(&Instruction::new(0x00EE)), "RET");
+        assert_eq!(opcode_class(&Instruction::new(0x8124)), "ADD");
+        assert_eq!(opcode_class(&Instruction::new(0xF11E)), "ADD");
+        assert_eq!(opcode_class(&Instruction::new(0x810E)), "SHIFT");
+        assert_eq!(opcode_class(&Instruction::new(0xF155)), "LD [I]");
+        assert_eq!(opcode_class(&Instruction::new(0xF10A)), "LD K");
+        assert_eq!(opcode_class(&Instruction::new(0xF107)), "LD");
+    }
+
+    #[test]
+    fn write_csv_lists_the_hot_addresses() {
+        let (profiler, ram) = profile_program();
+        let mut csv = Vec::new();
+        profiler.write_csv(&mut csv, &ram, name).unwrap();
+
+        let csv = String::from_utf8(csv).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 5);
+        assert_eq!(lines[0], "address,name,opcode,instruction,executions,cycles,percent");
+        assert_eq!(lines[1], "0x202,,2206,CALL 0x206,3,60,30.000");
+        assert_eq!(lines[3], "0x206,sub,00EE,RET,3,30,30.000");
+    }
+
+    #[test]
+    fn write_folded_names_the_called_functions() {
+        let (profiler, ram) = profile_program();
+        let mut folded = Vec::new();
+        profiler.write_folded(&mut folded, &ram, name).unwrap();
+
+        assert_eq!(String::from_utf8(folded).unwrap(), "main 7\nmain;sub 3\n");
+    }
+
+    fn odd_name(address: u16) -> String {
+        if address == 0x206 { String::from("my \"sub\";, 2") } else { String::new() }
+    }
+
+    #[test]
+    fn exports_escape_the_names() {
+        let (profiler, ram) = profile_program();
+        let mut csv = Vec::new();
+        profiler.write_csv(&mut csv, &ram, odd_name).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        assert_eq!(csv.lines().nth(3), Some("0x206,\"my \"\"sub\"\";, 2\",00EE,RET,3,30,30.000"));
+
+        let mut folded = Vec::new();
+        profiler.write_folded(&mut folded, &ram, odd_name).unwrap();
+        assert_eq!(String::from_utf8(folded).unwrap(), "main 7\nmain;my_\"sub\"_,_2 3\n");
+    }
+}
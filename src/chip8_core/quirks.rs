@@ -0,0 +1,23 @@
+/// Behaviours that differ between CHIP-8 interpreters.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quirks {
+    /// On the COSMAC VIP the buzzer only responds to sound timer values of 2 or more,
+    /// setting the timer to 1 is silent.
+    pub cosmac_minimum_beep: bool,
+    /// `FX0A` completes when the pressed key is released (COSMAC VIP) instead of as soon
+    /// as it is pressed (SCHIP).
+    pub fx0a_wait_release: bool,
+    /// `DXYN` waits for the next vertical blank (COSMAC VIP), so a program draws at most
+    /// one sprite per 60 Hz frame.
+    pub display_wait: bool,
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Self {
+            cosmac_minimum_beep: false,
+            fx0a_wait_release: true,
+            display_wait: false,
+        }
+    }
+}
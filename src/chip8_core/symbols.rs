@@ -0,0 +1,331 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use super::cpu::CPU;
+
+pub const LABELS_EXTENSION: &str = "labels";
+
+/// A register as the debuggers name it: `v0` to `vf`, `i`, `pc`, `sp`, `dt` or `st`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Register {
+    V(usize),
+    I,
+    Pc,
+    Sp,
+    Dt,
+    St,
+}
+
+impl Register {
+    /// Reads a register name in any case.
+    pub fn parse(name: &str) -> Option<Self> {
+        let name = name.to_ascii_lowercase();
+        let register = match name.as_str() {
+            "i" => Register::I,
+            "pc" => Register::Pc,
+            "sp" => Register::Sp,
+            "dt" => Register::Dt,
+            "st" => Register::St,
+            _ => {
+                let index = name.strip_prefix('v').filter(|index| index.len() == 1)?;
+                Register::V(usize::from_str_radix(index, 16).ok()?)
+            },
+        };
+        Some(register)
+    }
+
+    /// Whether the register holds an address rather than a byte.
+    pub fn holds_address(self) -> bool {
+        matches!(self, Register::I | Register::Pc)
+    }
+
+    pub fn read(self, cpu: &CPU) -> u16 {
+        match self {
+            Register::V(index) => cpu.v[index] as u16,
+            Register::I => cpu.i,
+            Register::Pc => cpu.pc,
+            Register::Sp => cpu.stack.sp as u16,
+            Register::Dt => cpu.delay_timer as u16,
+            Register::St => cpu.sound_timer as u16,
+        }
+    }
+
+    /// Fails when the value does not fit in the register, or for the stack pointer.
+    pub fn write(self, cpu: &mut CPU, value: u16) -> Result<(), String> {
+        let byte = u8::try_from(value).map_err(|_| format!("{:#X} does not fit in a byte", value));
+        match self {
+            Register::V(index) => cpu.v[index] = byte?,
+            Register::I => cpu.i = value,
+            Register::Pc => cpu.pc = value,
+            Register::Sp => return Err(String::from("the stack pointer only moves with CALL and RET")),
+            Register::Dt => cpu.delay_timer = byte?,
+            Register::St => cpu.sound_timer = byte?,
+        }
+        Ok(())
+    }
+}
+
+/// Names and comments attached to memory addresses, shown by the debuggers.
+#[derive(Debug, Clone, Default)]
+pub struct SymbolTable {
+    labels: BTreeMap<u16, String>,
+    comments: BTreeMap<u16, String>,
+}
+
+impl SymbolTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Labels and comments file stored next to a ROM.
+    pub fn rom_labels_path(rom: &Path) -> PathBuf {
+        rom.with_extension(LABELS_EXTENSION)
+    }
+
+    pub fn clear(&mut self) {
+        self.labels.clear();
+        self.comments.clear();
+    }
+
+    pub fn label(&self, address: u16) -> Option<&str> {
+        self.labels.get(&address).map(String::as_str)
+    }
+
+    pub fn comment(&self, address: u16) -> Option<&str> {
+        self.comments.get(&address).map(String::as_str)
+    }
+
+    /// Sets the label of an address, an empty label removes it.
+    pub fn set_label(&mut self, address: u16, label: &str) {
+        set_entry(&mut self.labels, address, label);
+    }
+
+    /// Sets the comment of an address, an empty comment removes it.
+    pub fn set_comment(&mut self, address: u16, comment: &str) {
+        set_entry(&mut self.comments, address, comment);
+    }
+
+    pub fn labels(&self) -> impl Iterator<Item = (u16, &str)> {
+        self.labels.iter().map(|(address, label)| (*address, label.as_str()))
+    }
+
+    pub fn address_of(&self, label: &str) -> Option<u16> {
+        self.labels()
+        .find(|(_, name)| *name == label)
+        .map(|(address, _)| address)
+    }
+
+    /// Label of the address, or the address itself in hexadecimal.
+    pub fn describe(&self, address: u16) -> String {
+        match self.label(address) {
+            Some(label) => String::from(label),
+            None => format!("{:#05X}", address),
+        }
+    }
+
+    /// Reads a label or an address such as `0x2A0`, `$2A0` or `2A0`.
+    pub fn parse_address(&self, text: &str) -> Option<u16> {
+        let text = text.trim();
+        self.address_of(text).or_else(|| parse_hex(text))
+    }
+
+    /// Merges the symbols written by an assembler: one symbol per line, either
+    /// `name = address`, `name address` or `address name`. Lines starting with `;`
+    /// or `//` are comments. Returns the number of symbols read.
+    pub fn load_symbols(&mut self, path: &Path) -> io::Result<usize> {
+        let content = fs::read_to_string(path)?;
+        self.parse_symbols(&content)
+    }
+
+    fn parse_symbols(&mut self, content: &str) -> io::Result<usize> {
+        let mut count = 0;
+
+        for (line_index, line) in content.lines().map(str::trim).enumerate() {
+            if line.is_empty() || line.starts_with(';') || line.starts_with("//") {
+                continue;
+            }
+
+            let fields: Vec<&str> = line.split(|c: char| c.is_whitespace() || c == '=' || c == ':')
+            .filter(|field| !field.is_empty())
+            .collect();
+
+            let symbol = match fields.as_slice() {
+                [name, address] if line.contains('=') => parse_hex(address).map(|address| (address, *name)),
+                // Names such as `add` are valid hexadecimal, only a prefix on the second
+                // field turns the `address name` reading around.
+                [first, second] => match (parse_hex(first), parse_hex(second)) {
+                    (Some(_), Some(address)) if is_address_like(second) && !is_address_like(first) => Some((address, *first)),
+                    (Some(address), _) => Some((address, *second)),
+                    (None, Some(address)) => Some((address, *first)),
+                    _ => None,
+                },
+                _ => None,
+            };
+
+            let (address, name) = symbol.ok_or_else(|| io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("line {}: expected a symbol name and an address, found '{}'", line_index + 1, line)
+            ))?;
+            self.set_label(address, name);
+            count += 1;
+        }
+
+        Ok(count)
+    }
+
+    /// Reads a file written by `save`, replacing the current labels and comments.
+    pub fn load(&mut self, path: &Path) -> io::Result<()> {
+        let content = fs::read_to_string(path)?;
+        self.clear();
+
+        for (line_index, line) in content.lines().enumerate() {
+            if line.trim().is_empty() || line.starts_with(';') {
+                continue;
+            }
+
+            let mut fields = line.splitn(3, ' ');
+            let entry = match (fields.next(), fields.next(), fields.next()) {
+                (Some(address), Some(kind), Some(text)) => parse_hex(address).map(|address| (address, kind, text)),
+                _ => None,
+            };
+
+            match entry {
+                Some((address, "label", text)) => self.set_label(address, text),
+                Some((address, "comment", text)) => self.set_comment(address, text),
+                _ => return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("line {}: invalid entry '{}'", line_index + 1, line)
+                )),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes one `<address> label <name>` or `<address> comment <text>` line per entry.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut content = String::from("; chip8_emu labels and comments\n");
+        for (address, label) in self.labels.iter() {
+            content += &format!("{:#05X} label {}\n", address, label);
+        }
+        for (address, comment) in self.comments.iter() {
+            content += &format!("{:#05X} comment {}\n", address, comment);
+        }
+        fs::write(path, content)
+    }
+}
+
+fn set_entry(entries: &mut BTreeMap<u16, String>, address: u16, text: &str) {
+    let text = text.trim();
+    if text.is_empty() {
+        entries.remove(&address);
+    }
+    else {
+        entries.insert(address, text.replace('\n', " "));
+    }
+}
+
+fn is_address_like(text: &str) -> bool {
+    text.starts_with("0x") || text.starts_with("0X") || text.starts_with('$') || text.starts_with('#')
+}
+
+/// Reads a hexadecimal address with or without a `0x`, `$` or `#` prefix.
+pub fn parse_hex(text: &str) -> Option<u16> {
+    let hex = text.strip_prefix("0x")
+    .or_else(|| text.strip_prefix("0X"))
+    .or_else(|| text.strip_prefix('$'))
+    .or_else(|| text.strip_prefix('#'))
+    .unwrap_or(text);
+
+    u16::from_str_radix(hex, 16).ok()
+}
+
+/// Reads `0x10`, `$10` or the decimal `16`.
+pub fn parse_number(text: &str) -> Option<u16> {
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")).or_else(|| text.strip_prefix('$')) {
+        Some(hex) => u16::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(content: &str) -> SymbolTable {
+        let mut symbols = SymbolTable::new();
+        symbols.parse_symbols(content).unwrap();
+        symbols
+    }
+
+    #[test]
+    fn symbols_are_read_in_every_layout() {
+        let symbols = parse("; sprites\nplayer = 0x300\nenemy $310\n0x320 score\n// end\n");
+
+        assert_eq!(symbols.label(0x300), Some("player"));
+        assert_eq!(symbols.label(0x310), Some("enemy"));
+        assert_eq!(symbols.label(0x320), Some("score"));
+    }
+
+    #[test]
+    fn unprefixed_fields_are_read_as_address_then_name() {
+        let symbols = parse("200 abc\nadd 0x250\nbeef = 260\n");
+
+        assert_eq!(symbols.label(0x200), Some("abc"));
+        assert_eq!(symbols.label(0x250), Some("add"));
+        assert_eq!(symbols.label(0x260), Some("beef"));
+        assert_eq!(symbols.label(0xABC), None);
+    }
+
+    #[test]
+    fn invalid_symbol_lines_are_rejected() {
+        let mut symbols = SymbolTable::new();
+
+        assert!(symbols.parse_symbols("start\n").is_err());
+        assert!(symbols.parse_symbols("start main\n").is_err());
+        assert!(symbols.parse_symbols("0x200 start extra\n").is_err());
+    }
+
+    #[test]
+    fn numbers_are_decimal_unless_prefixed() {
+        assert_eq!(parse_number("0x2A"), Some(0x2A));
+        assert_eq!(parse_number("$2a"), Some(0x2A));
+        assert_eq!(parse_number("42"), Some(42));
+        assert_eq!(parse_number("2A"), None);
+        assert_eq!(parse_number("0x10000"), None);
+
+        assert_eq!(parse_hex("2A0"), Some(0x2A0));
+        assert_eq!(parse_hex("#2a0"), Some(0x2A0));
+        assert_eq!(parse_hex("0x"), None);
+    }
+
+    #[test]
+    fn registers_are_parsed_read_and_written() {
+        assert_eq!(Register::parse("VA"), Some(Register::V(0xA)));
+        assert_eq!(Register::parse("pc"), Some(Register::Pc));
+        assert_eq!(Register::parse("vg"), None);
+        assert_eq!(Register::parse("v10"), None);
+
+        let mut cpu = CPU::new(0x200);
+        Register::V(0xA).write(&mut cpu, 0x2A).unwrap();
+        Register::I.write(&mut cpu, 0x300).unwrap();
+        assert_eq!((cpu.v[0xA], cpu.i), (0x2A, 0x300));
+        assert_eq!(Register::Pc.read(&cpu), 0x200);
+
+        assert!(Register::Dt.write(&mut cpu, 0x100).is_err());
+        assert!(Register::Sp.write(&mut cpu, 1).is_err());
+        assert_eq!(cpu.delay_timer, 0);
+    }
+
+    #[test]
+    fn labels_resolve_before_addresses() {
+        let mut symbols = SymbolTable::new();
+        symbols.set_label(0x300, "add");
+
+        assert_eq!(symbols.parse_address("add"), Some(0x300));
+        assert_eq!(symbols.parse_address(" $2A0 "), Some(0x2A0));
+        assert_eq!(symbols.parse_address("main"), None);
+    }
+}
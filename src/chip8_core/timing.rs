@@ -0,0 +1,69 @@
+use super::instruction::Instruction;
+
+/// The VIP runs its 1802 at 1.7609 MHz and a machine cycle takes 8 clock cycles,
+/// which leaves 3668 machine cycles per 60 Hz frame.
+pub const COSMAC_CYCLES_PER_FRAME: u32 = 3668;
+/// Machine cycles stolen every frame by the CDP1861 display DMA and its interrupt routine.
+pub const COSMAC_DISPLAY_CYCLES_PER_FRAME: u32 = 1024 + 46;
+
+/// Cycles left to the interpreter every frame.
+pub const COSMAC_FRAME_BUDGET: u32 = COSMAC_CYCLES_PER_FRAME - COSMAC_DISPLAY_CYCLES_PER_FRAME;
+
+/// Fetching and dispatching an instruction in the VIP interpreter.
+const FETCH_CYCLES: u32 = 40;
+/// Extra cycles spent by a conditional instruction when it skips.
+const SKIP_CYCLES: u32 = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TimingMode {
+    /// A fixed number of instructions per second, whatever the instruction.
+    Instructions,
+    /// Every instruction costs the machine cycles it takes on the COSMAC VIP.
+    CosmacVip,
+}
+
+/// Approximate cost in machine cycles of an instruction in the COSMAC VIP interpreter.
+/// `vx` is the value of VX before the instruction and `skipped` tells whether a
+/// conditional instruction skipped the next one.
+pub fn cosmac_cycles(instruction: &Instruction, vx: u8, skipped: bool) -> u32 {
+    let x = instruction.x() as u32;
+    let n = instruction.n() as u32;
+    let skip = if skipped { SKIP_CYCLES } else { 0 };
+
+    let execution = match (instruction.extract(0xF000, 12), instruction.nn()) {
+        (0x0, 0xE0) => 3078,
+        (0x0, 0xEE) => 10,
+        (0x0, _) => 10,
+        (0x1, _) => 12,
+        (0x2, _) => 26,
+        (0x3, _) | (0x4, _) => 10 + skip,
+        (0x5, _) | (0x9, _) => 14 + skip,
+        (0x6, _) => 6,
+        (0x7, _) => 10,
+        (0x8, _) => 44,
+        (0xA, _) => 12,
+        (0xB, _) => 22,
+        (0xC, _) => 36,
+        (0xD, _) => {
+            // Sprites that are not byte aligned are shifted bit by bit into two bytes.
+            let shift = (vx % 8) as u32;
+            let row = if shift == 0 { 34 } else { 58 + 8 * shift };
+            26 + n * row
+        },
+        (0xE, _) => 14 + skip,
+        (0xF, 0x07) => 10,
+        (0xF, 0x0A) => 20,
+        (0xF, 0x15) | (0xF, 0x18) => 10,
+        (0xF, 0x1E) => 14,
+        (0xF, 0x29) | (0xF, 0x30) => 16,
+        (0xF, 0x33) => {
+            // Digits are computed by repeated subtractions.
+            let digits = (vx / 100 + (vx / 10) % 10 + vx % 10) as u32;
+            80 + 16 * digits
+        },
+        (0xF, 0x55) | (0xF, 0x65) => 14 + 14 * (x + 1),
+        _ => 0,
+    };
+
+    FETCH_CYCLES + execution
+}
@@ -0,0 +1,127 @@
+use std::path::PathBuf;
+
+use crate::app::audio::{ToneSettings, WavRecorder};
+use crate::chip8_core::chip8::Chip8;
+use crate::chip8_core::machine::MachineConfig;
+use crate::chip8_core::symbols::parse_hex;
+
+const DEFAULT_FRAMES: u32 = 600;
+const DEFAULT_INSTRUCTIONS_PER_FRAME: u32 = 10;
+
+pub const USAGE: &str = "\
+Usage: chip8_emu --headless <rom> [--frames <count>] [--ipf <instructions per frame>] [--start <hex address>] [--wav <output.wav>]";
+
+/// Options of a run without window nor sound device.
+pub struct HeadlessOptions {
+    pub rom: PathBuf,
+    pub frames: u32,
+    pub instructions_per_frame: u32,
+    pub machine: MachineConfig,
+    pub wav: Option<PathBuf>,
+}
+
+impl HeadlessOptions {
+    /// Parses the command line, returns `None` when the emulator is not run headless.
+    pub fn from_args(args: &[String]) -> Result<Option<Self>, String> {
+        let mut args = args.iter();
+        match args.next() {
+            Some(arg) if arg == "--headless" => {},
+            _ => return Ok(None),
+        }
+
+        let rom = args.next().ok_or("missing ROM path")?;
+        let mut options = Self {
+            rom: PathBuf::from(rom),
+            frames: DEFAULT_FRAMES,
+            instructions_per_frame: DEFAULT_INSTRUCTIONS_PER_FRAME,
+            machine: MachineConfig::default(),
+            wav: None,
+        };
+
+        while let Some(arg) = args.next() {
+            let value = args.next().ok_or(format!("missing value for {}", arg))?;
+            let parse_count = |value: &String| value.parse::<u32>()
+            .map_err(|_| format!("invalid value '{}' for {}", value, arg));
+
+            match arg.as_str() {
+                "--frames" => options.frames = parse_count(value)?,
+                "--ipf" => options.instructions_per_frame = parse_count(value)?,
+                "--start" => {
+                    options.machine.program_start = parse_hex(value)
+                    .ok_or_else(|| format!("invalid address '{}' for {}", value, arg))?;
+                    options.machine.validate()
+                    .map_err(|e| format!("invalid program start {}: {}", value, e))?;
+                },
+                "--wav" => options.wav = Some(PathBuf::from(value)),
+                _ => return Err(format!("unknown option {}", arg)),
+            }
+        }
+
+        Ok(Some(options))
+    }
+}
+
+/// Runs the ROM for the given number of 60 Hz frames, rendering the buzzer to a WAV
+/// file when asked.
+pub fn run(options: &HeadlessOptions) -> Result<(), String> {
+    let mut chip8 = Chip8::new(options.machine);
+    chip8.load_program(&options.rom)
+    .map_err(|e| format!("cannot load {}: {}", options.rom.display(), e))?;
+
+    let mut wav_recorder = options.wav.as_ref()
+    .map(|_| WavRecorder::new(ToneSettings::default()));
+
+    chip8.set_cpu_frequency(options.instructions_per_frame as f64 * 60.0);
+    for _ in 0..options.frames {
+        chip8.run_frame().map_err(|e| format!("execution error: {}", e))?;
+
+        if let Some(wav_recorder) = &mut wav_recorder {
+            wav_recorder.push_frame(chip8.played_sound_last_frame());
+        }
+    }
+
+    if let (Some(wav_recorder), Some(path)) = (&wav_recorder, &options.wav) {
+        wav_recorder.save(path)
+        .map_err(|e| format!("cannot write {}: {}", path.display(), e))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::audio::SAMPLE_RATE;
+    use std::fs;
+
+    #[test]
+    fn the_buzzer_sounds_for_the_sound_timer_frames() {
+        let directory = std::env::temp_dir().join(format!("chip8_headless_{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let rom = directory.join("beep.ch8");
+        let wav = directory.join("beep.wav");
+
+        // LD VA, 30; LD ST, VA; loop: JP loop
+        fs::write(&rom, [0x6A, 0x1E, 0xFA, 0x18, 0x12, 0x04]).unwrap();
+        let options = HeadlessOptions {
+            rom,
+            frames: 60,
+            instructions_per_frame: DEFAULT_INSTRUCTIONS_PER_FRAME,
+            machine: MachineConfig::default(),
+            wav: Some(wav.clone()),
+        };
+        run(&options).unwrap();
+
+        let samples: Vec<i16> = fs::read(&wav).unwrap()[44..]
+            .chunks(2)
+            .map(|bytes| i16::from_le_bytes([bytes[0], bytes[1]]))
+            .collect();
+        fs::remove_dir_all(&directory).unwrap();
+
+        let frame = SAMPLE_RATE as usize / 60;
+        assert_eq!(samples.len(), 60 * frame);
+        assert!(samples[..30 * frame].iter().all(|sample| *sample != 0));
+        // The release of the envelope fades out within the next frame.
+        assert!(samples[31 * frame..].iter().all(|sample| *sample == 0));
+    }
+}
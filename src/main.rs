@@ -3,8 +3,34 @@ use sfml::window::VideoMode;
 mod chip8_core;
 mod app;
 mod timer;
+mod headless;
+mod remote;
 
 fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match headless::HeadlessOptions::from_args(&args) {
+        Ok(Some(options)) => {
+            if let Err(e) = headless::run(&options) {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+            return;
+        },
+        Ok(None) => {},
+        Err(e) => {
+            eprintln!("{}\n{}", e, headless::USAGE);
+            std::process::exit(2);
+        },
+    }
+
+    let dap_port = match remote::dap::port_from_args(&args) {
+        Ok(port) => port,
+        Err(e) => {
+            eprintln!("{}\nUsage: chip8_emu --dap [port]", e);
+            std::process::exit(2);
+        },
+    };
+
     let spec = app::AppSpec {
         window_size: VideoMode::desktop_mode(),
         title: String::from("Chip8 Emulator"),
@@ -12,8 +38,10 @@ fn main() {
     };
 
     app::run_app(spec, |ctx| {
-        Box::new(
-            app::main_app::MainApp::new(ctx)
-        )
+        let mut main_app = app::main_app::MainApp::new(ctx);
+        if let Some(port) = dap_port {
+            main_app.start_dap_server(port);
+        }
+        Box::new(main_app)
     });
 }
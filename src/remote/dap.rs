@@ -0,0 +1,876 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{self, Read, Write};
+use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+
+use serde_json::{json, Value};
+
+use crate::chip8_core::chip8::Chip8;
+use crate::chip8_core::coverage::SourceMap;
+use crate::chip8_core::instruction::Instruction;
+use crate::chip8_core::symbols::{parse_number, Register, SymbolTable};
+use super::{RemoteCommand, StopReason, DEFAULT_DAP_PORT};
+
+const THREAD_ID: i64 = 1;
+
+const REGISTERS_REFERENCE: i64 = 1;
+const TIMERS_REFERENCE: i64 = 2;
+const STACK_REFERENCE: i64 = 3;
+
+const FUNCTION_BREAKPOINTS: &str = "function";
+const INSTRUCTION_BREAKPOINTS: &str = "instruction";
+
+/// Longer disassembly requests are cut to this many instructions.
+const MAX_DISASSEMBLED_INSTRUCTIONS: i64 = 4096;
+
+const BASE64_ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Reads `--dap [port]` from the command line, `None` when the first argument is not
+/// `--dap`.
+pub fn port_from_args(args: &[String]) -> Result<Option<u16>, String> {
+    let mut args = args.iter();
+    match args.next() {
+        Some(arg) if arg == "--dap" => {},
+        _ => return Ok(None),
+    }
+
+    match args.next() {
+        Some(port) => port.parse()
+            .map(Some)
+            .map_err(|_| format!("invalid DAP port: {}", port)),
+        None => Ok(Some(DEFAULT_DAP_PORT)),
+    }
+}
+
+/// Server of the Debug Adapter Protocol on localhost, for editors. It launches ROMs,
+/// sets breakpoints by address, label or source line, steps, and shows the registers,
+/// the timers, the stack and the RAM. Only one client is served at a time.
+pub struct DapServer {
+    listener: TcpListener,
+    client: Option<TcpStream>,
+    input: Vec<u8>,
+    sequence: i64,
+    /// Events to send after the response of the current request.
+    events: Vec<Value>,
+
+    /// Maps the addresses of the launched ROM to the lines of its sources, the paths
+    /// of the map are relative to `source_dir`.
+    source_map: Option<SourceMap>,
+    source_dir: PathBuf,
+    /// Addresses set in `Chip8` by each `setBreakpoints` source, and by the function
+    /// and instruction breakpoints.
+    breakpoints: BTreeMap<String, BTreeSet<u16>>,
+
+    launched: bool,
+    configured: bool,
+    stop_on_entry: bool,
+    /// The client sent a continue or a step and waits for a stopped event.
+    running: bool,
+    stepping: bool,
+    pause_requested: bool,
+}
+
+impl DapServer {
+    /// Listens on `127.0.0.1:port`, port 0 picks a free port.
+    pub fn bind(port: u16) -> io::Result<Self> {
+        let listener = TcpListener::bind(SocketAddr::from((Ipv4Addr::LOCALHOST, port)))?;
+        listener.set_nonblocking(true)?;
+
+        Ok(Self {
+            listener,
+            client: None,
+            input: Vec::new(),
+            sequence: 0,
+            events: Vec::new(),
+            source_map: None,
+            source_dir: PathBuf::new(),
+            breakpoints: BTreeMap::new(),
+            launched: false,
+            configured: false,
+            stop_on_entry: false,
+            running: false,
+            stepping: false,
+            pause_requested: false,
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.client.is_some()
+    }
+
+    /// Accepts a client, answers its requests and returns what it asks the emulator to
+    /// do, in order. Errors on the client connection drop it.
+    pub fn poll(&mut self, chip8: &mut Chip8, symbols: &SymbolTable) -> Vec<RemoteCommand> {
+        if self.client.is_none() {
+            let stream = match self.listener.accept() {
+                Ok((stream, _)) => stream,
+                Err(_) => return Vec::new(),
+            };
+            if stream.set_nonblocking(true).is_err() {
+                return Vec::new();
+            }
+
+            self.client = Some(stream);
+            self.input.clear();
+            self.sequence = 0;
+            self.launched = false;
+            self.configured = false;
+            self.running = false;
+            self.stepping = false;
+            self.pause_requested = false;
+        }
+
+        let mut commands = Vec::new();
+        if self.receive(chip8, symbols, &mut commands).is_err() {
+            self.remove_breakpoints(chip8);
+            self.disconnect();
+            commands.push(RemoteCommand::Continue);
+        }
+        commands
+    }
+
+    /// Sends the stopped event the client waits for since its last continue or step.
+    /// `error` describes why the program halted.
+    pub fn report_stop(&mut self, reason: StopReason, chip8: &Chip8, error: Option<&str>) {
+        if !self.running {
+            return;
+        }
+        self.running = false;
+
+        let pc = chip8.get_cpu().pc;
+        let mut body = json!({ "threadId": THREAD_ID, "allThreadsStopped": true });
+        body["reason"] = json!(match reason {
+            StopReason::Error => "exception",
+            StopReason::Trap if self.pause_requested => "pause",
+            StopReason::Trap if self.stepping => "step",
+            StopReason::Trap if chip8.get_breakpoints().contains(&pc) => "breakpoint",
+            StopReason::Trap => "pause",
+        });
+        if let Some(error) = error {
+            body["description"] = json!(error);
+            body["text"] = json!(error);
+        }
+        if body["reason"] == "breakpoint" {
+            body["hitBreakpointIds"] = json!([pc]);
+        }
+        self.stepping = false;
+        self.pause_requested = false;
+
+        if self.send_event("stopped", body).is_err() {
+            self.disconnect();
+        }
+    }
+
+    /// Tells the client the emulator runs again after it was resumed from the UI.
+    pub fn report_continued(&mut self) {
+        if self.running || !self.configured {
+            return;
+        }
+        self.running = true;
+
+        let body = json!({ "threadId": THREAD_ID, "allThreadsContinued": true });
+        if self.send_event("continued", body).is_err() {
+            self.disconnect();
+        }
+    }
+
+    fn disconnect(&mut self) {
+        self.client = None;
+        self.running = false;
+        self.configured = false;
+    }
+
+    fn receive(&mut self, chip8: &mut Chip8, symbols: &SymbolTable, commands: &mut Vec<RemoteCommand>) -> io::Result<()> {
+        let mut buffer = [0; 4096];
+        let client = match &mut self.client {
+            Some(client) => client,
+            None => return Ok(()),
+        };
+
+        loop {
+            match client.read(&mut buffer) {
+                Ok(0) => return Err(io::ErrorKind::ConnectionAborted.into()),
+                Ok(count) => self.input.extend_from_slice(&buffer[..count]),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => return Err(e),
+            }
+        }
+
+        while let Some(message) = self.next_message()? {
+            if message["type"] == "request" {
+                self.handle_request(&message, chip8, symbols, commands)?;
+            }
+            if self.client.is_none() {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Takes the next complete `Content-Length` framed message from the input.
+    fn next_message(&mut self) -> io::Result<Option<Value>> {
+        let header_end = match self.input.windows(4).position(|window| window == b"\r\n\r\n") {
+            Some(header_end) => header_end,
+            None => return Ok(None),
+        };
+
+        let header = String::from_utf8_lossy(&self.input[..header_end]).to_string();
+        let length = header.lines()
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("Content-Length"))
+        .and_then(|(_, length)| length.trim().parse::<usize>().ok())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "missing Content-Length"))?;
+
+        let body_start = header_end + 4;
+        if self.input.len() < body_start + length {
+            return Ok(None);
+        }
+
+        let message: Vec<u8> = self.input.drain(..body_start + length).collect();
+        serde_json::from_slice(&message[body_start..])
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    fn handle_request(&mut self, request: &Value, chip8: &mut Chip8, symbols: &SymbolTable, commands: &mut Vec<RemoteCommand>) -> io::Result<()> {
+        let command = request["command"].as_str().unwrap_or_default();
+        let arguments = &request["arguments"];
+
+        let result = match command {
+            "initialize" => Ok(capabilities()),
+            "launch" => self.launch(arguments, chip8, commands),
+            "attach" => {
+                self.launched = true;
+                self.stop_on_entry = arguments["stopOnEntry"].as_bool().unwrap_or(true);
+                self.events.push(event("initialized", Value::Null));
+                Ok(Value::Null)
+            },
+            "configurationDone" => {
+                self.configured = true;
+                self.start(commands);
+                Ok(Value::Null)
+            },
+            "setBreakpoints" => self.set_source_breakpoints(arguments, chip8),
+            "setFunctionBreakpoints" => {
+                let addresses = arguments["breakpoints"].as_array().into_iter().flatten()
+                .map(|breakpoint| breakpoint["name"].as_str().and_then(|name| symbols.parse_address(name)))
+                .collect();
+                Ok(self.set_breakpoints(FUNCTION_BREAKPOINTS, addresses, chip8))
+            },
+            "setInstructionBreakpoints" => {
+                let addresses = arguments["breakpoints"].as_array().into_iter().flatten()
+                .map(|breakpoint| {
+                    let address = breakpoint["instructionReference"].as_str().and_then(|reference| symbols.parse_address(reference))?;
+                    offset_address(address, breakpoint["offset"].as_i64().unwrap_or(0))
+                })
+                .collect();
+                Ok(self.set_breakpoints(INSTRUCTION_BREAKPOINTS, addresses, chip8))
+            },
+            "threads" => Ok(json!({ "threads": [{ "id": THREAD_ID, "name": "CHIP-8" }] })),
+            "stackTrace" => Ok(self.stack_trace(chip8, symbols)),
+            "scopes" => Ok(json!({ "scopes": [
+                { "name": "Registers", "presentationHint": "registers", "variablesReference": REGISTERS_REFERENCE, "expensive": false },
+                { "name": "Timers", "variablesReference": TIMERS_REFERENCE, "expensive": false },
+                { "name": "Stack", "variablesReference": STACK_REFERENCE, "expensive": false },
+            ] })),
+            "variables" => Ok(json!({ "variables": variables(arguments["variablesReference"].as_i64().unwrap_or(0), chip8, symbols) })),
+            "setVariable" => set_variable(arguments, chip8),
+            "continue" => {
+                self.resume(RemoteCommand::Continue, commands);
+                Ok(json!({ "allThreadsContinued": true }))
+            },
+            "next" => {
+                self.resume(RemoteCommand::StepOver, commands);
+                Ok(Value::Null)
+            },
+            "stepIn" => {
+                self.resume(RemoteCommand::Step, commands);
+                Ok(Value::Null)
+            },
+            "stepOut" => {
+                self.resume(RemoteCommand::StepOut, commands);
+                Ok(Value::Null)
+            },
+            "pause" => {
+                self.pause_requested = true;
+                self.running = true;
+                commands.push(RemoteCommand::Pause);
+                Ok(Value::Null)
+            },
+            "readMemory" => read_memory(arguments, chip8, symbols),
+            "writeMemory" => write_memory(arguments, chip8, symbols),
+            "disassemble" => disassemble(arguments, chip8, symbols, self.source_map.as_ref()),
+            "evaluate" => evaluate(arguments, chip8, symbols),
+            "terminate" => {
+                self.events.push(event("terminated", Value::Null));
+                Ok(Value::Null)
+            },
+            "disconnect" => Ok(Value::Null),
+            _ => Err(format!("unsupported request: {}", command)),
+        };
+
+        self.send_response(request, result)?;
+        for event in std::mem::take(&mut self.events) {
+            self.send(event)?;
+        }
+
+        if command == "disconnect" {
+            self.remove_breakpoints(chip8);
+            self.disconnect();
+            commands.push(RemoteCommand::Continue);
+        }
+        Ok(())
+    }
+
+    /// `launch { program, stopOnEntry, sourceMap }`, the source map defaults to the ROM
+    /// path with the `map` extension.
+    fn launch(&mut self, arguments: &Value, chip8: &mut Chip8, commands: &mut Vec<RemoteCommand>) -> Result<Value, String> {
+        let program = arguments["program"].as_str()
+        .map(PathBuf::from)
+        .ok_or("missing program")?;
+
+        chip8.reset();
+        chip8.load_program(&program)
+        .map_err(|e| format!("could not load {}: {}", program.display(), e))?;
+        commands.push(RemoteCommand::Pause);
+        commands.push(RemoteCommand::RomLoaded(program.clone()));
+
+        let source_map_path = match arguments["sourceMap"].as_str() {
+            Some(path) => Some(PathBuf::from(path)),
+            None => Some(program.with_extension("map")).filter(|path| path.exists()),
+        };
+        self.source_map = match source_map_path {
+            Some(path) => {
+                let source_map = SourceMap::load(&path)
+                .map_err(|e| format!("could not load {}: {}", path.display(), e))?;
+                self.source_dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+                Some(source_map)
+            },
+            None => None,
+        };
+
+        self.launched = true;
+        self.stop_on_entry = arguments["stopOnEntry"].as_bool().unwrap_or(false);
+        self.events.push(event("initialized", Value::Null));
+        Ok(Value::Null)
+    }
+
+    /// Starts the program once launched and configured, or stops on its first instruction.
+    fn start(&mut self, commands: &mut Vec<RemoteCommand>) {
+        if !self.launched {
+            return;
+        }
+
+        if self.stop_on_entry {
+            commands.push(RemoteCommand::Pause);
+            self.events.push(event("stopped", json!({ "reason": "entry", "threadId": THREAD_ID, "allThreadsStopped": true })));
+        }
+        else {
+            self.resume(RemoteCommand::Continue, commands);
+        }
+    }
+
+    fn resume(&mut self, command: RemoteCommand, commands: &mut Vec<RemoteCommand>) {
+        self.stepping = command != RemoteCommand::Continue;
+        self.pause_requested = false;
+        self.running = true;
+        commands.push(command);
+    }
+
+    /// `setBreakpoints { source, breakpoints }`, lines are resolved with the source map.
+    fn set_source_breakpoints(&mut self, arguments: &Value, chip8: &mut Chip8) -> Result<Value, String> {
+        let path = arguments["source"]["path"].as_str()
+        .or_else(|| arguments["source"]["name"].as_str())
+        .ok_or("missing source path")?;
+
+        let addresses: Vec<Option<u16>> = arguments["breakpoints"].as_array().into_iter().flatten()
+        .map(|breakpoint| {
+            let line = breakpoint["line"].as_u64()? as u32;
+            self.source_map.as_ref()?.address_of(line, |file| Path::new(path).ends_with(file))
+        })
+        .collect();
+
+        let mut body = self.set_breakpoints(&format!("source:{}", path), addresses, chip8);
+        let lines = arguments["breakpoints"].as_array().into_iter().flatten();
+        if let Some(breakpoints) = body["breakpoints"].as_array_mut() {
+            for (breakpoint, requested) in breakpoints.iter_mut().zip(lines) {
+                breakpoint["line"] = requested["line"].clone();
+            }
+        }
+        Ok(body)
+    }
+
+    fn remove_breakpoints(&mut self, chip8: &mut Chip8) {
+        for addresses in std::mem::take(&mut self.breakpoints).values() {
+            for address in addresses {
+                chip8.remove_breakpoint(*address);
+            }
+        }
+    }
+
+    /// Replaces the breakpoints of `key` in `Chip8`, `None` addresses could not be resolved.
+    fn set_breakpoints(&mut self, key: &str, addresses: Vec<Option<u16>>, chip8: &mut Chip8) -> Value {
+        let previous = self.breakpoints.remove(key).unwrap_or_default();
+        for address in previous {
+            let used_elsewhere = self.breakpoints.values().any(|addresses| addresses.contains(&address));
+            if !used_elsewhere {
+                chip8.remove_breakpoint(address);
+            }
+        }
+
+        let ram_size = chip8.get_ram().size();
+        let mut set = BTreeSet::new();
+        let breakpoints: Vec<Value> = addresses.into_iter()
+        .map(|address| match address {
+            Some(address) if (address as usize) < ram_size => {
+                chip8.add_breakpoint(address);
+                set.insert(address);
+                json!({ "id": address, "verified": true, "instructionReference": format!("{:#05X}", address) })
+            },
+            _ => json!({ "verified": false, "message": "no instruction at this location" }),
+        })
+        .collect();
+
+        self.breakpoints.insert(key.to_string(), set);
+        json!({ "breakpoints": breakpoints })
+    }
+
+    /// The current instruction, then the `CALL` of each subroutine being run.
+    fn stack_trace(&self, chip8: &Chip8, symbols: &SymbolTable) -> Value {
+        let cpu = chip8.get_cpu();
+        let ram = chip8.get_ram();
+
+        let mut addresses = vec![cpu.pc];
+        addresses.extend(cpu.stack.frames().iter().rev().map(|address| address.wrapping_sub(2)));
+
+        let frames: Vec<Value> = addresses.iter().enumerate()
+        .map(|(index, address)| {
+            // The subroutine runs from the target of the CALL below it in the stack.
+            let entry = addresses.get(index + 1)
+            .and_then(|call| ram.read16(*call).ok())
+            .map(|opcode| Instruction::new(opcode).nnn())
+            .unwrap_or(chip8.get_config().program_start);
+
+            let mut frame = json!({
+                "id": index,
+                "name": format!("{} @ {:#05X}", symbols.describe(entry), address),
+                "instructionPointerReference": format!("{:#05X}", address),
+                "line": 0,
+                "column": 0,
+            });
+            if let Some((file, line)) = self.source_map.as_ref().and_then(|source_map| source_map.location(*address)) {
+                frame["source"] = self.source(file);
+                frame["line"] = json!(line);
+                frame["column"] = json!(1);
+            }
+            frame
+        })
+        .collect();
+
+        json!({ "stackFrames": frames, "totalFrames": addresses.len() })
+    }
+
+    fn source(&self, file: &str) -> Value {
+        let path = self.source_dir.join(file);
+        json!({
+            "name": path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default(),
+            "path": path.to_string_lossy(),
+        })
+    }
+
+    fn send_response(&mut self, request: &Value, result: Result<Value, String>) -> io::Result<()> {
+        let mut response = json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": result.is_ok(),
+        });
+        match result {
+            Ok(Value::Null) => {},
+            Ok(body) => response["body"] = body,
+            Err(message) => response["message"] = json!(message),
+        }
+        self.send(response)
+    }
+
+    fn send_event(&mut self, name: &str, body: Value) -> io::Result<()> {
+        self.send(event(name, body))
+    }
+
+    fn send(&mut self, mut message: Value) -> io::Result<()> {
+        self.sequence += 1;
+        message["seq"] = json!(self.sequence);
+        let body = message.to_string();
+
+        match &mut self.client {
+            Some(client) => {
+                // The socket is non-blocking, wait until the whole message is written.
+                client.set_nonblocking(false)?;
+                let result = client.write_all(format!("Content-Length: {}\r\n\r\n{}", body.len(), body).as_bytes());
+                client.set_nonblocking(true)?;
+                result
+            },
+            None => Ok(()),
+        }
+    }
+}
+
+fn event(name: &str, body: Value) -> Value {
+    let mut event = json!({ "type": "event", "event": name });
+    if !body.is_null() {
+        event["body"] = body;
+    }
+    event
+}
+
+fn capabilities() -> Value {
+    json!({
+        "supportsConfigurationDoneRequest": true,
+        "supportsFunctionBreakpoints": true,
+        "supportsInstructionBreakpoints": true,
+        "supportsSetVariable": true,
+        "supportsReadMemoryRequest": true,
+        "supportsWriteMemoryRequest": true,
+        "supportsDisassembleRequest": true,
+        "supportsTerminateRequest": true,
+        "supportsEvaluateForHovers": true,
+    })
+}
+
+fn offset_address(address: u16, offset: i64) -> Option<u16> {
+    u16::try_from(address as i64 + offset).ok()
+}
+
+fn byte_value(value: u8) -> String {
+    format!("{:#04X} ({})", value, value)
+}
+
+fn variable(name: &str, value: String) -> Value {
+    json!({ "name": name, "value": value, "variablesReference": 0 })
+}
+
+fn address_variable(name: &str, address: u16, symbols: &SymbolTable) -> Value {
+    let value = match symbols.label(address) {
+        Some(label) => format!("{:#05X} ({})", address, label),
+        None => format!("{:#05X}", address),
+    };
+    let mut variable = variable(name, value);
+    variable["memoryReference"] = json!(format!("{:#05X}", address));
+    variable
+}
+
+fn variables(reference: i64, chip8: &Chip8, symbols: &SymbolTable) -> Vec<Value> {
+    let cpu = chip8.get_cpu();
+    match reference {
+        REGISTERS_REFERENCE => {
+            let mut variables: Vec<Value> = cpu.v.iter().enumerate()
+            .map(|(register, value)| variable(&format!("V{:X}", register), byte_value(*value)))
+            .collect();
+            variables.push(address_variable("I", cpu.i, symbols));
+            variables.push(address_variable("PC", cpu.pc, symbols));
+            variables.push(variable("SP", cpu.stack.sp.to_string()));
+            variables
+        },
+        TIMERS_REFERENCE => vec![
+            variable("DT", byte_value(cpu.delay_timer)),
+            variable("ST", byte_value(cpu.sound_timer)),
+            variable("Frame", chip8.get_frame_count().to_string()),
+        ],
+        STACK_REFERENCE => cpu.stack.frames().iter().enumerate().rev()
+            .map(|(depth, address)| address_variable(&format!("#{}", depth), *address, symbols))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// `setVariable { variablesReference, name, value }` on a register or a timer.
+fn set_variable(arguments: &Value, chip8: &mut Chip8) -> Result<Value, String> {
+    let name = arguments["name"].as_str().unwrap_or_default();
+    // The decimal value shown after hexadecimal ones is ignored.
+    let value = arguments["value"].as_str()
+    .and_then(|value| value.split_whitespace().next())
+    .and_then(parse_number)
+    .ok_or("the value must be a number such as 0x2A or 42")?;
+
+    let register = Register::parse(name).ok_or_else(|| format!("{} cannot be changed", name))?;
+    register.write(chip8.get_cpu_mut(), value)?;
+    let shown = if register.holds_address() { format!("{:#05X}", value) } else { byte_value(value as u8) };
+
+    Ok(json!({ "value": shown }))
+}
+
+/// Address of a `memoryReference` plus its `offset`.
+fn memory_address(arguments: &Value, symbols: &SymbolTable) -> Result<u16, String> {
+    let reference = arguments["memoryReference"].as_str().unwrap_or_default();
+    symbols.parse_address(reference)
+    .and_then(|address| offset_address(address, arguments["offset"].as_i64().unwrap_or(0)))
+    .ok_or_else(|| format!("invalid memory reference: {}", reference))
+}
+
+/// `readMemory { memoryReference, offset, count }`, bytes past the RAM are unreadable.
+fn read_memory(arguments: &Value, chip8: &Chip8, symbols: &SymbolTable) -> Result<Value, String> {
+    let address = memory_address(arguments, symbols)?;
+    let count = arguments["count"].as_u64().unwrap_or(0) as usize;
+    let ram = chip8.get_ram();
+
+    let end = ram.size().min((address as usize).saturating_add(count));
+    let bytes: Vec<u8> = (address as usize..end)
+    .map_while(|address| ram.read8(address as u16).ok())
+    .collect();
+
+    Ok(json!({
+        "address": format!("{:#05X}", address),
+        "data": encode_base64(&bytes),
+        "unreadableBytes": count - bytes.len(),
+    }))
+}
+
+/// `writeMemory { memoryReference, offset, data }` with base64 data.
+fn write_memory(arguments: &Value, chip8: &mut Chip8, symbols: &SymbolTable) -> Result<Value, String> {
+    let address = memory_address(arguments, symbols)?;
+    let bytes = arguments["data"].as_str()
+    .and_then(decode_base64)
+    .ok_or("invalid base64 data")?;
+
+    chip8.get_ram_mut().write_bytes(address, &bytes)
+    .map_err(|e| e.to_string())?;
+    Ok(json!({ "bytesWritten": bytes.len() }))
+}
+
+/// `disassemble { memoryReference, offset, instructionOffset, instructionCount }`,
+/// instructions are two bytes long.
+fn disassemble(arguments: &Value, chip8: &Chip8, symbols: &SymbolTable, source_map: Option<&SourceMap>) -> Result<Value, String> {
+    let reference = arguments["memoryReference"].as_str().unwrap_or_default();
+    let base = symbols.parse_address(reference)
+    .ok_or_else(|| format!("invalid memory reference: {}", reference))?;
+    let start = (base as i64)
+        .saturating_add(arguments["offset"].as_i64().unwrap_or(0))
+        .saturating_add(arguments["instructionOffset"].as_i64().unwrap_or(0).saturating_mul(2));
+    let count = arguments["instructionCount"].as_i64().unwrap_or(0).clamp(0, MAX_DISASSEMBLED_INSTRUCTIONS);
+    let ram = chip8.get_ram();
+
+    let instructions: Vec<Value> = (0..count)
+    .map(|index| {
+        let address = start.saturating_add(2 * index);
+        let opcode = u16::try_from(address).ok()
+        .and_then(|address| ram.read16(address).ok().map(|opcode| (address, opcode)));
+
+        match opcode {
+            Some((address, opcode)) => {
+                let mut instruction = json!({
+                    "address": format!("{:#05X}", address),
+                    "instructionBytes": format!("{:04X}", opcode),
+                    "instruction": Instruction::new(opcode).to_string(),
+                });
+                if let Some(label) = symbols.label(address) {
+                    instruction["symbol"] = json!(label);
+                }
+                if let Some((_, line)) = source_map.and_then(|source_map| source_map.location(address)) {
+                    instruction["line"] = json!(line);
+                }
+                instruction
+            },
+            None => json!({
+                "address": format!("{:#05X}", address.max(0)),
+                "instruction": "??",
+                "presentationHint": "invalid",
+            }),
+        }
+    })
+    .collect();
+
+    Ok(json!({ "instructions": instructions }))
+}
+
+/// `evaluate { expression }` of a register, a timer, a label or an address.
+fn evaluate(arguments: &Value, chip8: &Chip8, symbols: &SymbolTable) -> Result<Value, String> {
+    let expression = arguments["expression"].as_str().unwrap_or_default().trim();
+    let cpu = chip8.get_cpu();
+
+    let name = expression.to_ascii_uppercase();
+    let result = match Register::parse(expression) {
+        Some(register) if register.holds_address() => address_variable(&name, register.read(cpu), symbols),
+        Some(Register::Sp) => variable(&name, cpu.stack.sp.to_string()),
+        Some(register) => variable(&name, byte_value(register.read(cpu) as u8)),
+        None => {
+            let address = symbols.parse_address(expression)
+            .ok_or_else(|| format!("unknown expression: {}", expression))?;
+            address_variable(expression, address, symbols)
+        },
+    };
+
+    let mut body = json!({ "result": result["value"], "variablesReference": 0 });
+    if let Some(reference) = result.get("memoryReference") {
+        body["memoryReference"] = reference.clone();
+    }
+    Ok(body)
+}
+
+fn encode_base64(bytes: &[u8]) -> String {
+    let mut text = String::new();
+    for chunk in bytes.chunks(3) {
+        let group = chunk.iter().enumerate()
+        .fold(0u32, |group, (index, byte)| group | (*byte as u32) << (16 - 8 * index));
+        for index in 0..4 {
+            if index <= chunk.len() {
+                text.push(BASE64_ALPHABET[(group >> (18 - 6 * index)) as usize & 0x3F] as char);
+            }
+            else {
+                text.push('=');
+            }
+        }
+    }
+    text
+}
+
+fn decode_base64(text: &str) -> Option<Vec<u8>> {
+    let digits: Vec<u32> = text.trim_end_matches('=').bytes()
+    .map(|digit| BASE64_ALPHABET.iter().position(|c| *c == digit).map(|value| value as u32))
+    .collect::<Option<_>>()?;
+
+    let mut bytes = Vec::new();
+    for chunk in digits.chunks(4) {
+        if chunk.len() == 1 {
+            return None;
+        }
+        let group = chunk.iter().enumerate()
+        .fold(0u32, |group, (index, digit)| group | digit << (18 - 6 * index));
+        for index in 0..chunk.len() - 1 {
+            bytes.push((group >> (16 - 8 * index)) as u8);
+        }
+    }
+    Some(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8_core::machine::MachineConfig;
+
+    fn frame(body: &str) -> Vec<u8> {
+        format!("Content-Length: {}\r\n\r\n{}", body.len(), body).into_bytes()
+    }
+
+    #[test]
+    fn base64_round_trips_with_padding() {
+        assert_eq!(encode_base64(b""), "");
+        assert_eq!(encode_base64(b"C"), "Qw==");
+        assert_eq!(encode_base64(b"CH"), "Q0g=");
+        assert_eq!(encode_base64(b"CHIP-8"), "Q0hJUC04");
+
+        for length in 0..8 {
+            let bytes: Vec<u8> = (0..length).map(|byte| 0xF0 + byte).collect();
+            assert_eq!(decode_base64(&encode_base64(&bytes)), Some(bytes));
+        }
+        assert_eq!(decode_base64("Q"), None);
+        assert_eq!(decode_base64("Q0*="), None);
+    }
+
+    #[test]
+    fn messages_are_framed_by_their_length() {
+        let mut server = DapServer::bind(0).unwrap();
+        let first = frame(r#"{"seq":1}"#);
+        server.input.extend_from_slice(&first[..10]);
+        assert!(server.next_message().unwrap().is_none());
+
+        server.input.extend_from_slice(&first[10..first.len() - 1]);
+        assert!(server.next_message().unwrap().is_none());
+
+        server.input.extend_from_slice(&first[first.len() - 1..]);
+        server.input.extend_from_slice(&frame(r#"{"seq":2}"#));
+        server.input.extend_from_slice(&frame(r#"{"seq":3}"#));
+        assert_eq!(server.next_message().unwrap().unwrap()["seq"], 1);
+        assert_eq!(server.next_message().unwrap().unwrap()["seq"], 2);
+        assert_eq!(server.next_message().unwrap().unwrap()["seq"], 3);
+        assert!(server.next_message().unwrap().is_none());
+
+        server.input.extend_from_slice(b"Content-Type: json\r\n\r\n{}");
+        assert!(server.next_message().is_err());
+    }
+
+    #[test]
+    fn breakpoints_are_replaced_per_source() {
+        let mut server = DapServer::bind(0).unwrap();
+        let mut chip8 = Chip8::new(MachineConfig::default());
+
+        let body = server.set_breakpoints("source:a", vec![Some(0x200), Some(0x204), None], &mut chip8);
+        assert_eq!(body["breakpoints"][1]["verified"], true);
+        assert_eq!(body["breakpoints"][2]["verified"], false);
+        server.set_breakpoints("source:b", vec![Some(0x204)], &mut chip8);
+
+        server.set_breakpoints("source:a", vec![Some(0x206)], &mut chip8);
+        assert_eq!(chip8.get_breakpoints().iter().copied().collect::<Vec<u16>>(), vec![0x204, 0x206]);
+
+        server.set_breakpoints("source:b", Vec::new(), &mut chip8);
+        assert_eq!(chip8.get_breakpoints().iter().copied().collect::<Vec<u16>>(), vec![0x206]);
+
+        server.remove_breakpoints(&mut chip8);
+        assert!(chip8.get_breakpoints().is_empty());
+    }
+
+    #[test]
+    fn offsets_stay_in_the_address_space() {
+        assert_eq!(offset_address(0x200, -2), Some(0x1FE));
+        assert_eq!(offset_address(0, -1), None);
+        assert_eq!(offset_address(0xFFFF, 1), None);
+    }
+
+    #[test]
+    fn set_variable_checks_the_register_size() {
+        let mut chip8 = Chip8::new(MachineConfig::default());
+
+        let body = set_variable(&json!({ "name": "VA", "value": "0x2A (42)" }), &mut chip8).unwrap();
+        assert_eq!(body["value"], "0x2A (42)");
+        set_variable(&json!({ "name": "I", "value": "0x300" }), &mut chip8).unwrap();
+        assert_eq!((chip8.get_cpu().v[0xA], chip8.get_cpu().i), (0x2A, 0x300));
+
+        assert!(set_variable(&json!({ "name": "DT", "value": "256" }), &mut chip8).is_err());
+        assert!(set_variable(&json!({ "name": "SP", "value": "1" }), &mut chip8).is_err());
+        assert!(set_variable(&json!({ "name": "VG", "value": "1" }), &mut chip8).is_err());
+        assert!(set_variable(&json!({ "name": "V0", "value": "one" }), &mut chip8).is_err());
+    }
+
+    #[test]
+    fn memory_reads_stop_at_the_end_of_the_ram() {
+        let mut chip8 = Chip8::new(MachineConfig::default());
+        chip8.get_ram_mut().write_bytes(0x200, b"CHIP-8").unwrap();
+        let symbols = SymbolTable::new();
+
+        let body = read_memory(&json!({ "memoryReference": "0x1FF", "offset": 1, "count": 6 }), &chip8, &symbols).unwrap();
+        assert_eq!(body["data"], "Q0hJUC04");
+        assert_eq!(body["unreadableBytes"], 0);
+
+        let last = format!("{:#X}", chip8.get_ram().size() - 1);
+        let body = read_memory(&json!({ "memoryReference": last, "count": u64::MAX }), &chip8, &symbols).unwrap();
+        assert_eq!(decode_base64(body["data"].as_str().unwrap()).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn disassemble_marks_the_addresses_outside_of_the_ram() {
+        let mut chip8 = Chip8::new(MachineConfig::default());
+        // CLS; JP 0x200
+        chip8.get_ram_mut().write_bytes(0x200, &[0x00, 0xE0, 0x12, 0x00]).unwrap();
+        let mut symbols = SymbolTable::new();
+        symbols.set_label(0x202, "loop");
+
+        let arguments = json!({ "memoryReference": "0x200", "instructionOffset": -1, "instructionCount": 3 });
+        let body = disassemble(&arguments, &chip8, &symbols, None).unwrap();
+        let instructions = body["instructions"].as_array().unwrap();
+        assert_eq!(instructions[1]["instruction"], "CLS");
+        assert_eq!(instructions[2]["symbol"], "loop");
+
+        let arguments = json!({ "memoryReference": "0x0", "instructionOffset": -1, "instructionCount": 1 });
+        let body = disassemble(&arguments, &chip8, &symbols, None).unwrap();
+        assert_eq!(body["instructions"][0]["presentationHint"], "invalid");
+
+        let arguments = json!({ "memoryReference": "0x0", "offset": i64::MAX, "instructionOffset": i64::MAX, "instructionCount": i64::MAX });
+        let body = disassemble(&arguments, &chip8, &symbols, None).unwrap();
+        let instructions = body["instructions"].as_array().unwrap();
+        assert_eq!(instructions.len(), MAX_DISASSEMBLED_INSTRUCTIONS as usize);
+        assert_eq!(instructions[0]["presentationHint"], "invalid");
+    }
+}
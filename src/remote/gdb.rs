@@ -0,0 +1,488 @@
+use std::io::{self, Read, Write};
+use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream};
+
+use crate::chip8_core::chip8::Chip8;
+use crate::chip8_core::symbols::Register;
+use super::{RemoteCommand, StopReason};
+
+const PACKET_SIZE: usize = 4096;
+/// Byte sent by GDB to interrupt the target, outside of any packet.
+const INTERRUPT: u8 = 0x03;
+
+const SIGINT: u8 = 2;
+const SIGTRAP: u8 = 5;
+const SIGSEGV: u8 = 11;
+
+/// Names and sizes in bits of the registers, in the order of the `g` packet.
+const REGISTERS: [(&str, u32); 21] = [
+    ("v0", 8), ("v1", 8), ("v2", 8), ("v3", 8), ("v4", 8), ("v5", 8), ("v6", 8), ("v7", 8),
+    ("v8", 8), ("v9", 8), ("va", 8), ("vb", 8), ("vc", 8), ("vd", 8), ("ve", 8), ("vf", 8),
+    ("i", 16), ("pc", 16), ("sp", 8), ("dt", 8), ("st", 8),
+];
+
+/// Server of the GDB remote serial protocol on localhost. It exposes the registers,
+/// the RAM, breakpoints, single-step and continue. Only one client is served at a time.
+pub struct GdbServer {
+    listener: TcpListener,
+    client: Option<TcpStream>,
+    input: Vec<u8>,
+    /// The client sent a continue or a step and waits for a stop reply.
+    running: bool,
+    interrupted: bool,
+    no_ack: bool,
+}
+
+impl GdbServer {
+    /// Listens on `127.0.0.1:port`, port 0 picks a free port.
+    pub fn bind(port: u16) -> io::Result<Self> {
+        let listener = TcpListener::bind(SocketAddr::from((Ipv4Addr::LOCALHOST, port)))?;
+        listener.set_nonblocking(true)?;
+
+        Ok(Self {
+            listener,
+            client: None,
+            input: Vec::new(),
+            running: false,
+            interrupted: false,
+            no_ack: false,
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.client.is_some()
+    }
+
+    /// Accepts a client, answers its requests and returns what it asks the emulator to
+    /// do. A newly attached client pauses the emulator. Errors on the client connection
+    /// drop it.
+    pub fn poll(&mut self, chip8: &mut Chip8) -> Option<RemoteCommand> {
+        if self.client.is_none() {
+            let stream = match self.listener.accept() {
+                Ok((stream, _)) => stream,
+                Err(_) => return None,
+            };
+            if stream.set_nonblocking(true).is_err() {
+                return None;
+            }
+
+            self.client = Some(stream);
+            self.input.clear();
+            self.running = false;
+            self.interrupted = false;
+            self.no_ack = false;
+            return Some(RemoteCommand::Pause);
+        }
+
+        match self.receive(chip8) {
+            Ok(command) => command,
+            Err(_) => {
+                self.disconnect();
+                None
+            },
+        }
+    }
+
+    /// Sends the stop reply the client waits for since its last continue or step.
+    pub fn report_stop(&mut self, reason: StopReason) {
+        if !self.running {
+            return;
+        }
+        self.running = false;
+
+        let signal = match reason {
+            StopReason::Error => SIGSEGV,
+            StopReason::Trap if self.interrupted => SIGINT,
+            StopReason::Trap => SIGTRAP,
+        };
+        self.interrupted = false;
+
+        if self.send(&format!("S{:02x}", signal)).is_err() {
+            self.disconnect();
+        }
+    }
+
+    fn disconnect(&mut self) {
+        self.client = None;
+        self.running = false;
+    }
+
+    fn receive(&mut self, chip8: &mut Chip8) -> io::Result<Option<RemoteCommand>> {
+        let mut buffer = [0; PACKET_SIZE];
+        let client = match &mut self.client {
+            Some(client) => client,
+            None => return Ok(None),
+        };
+
+        loop {
+            match client.read(&mut buffer) {
+                Ok(0) => return Err(io::ErrorKind::ConnectionAborted.into()),
+                Ok(count) => self.input.extend_from_slice(&buffer[..count]),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => return Err(e),
+            }
+        }
+
+        let mut command = None;
+        while let Some(packet) = self.next_packet()? {
+            if let Some(packet_command) = self.handle_packet(&packet, chip8)? {
+                command = Some(packet_command);
+            }
+        }
+        Ok(command)
+    }
+
+    /// Takes the next complete `$packet#checksum` from the input, skipping acks. An
+    /// interrupt byte is returned as an empty packet.
+    fn next_packet(&mut self) -> io::Result<Option<String>> {
+        loop {
+            let start = self.input.iter().position(|byte| *byte == b'$' || *byte == INTERRUPT);
+            self.input.drain(..start.unwrap_or(self.input.len()));
+            match self.input.first() {
+                None => return Ok(None),
+                Some(&INTERRUPT) => {
+                    self.input.drain(..1);
+                    return Ok(Some(String::new()));
+                },
+                Some(_) => {},
+            }
+
+            let end = match self.input.iter().position(|byte| *byte == b'#') {
+                Some(end) if self.input.len() >= end + 3 => end,
+                _ => return Ok(None),
+            };
+
+            let packet: Vec<u8> = self.input.drain(..end + 3).collect();
+            let data = &packet[1..end];
+            let checksum = std::str::from_utf8(&packet[end + 1..])
+            .ok()
+            .and_then(|checksum| u8::from_str_radix(checksum, 16).ok());
+
+            let valid = checksum == Some(compute_checksum(data));
+            if !self.no_ack {
+                self.write_raw(if valid { b"+" } else { b"-" })?;
+            }
+            if valid {
+                return Ok(Some(String::from_utf8_lossy(data).to_string()));
+            }
+        }
+    }
+
+    fn handle_packet(&mut self, packet: &str, chip8: &mut Chip8) -> io::Result<Option<RemoteCommand>> {
+        if packet.is_empty() {
+            self.interrupted = true;
+            return Ok(Some(RemoteCommand::Pause));
+        }
+
+        let (kind, arguments) = packet.split_at(packet.chars().next().map_or(0, char::len_utf8));
+        let reply = match kind {
+            "?" => format!("S{:02x}", SIGTRAP),
+            "g" => read_registers(chip8),
+            "G" => ok_or_error(write_registers(chip8, arguments)),
+            "p" => parse_hex(arguments)
+                .and_then(|register| read_register(chip8, register as usize))
+                .unwrap_or_else(|| String::from("E01")),
+            "P" => ok_or_error(arguments.split_once('=')
+                .and_then(|(register, value)| write_register(chip8, parse_hex(register)? as usize, value))),
+            "m" => read_memory(chip8, arguments).unwrap_or_else(|| String::from("E01")),
+            "M" => ok_or_error(write_memory(chip8, arguments)),
+            "Z" | "z" => match parse_breakpoint(arguments) {
+                Some(address) => {
+                    if kind == "Z" {
+                        chip8.add_breakpoint(address);
+                    }
+                    else {
+                        chip8.remove_breakpoint(address);
+                    }
+                    String::from("OK")
+                },
+                // Watchpoints are not supported.
+                None => String::new(),
+            },
+            "c" | "s" => {
+                if let Some(address) = parse_hex(arguments) {
+                    chip8.get_cpu_mut().pc = address;
+                }
+                self.running = true;
+                return Ok(Some(if kind == "c" { RemoteCommand::Continue } else { RemoteCommand::Step }));
+            },
+            "D" => {
+                self.send("OK")?;
+                self.disconnect();
+                return Ok(Some(RemoteCommand::Continue));
+            },
+            "k" => {
+                self.disconnect();
+                return Ok(None);
+            },
+            "H" | "T" => String::from("OK"),
+            "q" | "Q" => self.query(packet),
+            _ => String::new(),
+        };
+
+        self.send(&reply)?;
+        Ok(None)
+    }
+
+    fn query(&mut self, packet: &str) -> String {
+        if packet.starts_with("qSupported") {
+            return format!("PacketSize={:x};qXfer:features:read+;QStartNoAckMode+", PACKET_SIZE);
+        }
+        if let Some(range) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+            return read_target_description(range).unwrap_or_else(|| String::from("E01"));
+        }
+
+        match packet {
+            "QStartNoAckMode" => {
+                self.no_ack = true;
+                String::from("OK")
+            },
+            "qAttached" => String::from("1"),
+            "qC" => String::from("QC1"),
+            "qfThreadInfo" => String::from("m1"),
+            "qsThreadInfo" => String::from("l"),
+            _ => String::new(),
+        }
+    }
+
+    fn send(&mut self, data: &str) -> io::Result<()> {
+        let packet = format!("${}#{:02x}", data, compute_checksum(data.as_bytes()));
+        self.write_raw(packet.as_bytes())
+    }
+
+    fn write_raw(&mut self, bytes: &[u8]) -> io::Result<()> {
+        match &mut self.client {
+            Some(client) => {
+                // The socket is non-blocking, small replies fit in its buffer.
+                client.set_nonblocking(false)?;
+                let result = client.write_all(bytes);
+                client.set_nonblocking(true)?;
+                result
+            },
+            None => Ok(()),
+        }
+    }
+}
+
+fn compute_checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |checksum, byte| checksum.wrapping_add(*byte))
+}
+
+fn ok_or_error(result: Option<()>) -> String {
+    match result {
+        Some(()) => String::from("OK"),
+        None => String::from("E01"),
+    }
+}
+
+fn parse_hex(text: &str) -> Option<u16> {
+    u16::from_str_radix(text, 16).ok()
+}
+
+fn decode_hex(text: &str) -> Option<Vec<u8>> {
+    text.as_bytes()
+    .chunks(2)
+    .map(|pair| match pair {
+        [_, _] => u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok(),
+        _ => None,
+    })
+    .collect()
+}
+
+/// Register of a `p` or `P` packet number.
+fn register_at(register: usize) -> Option<Register> {
+    REGISTERS.get(register).and_then(|(name, _)| Register::parse(name))
+}
+
+/// Registers are sent in little endian, on as many bytes as their size.
+fn encode_register(register: usize, value: u16) -> String {
+    value.to_le_bytes()[..REGISTERS[register].1 as usize / 8]
+    .iter()
+    .map(|byte| format!("{:02x}", byte))
+    .collect()
+}
+
+fn read_register(chip8: &Chip8, register: usize) -> Option<String> {
+    let value = register_at(register)?.read(chip8.get_cpu());
+    Some(encode_register(register, value))
+}
+
+fn read_registers(chip8: &Chip8) -> String {
+    (0..REGISTERS.len())
+    .filter_map(|register| read_register(chip8, register))
+    .collect()
+}
+
+fn write_register(chip8: &mut Chip8, register: usize, value: &str) -> Option<()> {
+    let bytes = decode_hex(value)?;
+    let value = match bytes.as_slice() {
+        [low] => *low as u16,
+        [low, high] => u16::from_le_bytes([*low, *high]),
+        _ => return None,
+    };
+
+    match register_at(register)? {
+        // The stack pointer only moves with CALL and RET, `G` packets still send it.
+        Register::Sp => Some(()),
+        register => register.write(chip8.get_cpu_mut(), value).ok(),
+    }
+}
+
+fn write_registers(chip8: &mut Chip8, values: &str) -> Option<()> {
+    let mut offset = 0;
+    for (register, (_, bitsize)) in REGISTERS.iter().enumerate() {
+        let length = *bitsize as usize / 4;
+        write_register(chip8, register, values.get(offset..offset + length)?)?;
+        offset += length;
+    }
+    Some(())
+}
+
+/// `m address,length`
+fn read_memory(chip8: &Chip8, arguments: &str) -> Option<String> {
+    let (address, length) = arguments.split_once(',')?;
+    let (address, length) = (parse_hex(address)?, parse_hex(length)?);
+    let ram = chip8.get_ram();
+
+    let mut bytes = String::new();
+    for offset in 0..length {
+        match ram.read8(address.checked_add(offset)?) {
+            Ok(byte) => bytes += &format!("{:02x}", byte),
+            // Reads crossing the end of the RAM return what could be read.
+            Err(_) if offset > 0 => break,
+            Err(_) => return None,
+        }
+    }
+    Some(bytes)
+}
+
+/// `M address,length:bytes`
+fn write_memory(chip8: &mut Chip8, arguments: &str) -> Option<()> {
+    let (range, data) = arguments.split_once(':')?;
+    let (address, length) = range.split_once(',')?;
+    let (address, length) = (parse_hex(address)?, parse_hex(length)?);
+    let bytes = decode_hex(data)?;
+    if bytes.len() != length as usize {
+        return None;
+    }
+
+    chip8.get_ram_mut().write_bytes(address, &bytes).ok()
+}
+
+/// `type,address,kind` of a software or hardware breakpoint.
+fn parse_breakpoint(arguments: &str) -> Option<u16> {
+    let mut fields = arguments.split(',');
+    match fields.next()? {
+        "0" | "1" => parse_hex(fields.next()?),
+        _ => None,
+    }
+}
+
+fn target_description() -> String {
+    let mut registers = String::new();
+    for (name, bitsize) in REGISTERS {
+        let kind = match name {
+            "pc" => "code_ptr",
+            "i" => "data_ptr",
+            _ => "int",
+        };
+        registers += &format!("    <reg name=\"{}\" bitsize=\"{}\" type=\"{}\"/>\n", name, bitsize, kind);
+    }
+
+    format!(
+        "<?xml version=\"1.0\"?>\n<!DOCTYPE target SYSTEM \"gdb-target.dtd\">\n<target version=\"1.0\">\n  <feature name=\"org.chip8.core\">\n{}  </feature>\n</target>\n",
+        registers
+    )
+}
+
+/// `offset,length` of the target description, prefixed by `l` for the last part.
+fn read_target_description(range: &str) -> Option<String> {
+    let (offset, length) = range.split_once(',')?;
+    let (offset, length) = (usize::from_str_radix(offset, 16).ok()?, usize::from_str_radix(length, 16).ok()?);
+
+    let description = target_description();
+    let start = offset.min(description.len());
+    let end = offset.saturating_add(length).min(description.len());
+    let prefix = if end == description.len() { "l" } else { "m" };
+    Some(format!("{}{}", prefix, &description[start..end]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8_core::machine::MachineConfig;
+
+    fn packet(data: &str) -> String {
+        format!("${}#{:02x}", data, compute_checksum(data.as_bytes()))
+    }
+
+    #[test]
+    fn packets_are_checked_and_split() {
+        let mut server = GdbServer::bind(0).unwrap();
+        server.input.extend_from_slice(b"+");
+        server.input.extend_from_slice(packet("g").as_bytes());
+        server.input.push(INTERRUPT);
+        server.input.extend_from_slice(b"$m200,4#00");
+        server.input.extend_from_slice(packet("m200,4").as_bytes());
+        server.input.extend_from_slice(b"$c#6");
+
+        assert_eq!(compute_checksum(b"g"), 0x67);
+        assert_eq!(server.next_packet().unwrap().as_deref(), Some("g"));
+        assert_eq!(server.next_packet().unwrap().as_deref(), Some(""));
+        // The packet with a wrong checksum is dropped.
+        assert_eq!(server.next_packet().unwrap().as_deref(), Some("m200,4"));
+        // The last packet waits for the second digit of its checksum.
+        assert_eq!(server.next_packet().unwrap(), None);
+        assert_eq!(server.input, b"$c#6");
+    }
+
+    #[test]
+    fn write_registers_reads_every_register_in_order() {
+        let mut chip8 = Chip8::new(MachineConfig::default());
+        let registers = read_registers(&chip8);
+        assert_eq!(registers.len(), 46);
+
+        let mut values: String = (0..16).map(|x| format!("{:02x}", x + 1)).collect();
+        values += "3412"; // I
+        values += "0003"; // PC
+        values += "07"; // SP, read only
+        values += "2021"; // DT, ST
+        write_registers(&mut chip8, &values).unwrap();
+
+        let cpu = chip8.get_cpu();
+        assert_eq!(cpu.v[0xF], 0x10);
+        assert_eq!((cpu.i, cpu.pc, cpu.stack.sp), (0x1234, 0x300, 0));
+        assert_eq!((cpu.delay_timer, cpu.sound_timer), (0x20, 0x21));
+        assert_eq!(write_registers(&mut chip8, &values[..44]), None);
+    }
+
+    #[test]
+    fn read_memory_stops_at_the_end_of_the_ram() {
+        let mut chip8 = Chip8::new(MachineConfig::default());
+        chip8.get_ram_mut().write_bytes(0x200, &[0x12, 0x34, 0xAB]).unwrap();
+        let last = chip8.get_ram().size() as u16 - 1;
+
+        assert_eq!(read_memory(&chip8, "200,3").as_deref(), Some("1234ab"));
+        assert_eq!(read_memory(&chip8, &format!("{:x},4", last)).as_deref(), Some("00"));
+        assert_eq!(read_memory(&chip8, &format!("{:x},1", last + 1)), None);
+        assert_eq!(read_memory(&chip8, "200"), None);
+    }
+
+    #[test]
+    fn only_code_breakpoints_are_parsed() {
+        assert_eq!(parse_breakpoint("0,2a0,2"), Some(0x2A0));
+        assert_eq!(parse_breakpoint("1,2a0,2"), Some(0x2A0));
+        assert_eq!(parse_breakpoint("2,2a0,2"), None);
+        assert_eq!(parse_breakpoint("0"), None);
+    }
+
+    #[test]
+    fn target_description_ranges_are_clamped() {
+        let description = read_target_description("0,ffffffffffffffff").unwrap();
+        assert!(description.starts_with("l<?xml"));
+        assert_eq!(read_target_description("ffffffff,10").as_deref(), Some("l"));
+    }
+}
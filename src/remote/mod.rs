@@ -0,0 +1,34 @@
+use std::path::PathBuf;
+
+pub mod gdb;
+pub mod dap;
+
+/// Default TCP port of the GDB remote serial protocol server.
+pub const DEFAULT_GDB_PORT: u16 = 1234;
+/// Default TCP port of the Debug Adapter Protocol server.
+pub const DEFAULT_DAP_PORT: u16 = 4711;
+
+/// What a remote debugger asks the emulator to do. The servers never block, `MainApp`
+/// polls them once per update and applies the commands they return.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RemoteCommand {
+    Continue,
+    /// Executes one instruction then pauses.
+    Step,
+    /// Executes one instruction, or a whole subroutine for `CALL`, then pauses.
+    StepOver,
+    /// Runs until the current subroutine returns.
+    StepOut,
+    Pause,
+    /// The server loaded a ROM in the machine.
+    RomLoaded(PathBuf),
+}
+
+/// Why the emulator stopped running after a `Continue` or a `Step`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StopReason {
+    /// A step ended, a breakpoint was reached, the user or the debugger paused.
+    Trap,
+    /// The program halted on an error.
+    Error,
+}